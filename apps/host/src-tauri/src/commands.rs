@@ -1,7 +1,11 @@
-use crate::room_state::{RoomStateManager, PlaylistStore, Song, PlaylistCollection, PlayerStatus, CollectionVisibility};
+use crate::room_state::{RoomStateManager, RoomRegistry, HostedRoom, HandoffPackage, ConnectedClient, PlaylistStore, SessionStore, RestorableSession, PlayedSong, HistoryFormat, Song, PlaylistCollection, PlayerStatus, CollectionVisibility, QueueMode, QueueLimits, QueueRejection, AutoDjConfig, SkipPolicy, Role, DuetActor};
+use crate::network::{NetworkAddress, NetworkSettings, NetworkSettingsStore};
+use crate::tls::CertificateStore;
+use crate::signaling::RoomManager;
 use serde::{Deserialize, Serialize};
 use std::sync::atomic::{AtomicBool, Ordering};
-use tauri::{AppHandle, Emitter};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
 use uuid::Uuid;
 
 /// Guard so start_host_server is idempotent
@@ -10,11 +14,12 @@ static SERVER_STARTED: AtomicBool = AtomicBool::new(false);
 /// Client command types (from P2P protocol)
 #[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "type")]
-#[allow(non_camel_case_types)]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 pub enum ClientCommand {
     PLAY,
     PAUSE,
     SKIP,
+    VOTE_SKIP,
     SEEK { time: f64 },
     SET_VOLUME { volume: u8 },
     TOGGLE_MUTE,
@@ -50,8 +55,42 @@ pub enum ClientCommand {
         #[serde(rename = "newIndex")]
         new_index: usize,
     },
+    SET_QUEUE_MODE { mode: QueueMode },
+    // One vote per guest per song; ordering by votes is QueueMode::Votes
+    UPVOTE {
+        #[serde(rename = "songId")]
+        song_id: String,
+    },
+    UNVOTE {
+        #[serde(rename = "songId")]
+        song_id: String,
+    },
+    // Host-only queue history
+    UNDO,
+    REDO,
+    // Duet / group song commands
+    INVITE_TO_DUET {
+        #[serde(rename = "songId")]
+        song_id: String,
+        singer: String,
+        /// The guest to invite, when they are in the room
+        #[serde(rename = "clientId", default)]
+        client_id: Option<String>,
+    },
+    JOIN_DUET {
+        #[serde(rename = "songId")]
+        song_id: String,
+        singer: String,
+    },
+    LEAVE_DUET {
+        #[serde(rename = "songId")]
+        song_id: String,
+        singer: String,
+    },
     SET_DISPLAY_NAME { name: String },
     PING,
+    // Queries, answered to the sender only (see `CommandReply`)
+    GET_HISTORY,
     // Collection-based playlist commands
     PLAYLIST_ADD {
         #[serde(rename = "youtubeUrl")]
@@ -98,37 +137,302 @@ pub enum ClientCommand {
     },
 }
 
+const EVERYONE: &[Role] = &[Role::Host, Role::CoHost, Role::Singer, Role::Spectator];
+const PERFORMERS: &[Role] = &[Role::Host, Role::CoHost, Role::Singer];
+const STAFF: &[Role] = &[Role::Host, Role::CoHost];
+const HOST_ONLY: &[Role] = &[Role::Host];
+
+impl ClientCommand {
+    /// Permission table: the roles allowed to run each command. Handlers may
+    /// narrow this further (singers can only remove their own songs, and SKIP
+    /// is staff-only while the host restricts skipping).
+    pub fn allowed_roles(&self) -> &'static [Role] {
+        match self {
+            ClientCommand::VOTE_SKIP
+            | ClientCommand::UPVOTE { .. }
+            | ClientCommand::UNVOTE { .. }
+            | ClientCommand::SET_DISPLAY_NAME { .. }
+            | ClientCommand::PING
+            | ClientCommand::GET_HISTORY => EVERYONE,
+            ClientCommand::PLAY
+            | ClientCommand::PAUSE
+            | ClientCommand::SKIP
+            | ClientCommand::ADD_SONG { .. }
+            | ClientCommand::REMOVE_SONG { .. }
+            | ClientCommand::INVITE_TO_DUET { .. }
+            | ClientCommand::JOIN_DUET { .. }
+            | ClientCommand::LEAVE_DUET { .. }
+            | ClientCommand::PLAYLIST_TO_QUEUE { .. } => PERFORMERS,
+            ClientCommand::SEEK { .. }
+            | ClientCommand::SET_VOLUME { .. }
+            | ClientCommand::TOGGLE_MUTE
+            | ClientCommand::MOVE_SONG_UP { .. }
+            | ClientCommand::MOVE_SONG_DOWN { .. }
+            | ClientCommand::MOVE_SONG_TO_TOP { .. }
+            | ClientCommand::MOVE_SONG_TO_BOTTOM { .. }
+            | ClientCommand::REORDER_QUEUE { .. }
+            | ClientCommand::SET_QUEUE_MODE { .. }
+            | ClientCommand::PLAYLIST_ADD { .. }
+            | ClientCommand::PLAYLIST_REMOVE { .. }
+            | ClientCommand::CREATE_COLLECTION { .. }
+            | ClientCommand::RENAME_COLLECTION { .. }
+            | ClientCommand::SET_COLLECTION_VISIBILITY { .. }
+            | ClientCommand::IMPORT_COLLECTION { .. } => STAFF,
+            ClientCommand::UNDO
+            | ClientCommand::REDO
+            | ClientCommand::DELETE_COLLECTION { .. } => HOST_ONLY,
+        }
+    }
+
+    /// Commands that only read, which overflow spectators may still send
+    pub fn is_query(&self) -> bool {
+        matches!(self, ClientCommand::PING | ClientCommand::GET_HISTORY)
+    }
+}
+
 fn default_public_visibility() -> CollectionVisibility {
     CollectionVisibility::Public
 }
 
-/// Create a new room
+/// Answer to a query command, sent back to the guest who asked.
+///
+/// Serialised in the same `{ type, ... }` shape as the host's broadcasts.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+pub enum CommandReply {
+    HISTORY { entries: Vec<PlayedSong> },
+}
+
+/// Error returned by `process_command`.
+///
+/// Serialised as `{ code, message, details? }` so the host can forward it to
+/// the guest as-is and the remote UI can branch on `code` instead of parsing
+/// prose. Failures without a specific code use `COMMAND_FAILED`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandError {
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+impl CommandError {
+    fn failed(message: impl Into<String>) -> Self {
+        Self {
+            code: "COMMAND_FAILED",
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    fn forbidden(message: impl Into<String>) -> Self {
+        Self {
+            code: "FORBIDDEN",
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// A guest let in past capacity tried to do more than watch
+    fn read_only() -> Self {
+        Self {
+            code: "READ_ONLY",
+            message: "The room is full, so you can only watch for now".to_string(),
+            details: None,
+        }
+    }
+
+    /// A command the sender's role may not run, per `ClientCommand::allowed_roles`
+    fn not_permitted(role: Role, allowed: &[Role]) -> Self {
+        Self {
+            code: "FORBIDDEN",
+            message: format!("A {} can't do that", role.as_str()),
+            details: Some(serde_json::json!({ "role": role, "allowed": allowed })),
+        }
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        Self::failed(message)
+    }
+}
+
+impl From<&str> for CommandError {
+    fn from(message: &str) -> Self {
+        Self::failed(message)
+    }
+}
+
+impl From<QueueRejection> for CommandError {
+    fn from(rejection: QueueRejection) -> Self {
+        Self {
+            code: rejection.code(),
+            message: rejection.message(),
+            details: serde_json::to_value(&rejection).ok(),
+        }
+    }
+}
+
+/// Open a room hosted by the calling window
+///
+/// The window picks `room_id`, the same ID it registers with the signaling
+/// server, so guests and commands route to the room by one ID. Opening an ID
+/// that is already open (the window reloaded) keeps that room's state.
 #[tauri::command]
 pub fn create_room(
-    state: tauri::State<RoomStateManager>,
+    room_id: String,
+    rooms: tauri::State<RoomRegistry>,
     playlists: tauri::State<PlaylistStore>,
+    session: tauri::State<SessionStore>,
+    network: tauri::State<NetworkSettingsStore>,
+    window: tauri::WebviewWindow,
 ) -> Result<CreateRoomResponse, String> {
-    // Generate unique room ID and join token
-    let room_id = generate_room_id();
     let join_token = generate_join_token();
-    
+    let state = rooms.open(&room_id, window.label(), playlists.get_all());
+
     // Sync latest playlists from store into the new room state
     // This ensures that if the user created playlists in Guest mode (via bridge),
     // they are immediately available in the new Host session.
     state.write().sync_playlists(playlists.get_all());
-    
-    log::info!("Created room: {} with token", room_id);
-    
+
+    log::info!("Opened room {} in window {}", room_id, window.label());
+
+    // Rooms open once the web server is up, so guests can find it on the LAN
+    let port = crate::web_server::get_server_port();
+    if port != 0 {
+        if let Err(e) = crate::discovery::advertise_room(&room_id, port, &network.get()) {
+            log::warn!("Room {} is not discoverable: {}", room_id, e);
+        }
+    }
+
+    // Only the main room is snapshotted, so only it is offered the last session
+    let restorable_session = if rooms.is_main(&room_id) { session.pending() } else { None };
     Ok(CreateRoomResponse {
         room_id,
         join_token,
+        restorable_session,
     })
 }
 
+/// Close a room when its window leaves Host Mode
+#[tauri::command]
+pub fn close_room(room_id: String, rooms: tauri::State<RoomRegistry>) {
+    if rooms.close(&room_id) {
+        log::info!("Closed room {}", room_id);
+        crate::discovery::withdraw_room(&room_id);
+    }
+}
+
+/// Open another host window with a room of its own, e.g. a side room next to
+/// the main stage. Both are served by the same web server.
+#[tauri::command]
+pub fn open_room_window(app: AppHandle) -> Result<(), String> {
+    #[cfg(desktop)]
+    {
+        let label = format!("room-{}", &Uuid::new_v4().simple().to_string()[..8]);
+        tauri::WebviewWindowBuilder::new(&app, label, tauri::WebviewUrl::App("index.html?mode=host".into()))
+            .title("KaraokeNatin - Side room")
+            .inner_size(1280.0, 800.0)
+            .build()
+            .map_err(|e| format!("Could not open a room window: {}", e))?;
+        Ok(())
+    }
+
+    #[cfg(mobile)]
+    {
+        let _ = app;
+        Err("Extra rooms need the desktop app".to_string())
+    }
+}
+
+/// Restore the session left over from the last run (see `CreateRoomResponse`)
+#[tauri::command]
+pub fn restore_session(
+    room_id: String,
+    rooms: tauri::State<RoomRegistry>,
+    session: tauri::State<SessionStore>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let room = hosted_room(&rooms, &room_id)?;
+    let previous = session.take_pending()
+        .ok_or_else(|| "No previous session to restore".to_string())?;
+    log::info!("Restoring previous session ({} queued)", previous.queue.len());
+    room.state.write().restore_session(previous);
+    emit_state(&app, &room)
+}
+
+/// Carry on with a room another host handed off (see signaling.rs
+/// CLAIM_HANDOFF): its queue, history and settings join this room's
+#[tauri::command]
+pub fn import_handoff(
+    room_id: String,
+    package: HandoffPackage,
+    rooms: tauri::State<RoomRegistry>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let room = hosted_room(&rooms, &room_id)?;
+    log::info!("Taking over a handed-off room ({} queued, {} guests)", package.session.queue.len(), package.roster.len());
+    room.state.write().import_handoff(package);
+    emit_state(&app, &room)
+}
+
+/// Drop the session left over from the last run without restoring it
+#[tauri::command]
+pub fn discard_session(session: tauri::State<SessionStore>) {
+    session.discard_pending();
+}
+
 /// Get the QR code URL for clients to connect
 #[tauri::command]
-pub fn get_qr_url() -> Result<String, String> {
-    crate::network::generate_qr_url()
+pub fn get_qr_url(network: tauri::State<NetworkSettingsStore>) -> Result<String, String> {
+    crate::network::generate_qr_url(network.get().preferred_ip)
+}
+
+/// Every address guests might reach this host on, likeliest first
+#[tauri::command]
+pub fn list_network_addresses() -> Result<Vec<NetworkAddress>, String> {
+    crate::network::list_addresses()
+}
+
+#[tauri::command]
+pub fn get_network_settings(network: tauri::State<NetworkSettingsStore>) -> NetworkSettings {
+    network.get()
+}
+
+/// Pick the address for the QR code and the port. Listening on the address
+/// only, port changes and HTTPS take effect the next time the server starts.
+#[tauri::command]
+pub fn set_network_settings(settings: NetworkSettings, network: tauri::State<NetworkSettingsStore>) -> Result<(), String> {
+    network.set(settings)
+}
+
+/// This machine's LAN addresses, for the certificate to name
+fn lan_ips() -> Vec<String> {
+    crate::network::list_addresses()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|a| a.ip.to_string())
+        .collect()
+}
+
+/// SHA-256 fingerprint of the HTTPS certificate, made now if there is none,
+/// for guests to compare when their browser asks whether to trust it
+#[tauri::command]
+pub fn get_certificate_fingerprint(tls: tauri::State<CertificateStore>) -> Result<String, String> {
+    tls.fingerprint(&lan_ips())
+}
+
+/// How long `discover_hosts` listens for answers
+const DISCOVERY_WINDOW: Duration = Duration::from_secs(3);
+
+/// List the rooms advertised on the LAN, for Guest mode
+#[tauri::command]
+pub async fn discover_hosts() -> Result<Vec<crate::discovery::DiscoveredHost>, String> {
+    tauri::async_runtime::spawn_blocking(|| crate::discovery::discover(DISCOVERY_WINDOW))
+        .await
+        .map_err(|e| format!("Discovery failed: {}", e))?
 }
 
 /// Get the web server port
@@ -139,8 +443,22 @@ pub fn get_server_port() -> u16 {
 
 /// Get the current room state
 #[tauri::command]
-pub fn get_room_state(state: tauri::State<RoomStateManager>) -> Result<crate::room_state::RoomState, String> {
-    Ok(state.clone_state())
+pub fn get_room_state(room_id: String, rooms: tauri::State<RoomRegistry>) -> Result<crate::room_state::RoomState, String> {
+    Ok(hosted_room(&rooms, &room_id)?.state.clone_state())
+}
+
+/// Check the device token a guest's DataConnection carried in its metadata.
+/// The host window closes the connection if this fails; until it passes,
+/// commands from that peer id are refused.
+#[tauri::command]
+pub fn verify_guest(room_id: String, peer_id: String, device_token: String, rooms: tauri::State<RoomRegistry>) -> Result<bool, String> {
+    Ok(hosted_room(&rooms, &room_id)?.state.write().verify_client(&peer_id, &device_token))
+}
+
+/// The state as guests see it, for a guest who just connected
+#[tauri::command]
+pub fn get_public_state(room_id: String, rooms: tauri::State<RoomRegistry>) -> Result<crate::room_state::PublicBroadcast, String> {
+    Ok(hosted_room(&rooms, &room_id)?.state.clone_public_broadcast())
 }
 
 /// Search YouTube for videos
@@ -151,14 +469,41 @@ pub async fn search_youtube(query: String, limit: Option<u32>) -> Result<Vec<cra
 }
 
 /// Process a client command
+///
+/// `sender_id` is the PeerJS id of the guest the command came from; it is
+/// `None` when the host's own UI issues the command. Guest additions are
+/// subject to the room's `QueueLimits`, the host's are not.
+///
+/// Query commands return a `CommandReply` for the sender and leave the state
+/// untouched; everything else returns `None` and broadcasts the new state.
 #[tauri::command]
 pub async fn process_command(
+    room_id: String,
     command: ClientCommand,
-    state: tauri::State<'_, RoomStateManager>,
+    sender_id: Option<String>,
+    rooms: tauri::State<'_, RoomRegistry>,
     playlists: tauri::State<'_, PlaylistStore>,
     app: AppHandle,
-) -> Result<(), String> {
-    log::info!("Processing command: {:?}", command);
+) -> Result<Option<CommandReply>, CommandError> {
+    log::info!("Processing command: {:?} (from {:?} in {})", command, sender_id, room_id);
+    let hosted = hosted_room(&rooms, &room_id)?;
+    let state = &hosted.state;
+
+    if sender_id.as_deref().is_some_and(|id| !state.read().is_verified(id)) {
+        return Err(CommandError::forbidden("This connection has not shown it belongs to a guest in the room"));
+    }
+    let (role, overflow) = {
+        let room = state.read();
+        let overflow = sender_id.as_deref().is_some_and(|id| room.is_overflow(id));
+        (room.role_of(sender_id.as_deref()), overflow)
+    };
+    if overflow && !command.is_query() {
+        return Err(CommandError::read_only());
+    }
+    let allowed = command.allowed_roles();
+    if !allowed.contains(&role) {
+        return Err(CommandError::not_permitted(role, allowed));
+    }
     
     match command {
         ClientCommand::PLAY => {
@@ -168,7 +513,14 @@ pub async fn process_command(
             state.write().pause();
         }
         ClientCommand::SKIP => {
-            state.write().skip_song();
+            let mut room = state.write();
+            if room.skip_policy.restrict_skip && !STAFF.contains(&role) {
+                return Err(CommandError::forbidden("The host has turned off skipping; vote to skip instead"));
+            }
+            room.skip_song();
+        }
+        ClientCommand::VOTE_SKIP => {
+            state.write().vote_skip(voter_id(&sender_id))?;
         }
         ClientCommand::SEEK { time } => {
             state.write().seek(time);
@@ -182,6 +534,14 @@ pub async fn process_command(
         ClientCommand::ADD_SONG { youtube_url, added_by } => {
             let youtube_id = extract_youtube_id(&youtube_url)
                 .ok_or_else(|| "Invalid YouTube URL".to_string())?;
+            let added_by = added_by.unwrap_or_else(|| "Guest".to_string());
+
+            // Refuse early, before the metadata fetch, when the guest is over a
+            // limit; add_queued_song checks again once the song is built.
+            if let Some(guest) = &sender_id {
+                let now = chrono::Utc::now().timestamp_millis();
+                state.write().check_add_limits(guest, now)?;
+            }
             
             match crate::metadata::fetch_metadata(&youtube_id).await {
                 Ok(metadata) => {
@@ -192,20 +552,31 @@ pub async fn process_command(
                         artist: metadata.artist,
                         duration: metadata.duration,
                         thumbnail_url: metadata.thumbnail_url,
-                        added_by: added_by.unwrap_or_else(|| "Guest".to_string()),
+                        added_by,
                         added_at: chrono::Utc::now().timestamp_millis(),
+                        singers: Vec::new(),
+                        voters: Vec::new(),
+                        votes: 0,
+                        auto_filled: false,
+                        owner_id: None,
                     };
-                    state.write().add_song(song);
+                    add_queued_song(state, song, sender_id.as_deref())?;
                 }
                 Err(e) => {
                     log::error!("Failed to fetch metadata: {}", e);
-                    return Err(format!("Failed to fetch song metadata: {}", e));
+                    return Err(format!("Failed to fetch song metadata: {}", e).into());
                 }
             }
         }
         ClientCommand::REMOVE_SONG { song_id } => {
-            if !state.write().remove_song(&song_id) {
-                return Err("Song not found".to_string());
+            let mut room = state.write();
+            if let Some(guest) = sender_id.as_deref().filter(|_| !STAFF.contains(&role)) {
+                if !room.is_song_owner(&song_id, guest) {
+                    return Err(CommandError::forbidden("You can only remove songs you queued"));
+                }
+            }
+            if !room.remove_song(&song_id) {
+                return Err("Song not found".into());
             }
         }
         ClientCommand::MOVE_SONG_UP { song_id } => {
@@ -222,13 +593,51 @@ pub async fn process_command(
         }
         ClientCommand::REORDER_QUEUE { song_id, new_index } => {
             if !state.write().reorder_queue(&song_id, new_index) {
-                return Err("Failed to reorder queue".to_string());
+                return Err("Failed to reorder queue".into());
+            }
+        }
+        ClientCommand::SET_QUEUE_MODE { mode } => {
+            state.write().set_queue_mode(mode);
+        }
+        ClientCommand::UPVOTE { song_id } => {
+            state.write().upvote(&song_id, voter_id(&sender_id))?;
+        }
+        ClientCommand::UNVOTE { song_id } => {
+            state.write().unvote(&song_id, voter_id(&sender_id))?;
+        }
+        ClientCommand::UNDO => {
+            if !state.write().undo() {
+                return Err("Nothing to undo".into());
+            }
+        }
+        ClientCommand::REDO => {
+            if !state.write().redo() {
+                return Err("Nothing to redo".into());
             }
         }
+        ClientCommand::INVITE_TO_DUET { song_id, singer, client_id } => {
+            let actor = duet_actor(sender_id.as_deref(), role);
+            state.write().invite_to_duet(&song_id, &singer, client_id.as_deref(), actor)?;
+        }
+        ClientCommand::JOIN_DUET { song_id, singer } => {
+            state.write().join_duet(&song_id, &singer, duet_actor(sender_id.as_deref(), role))?;
+        }
+        ClientCommand::LEAVE_DUET { song_id, singer } => {
+            state.write().leave_duet(&song_id, &singer, duet_actor(sender_id.as_deref(), role))?;
+        }
         ClientCommand::SET_DISPLAY_NAME { name } => {
             log::info!("Client set display name: {}", name);
+            if let Some(guest) = sender_id.as_deref() {
+                state.write().rename_client(guest, &name)?;
+                // Signaling restores the name when the guest reconnects
+                app.state::<RoomManager>().rename_identity(&room_id, guest, name.trim());
+            }
         }
         ClientCommand::PING => {}
+        ClientCommand::GET_HISTORY => {
+            let entries = state.clone_play_history();
+            return Ok(Some(CommandReply::HISTORY { entries }));
+        }
         // ---- playlist commands delegate to PlaylistStore ----
         ClientCommand::PLAYLIST_ADD { youtube_url, collection_id, added_by } => {
             let youtube_id = extract_youtube_id(&youtube_url)
@@ -245,6 +654,11 @@ pub async fn process_command(
                         thumbnail_url: metadata.thumbnail_url,
                         added_by: added_by.unwrap_or_else(|| "Guest".to_string()),
                         added_at: chrono::Utc::now().timestamp_millis(),
+                        singers: Vec::new(),
+                        voters: Vec::new(),
+                        votes: 0,
+                        auto_filled: false,
+                        owner_id: None,
                     };
                     let target_id = if collection_id.is_empty() {
                         playlists.get_or_create_default_collection()
@@ -252,84 +666,275 @@ pub async fn process_command(
                         collection_id
                     };
                     if !playlists.add_to_collection(&target_id, song) {
-                        return Err("Collection not found".to_string());
+                        return Err("Collection not found".into());
                     }
                     // Sync snapshot into room state
-                    state.write().sync_playlists(playlists.get_all());
+                    rooms.sync_playlists(&playlists.get_all());
                 }
                 Err(e) => {
                     log::error!("Failed to fetch metadata: {}", e);
-                    return Err(format!("Failed to fetch song metadata: {}", e));
+                    return Err(format!("Failed to fetch song metadata: {}", e).into());
                 }
             }
         }
         ClientCommand::PLAYLIST_REMOVE { song_id, collection_id } => {
             if !playlists.remove_from_collection(&collection_id, &song_id) {
-                return Err("Song not found in collection".to_string());
+                return Err("Song not found in collection".into());
             }
-            state.write().sync_playlists(playlists.get_all());
+            rooms.sync_playlists(&playlists.get_all());
         }
         ClientCommand::PLAYLIST_TO_QUEUE { song_id, collection_id } => {
             if let Some(song) = playlists.clone_song_for_queue(&collection_id, &song_id) {
-                state.write().add_song(song);
+                add_queued_song(state, song, sender_id.as_deref())?;
             } else {
-                return Err("Song not found in collection".to_string());
+                return Err("Song not found in collection".into());
             }
         }
         ClientCommand::CREATE_COLLECTION { name, visibility } => {
             playlists.create_collection(name, visibility);
-            state.write().sync_playlists(playlists.get_all());
+            rooms.sync_playlists(&playlists.get_all());
         }
         ClientCommand::DELETE_COLLECTION { collection_id } => {
             if !playlists.delete_collection(&collection_id) {
-                return Err("Collection not found".to_string());
+                return Err("Collection not found".into());
             }
-            state.write().sync_playlists(playlists.get_all());
+            rooms.sync_playlists(&playlists.get_all());
         }
         ClientCommand::RENAME_COLLECTION { collection_id, name } => {
             if !playlists.rename_collection(&collection_id, name) {
-                return Err("Collection not found".to_string());
+                return Err("Collection not found".into());
             }
-            state.write().sync_playlists(playlists.get_all());
+            rooms.sync_playlists(&playlists.get_all());
         }
         ClientCommand::SET_COLLECTION_VISIBILITY { collection_id, visibility } => {
             if !playlists.set_collection_visibility(&collection_id, visibility) {
-                return Err("Collection not found".to_string());
+                return Err("Collection not found".into());
             }
-            state.write().sync_playlists(playlists.get_all());
+            rooms.sync_playlists(&playlists.get_all());
         }
         ClientCommand::IMPORT_COLLECTION { data } => {
             playlists.import_collection(&data)
                 .map_err(|e| format!("Import failed: {}", e))?;
-            state.write().sync_playlists(playlists.get_all());
+            rooms.sync_playlists(&playlists.get_all());
         }
     }
     
-    emit_state(&app, &state)?;
+    emit_state(&app, &hosted)?;
+
+    Ok(None)
+}
+
+/// Who a vote counts for: the guest's peer id, or the host
+fn voter_id(sender_id: &Option<String>) -> &str {
+    sender_id.as_deref().unwrap_or("host")
+}
+
+/// Who a duet change acts as: staff for anyone, other guests as themselves
+fn duet_actor(sender_id: Option<&str>, role: Role) -> DuetActor<'_> {
+    match sender_id {
+        Some(id) if !STAFF.contains(&role) => DuetActor::Guest(id),
+        staff => DuetActor::Staff(staff),
+    }
+}
+
+/// Queue a song, applying the room's `QueueLimits` when a guest sent it
+fn add_queued_song(
+    state: &RoomStateManager,
+    song: Song,
+    sender_id: Option<&str>,
+) -> Result<(), QueueRejection> {
+    match sender_id {
+        Some(guest) => {
+            let now = chrono::Utc::now().timestamp_millis();
+            state.write().add_guest_song(song, guest, now)
+        }
+        None => {
+            state.write().add_song(song);
+            Ok(())
+        }
+    }
+}
+
+/// The song on stage played to the end (called from the host player)
+#[tauri::command]
+pub fn finish_song(room_id: String, rooms: tauri::State<RoomRegistry>, app: AppHandle) -> Result<(), String> {
+    let room = hosted_room(&rooms, &room_id)?;
+    room.state.write().finish_song();
+    emit_state(&app, &room)
+}
+
+/// Get the session's play history, oldest first
+#[tauri::command]
+pub fn get_play_history(room_id: String, rooms: tauri::State<RoomRegistry>) -> Result<Vec<PlayedSong>, String> {
+    Ok(hosted_room(&rooms, &room_id)?.state.clone_play_history())
+}
+
+/// Save the session's play history through a native "Save As" dialog.
+///
+/// Writes through `FsExt` for the same Android reason as
+/// `save_collection_to_file`.
+#[tauri::command]
+pub async fn save_history_to_file(
+    room_id: String,
+    format: HistoryFormat,
+    rooms: tauri::State<'_, RoomRegistry>,
+    app: AppHandle,
+) -> Result<(), String> {
+    use std::io::Write;
+    use tauri_plugin_dialog::DialogExt;
+    use tauri_plugin_fs::{FsExt, OpenOptions};
+
+    let history = hosted_room(&rooms, &room_id)?.state.clone_play_history();
+    let content = crate::room_state::export_history(&history, format)?;
+
+    let file_name = format!(
+        "karaoke-session-{}.{}",
+        chrono::Local::now().format("%Y-%m-%d"),
+        format.extension()
+    );
+    let path = app.dialog()
+        .file()
+        .set_file_name(&file_name)
+        .add_filter("Session history", &[format.extension()])
+        .blocking_save_file();
+
+    let Some(file_path) = path else {
+        return Err("Save cancelled".into());
+    };
+
+    let target = describe_file_path(&file_path);
+    let mut opts = OpenOptions::new();
+    opts.write(true).create(true).truncate(true);
+
+    let mut file = app
+        .fs()
+        .open(file_path, opts)
+        .map_err(|e| describe_io_error("open for writing", &target, &e))?;
+
+    file.write_all(content.as_bytes())
+        .map_err(|e| describe_io_error("write", &target, &e))?;
+
+    log::info!("Saved play history ({} songs) to: {}", history.len(), target);
 
     Ok(())
 }
 
+/// Replace the room's per-guest queue limits (host only)
+#[tauri::command]
+pub fn set_queue_limits(
+    room_id: String,
+    limits: QueueLimits,
+    rooms: tauri::State<RoomRegistry>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let room = hosted_room(&rooms, &room_id)?;
+    room.state.write().set_queue_limits(limits);
+    emit_state(&app, &room)
+}
+
+/// Assign a guest's role (host only)
+#[tauri::command]
+pub fn set_guest_role(
+    room_id: String,
+    guest_id: String,
+    role: Role,
+    rooms: tauri::State<RoomRegistry>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let room = hosted_room(&rooms, &room_id)?;
+    room.state.write().set_guest_role(&guest_id, role)?;
+    emit_state(&app, &room)
+}
+
+/// Replace the vote-to-skip threshold and SKIP restriction (host only)
+#[tauri::command]
+pub fn set_skip_policy(
+    room_id: String,
+    policy: SkipPolicy,
+    rooms: tauri::State<RoomRegistry>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let room = hosted_room(&rooms, &room_id)?;
+    room.state.write().set_skip_policy(policy);
+    emit_state(&app, &room)
+}
+
+/// Turn the Auto-DJ on with a collection and order, or off with `None` (host only)
+#[tauri::command]
+pub fn set_auto_dj(
+    room_id: String,
+    config: Option<AutoDjConfig>,
+    rooms: tauri::State<RoomRegistry>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let room = hosted_room(&rooms, &room_id)?;
+    room.state.write().set_auto_dj(config);
+    emit_state(&app, &room)
+}
+
 /// Broadcast the room state to the frontend.
 ///
 /// Emits two events deliberately:
 ///   `room_state_updated` — full state, including personal collections, for the
 ///                          host's own UI.
-///   `room_state_public`  — personal collections stripped, for rebroadcast to
-///                          guests over the data channel.
+///   `room_state_public`  — personal collections and voter ids stripped, for
+///                          rebroadcast to guests over the data channel, with
+///                          each guest's own view alongside.
 ///
 /// Keeping the filtered view on this side means the guest broadcast path never
 /// receives private data in the first place. Filtering in the frontend, as this
 /// previously did, left one `.filter()` standing between a guest and every
 /// personal playlist.
-fn emit_state(app: &AppHandle, state: &tauri::State<RoomStateManager>) -> Result<(), String> {
-    app.emit("room_state_updated", state.clone_state())
+///
+/// Both go only to the room's own host window, which relays to that room's
+/// guests alone.
+fn emit_state(app: &AppHandle, room: &HostedRoom) -> Result<(), String> {
+    app.emit_to(room.window.as_str(), "room_state_updated", room.state.clone_state())
         .map_err(|e| e.to_string())?;
-    app.emit("room_state_public", state.clone_public_state())
+    app.emit_to(room.window.as_str(), "room_state_public", room.state.clone_public_broadcast())
         .map_err(|e| e.to_string())?;
     Ok(())
 }
 
+/// Look up the room a command is for
+fn hosted_room(rooms: &RoomRegistry, room_id: &str) -> Result<HostedRoom, String> {
+    rooms.get(room_id).ok_or_else(|| format!("Room {} is not open", room_id))
+}
+
+/// A guest joined through the signaling server (see signaling.rs JOIN_ROOM)
+pub(crate) fn client_joined(app: &AppHandle, room_id: &str, client: ConnectedClient) {
+    let Some(room) = app.state::<RoomRegistry>().get(room_id) else {
+        log::warn!("[Signaling] Guest joined room {}, which has no host window", room_id);
+        return;
+    };
+    room.state.write().add_client(client);
+    if let Err(e) = emit_state(app, &room) {
+        log::warn!("[Signaling] Failed to emit state after join: {}", e);
+    }
+}
+
+/// Whether a guest joining now would only spectate (see signaling.rs
+/// JOIN_ROOM)
+pub(crate) fn is_at_capacity(app: &AppHandle, room_id: &str, capacity: usize, client_id: &str) -> bool {
+    app.state::<RoomRegistry>()
+        .get(room_id)
+        .is_some_and(|room| room.state.read().is_at_capacity(capacity, client_id))
+}
+
+/// The room as another host would take it over, if this process hosts it
+pub(crate) fn handoff_package(app: &AppHandle, room_id: &str) -> Option<HandoffPackage> {
+    app.state::<RoomRegistry>().get(room_id).map(|room| room.state.handoff_package())
+}
+
+/// A guest's signaling socket went away
+pub(crate) fn client_left(app: &AppHandle, room_id: &str, client_id: &str) {
+    let Some(room) = app.state::<RoomRegistry>().get(room_id) else { return };
+    room.state.write().remove_client(client_id);
+    if let Err(e) = emit_state(app, &room) {
+        log::warn!("[Signaling] Failed to emit state after leave: {}", e);
+    }
+}
+
 /// Broadcast only the player slice of the state.
 ///
 /// The host player reports progress roughly every five seconds, and each report
@@ -342,16 +947,13 @@ fn emit_state(app: &AppHandle, state: &tauri::State<RoomStateManager>) -> Result
 /// else. Structural changes (queue, collections) deliberately keep emitting the
 /// full state: they are rare, and a patch protocol for them would need sequence
 /// numbers and a resync path to be safe. See OPTIMIZATION.md #1.
-fn emit_player_patch(
-    app: &AppHandle,
-    state: &tauri::State<RoomStateManager>,
-) -> Result<(), String> {
-    let player = state.clone_player();
+fn emit_player_patch(app: &AppHandle, room: &HostedRoom) -> Result<(), String> {
+    let player = room.state.clone_public_player();
     // The host UI still wants the full object; it is in-process, so the cost is
     // a clone rather than a serialise-and-transmit.
-    app.emit("room_state_updated", state.clone_state())
+    app.emit_to(room.window.as_str(), "room_state_updated", room.state.clone_state())
         .map_err(|e| e.to_string())?;
-    app.emit("room_player_patch", player)
+    app.emit_to(room.window.as_str(), "room_player_patch", player)
         .map_err(|e| e.to_string())?;
     Ok(())
 }
@@ -359,12 +961,14 @@ fn emit_player_patch(
 /// Update player state (called from frontend YouTube player)
 #[tauri::command]
 pub fn update_player_state(
+    room_id: String,
     status: Option<String>,
     current_time: Option<f64>,
     duration: Option<f64>,
-    state: tauri::State<RoomStateManager>,
+    rooms: tauri::State<RoomRegistry>,
     app: AppHandle,
 ) -> Result<(), String> {
+    let room = hosted_room(&rooms, &room_id)?;
     let player_status = status.and_then(|s| match s.as_str() {
         "playing" => Some(PlayerStatus::Playing),
         "paused" => Some(PlayerStatus::Paused),
@@ -374,11 +978,11 @@ pub fn update_player_state(
         _ => None,
     });
     
-    state.write().update_player(player_status, current_time, duration);
+    room.state.write().update_player(player_status, current_time, duration);
 
     // Player ticks are by far the highest-frequency broadcast; patch instead of
     // resending the whole room.
-    emit_player_patch(&app, &state)?;
+    emit_player_patch(&app, &room)?;
 
     Ok(())
 }
@@ -479,6 +1083,11 @@ pub async fn playlist_add_song(
         thumbnail_url: metadata.thumbnail_url,
         added_by: added_by.unwrap_or_else(|| "Host".to_string()),
         added_at: chrono::Utc::now().timestamp_millis(),
+        singers: Vec::new(),
+        voters: Vec::new(),
+        votes: 0,
+        auto_filled: false,
+        owner_id: None,
     };
     let target_id = if collection_id.is_empty() {
         playlists.get_or_create_default_collection()
@@ -629,7 +1238,7 @@ pub async fn load_collection_from_file(
 
 /// Start the web/signaling server (called when entering Host Mode)
 #[tauri::command]
-pub fn start_host_server() -> Result<u16, String> {
+pub fn start_host_server(app: AppHandle) -> Result<u16, String> {
     if SERVER_STARTED.swap(true, Ordering::SeqCst) {
         // Already started — just return port
         return Ok(crate::web_server::get_server_port());
@@ -640,11 +1249,27 @@ pub fn start_host_server() -> Result<u16, String> {
     // hoping the bind had landed: wasted latency on a fast machine, and a race
     // on a slow one, where get_qr_url could be called against a port that was
     // not listening yet.
-    let (listener, port) = crate::web_server::bind_web_server().inspect_err(|_| {
+    let network = app.state::<NetworkSettingsStore>();
+    let settings = network.get();
+    let (listeners, port) = crate::web_server::bind_web_server(&settings).inspect_err(|_| {
         // Binding failed, so nothing is listening — let a later attempt retry
         // rather than latching the guard on a server that never started.
         SERVER_STARTED.store(false, Ordering::SeqCst);
     })?;
+    network.remember_port(port);
+
+    let tls = if settings.https {
+        match app.state::<CertificateStore>().server_config(&lan_ips()) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                log::error!("[Tauri] {}; serving plain http", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    crate::web_server::set_secure(tls.is_some());
 
     // Serving is the async half, and it owns its own runtime on a dedicated
     // thread to keep the axum server off Tauri's executor.
@@ -659,7 +1284,7 @@ pub fn start_host_server() -> Result<u16, String> {
         };
         rt.block_on(async {
             log::info!("[Tauri] Serving embedded web server on port {}", port);
-            if let Err(e) = crate::web_server::serve_web_server(listener).await {
+            if let Err(e) = crate::web_server::serve_web_server(listeners, app, tls).await {
                 log::error!("[Tauri] Web server error: {}", e);
             }
         });
@@ -765,17 +1390,9 @@ fn spawn_opener(target: &str) -> Result<(), String> {
 pub struct CreateRoomResponse {
     pub room_id: String,
     pub join_token: String,
-}
-
-/// Generate a unique room ID
-fn generate_room_id() -> String {
-    use sha2::{Sha256, Digest};
-    
-    let uuid = Uuid::new_v4();
-    let mut hasher = Sha256::new();
-    hasher.update(uuid.as_bytes());
-    let result = hasher.finalize();
-    hex::encode(&result[..3]) // 6 characters
+    /// Queue and song saved by a previous run that crashed or was closed
+    /// mid-session, offered for `restore_session`
+    pub restorable_session: Option<RestorableSession>,
 }
 
 /// Generate a secure join token
@@ -866,4 +1483,43 @@ mod tests {
         assert!(msg.contains("content://example/doc"));
         assert!(msg.contains("denied"));
     }
+
+    fn command(json: &str) -> ClientCommand {
+        serde_json::from_str(json).expect("valid command")
+    }
+
+    #[test]
+    fn test_permission_table_by_role() {
+        let seek = command(r#"{"type":"SEEK","time":30}"#);
+        assert!(seek.allowed_roles().contains(&Role::CoHost));
+        assert!(!seek.allowed_roles().contains(&Role::Singer));
+
+        let add = command(r#"{"type":"ADD_SONG","youtubeUrl":"dQw4w9WgXcQ"}"#);
+        assert!(add.allowed_roles().contains(&Role::Singer));
+        assert!(!add.allowed_roles().contains(&Role::Spectator));
+
+        let delete = command(r#"{"type":"DELETE_COLLECTION","collectionId":"c1"}"#);
+        assert_eq!(delete.allowed_roles(), &[Role::Host]);
+
+        let vote = command(r#"{"type":"VOTE_SKIP"}"#);
+        assert!(vote.allowed_roles().contains(&Role::Spectator));
+    }
+
+    #[test]
+    fn test_overflow_spectators_may_only_query() {
+        assert!(command(r#"{"type":"GET_HISTORY"}"#).is_query());
+        assert!(command(r#"{"type":"PING"}"#).is_query());
+        assert!(!command(r#"{"type":"VOTE_SKIP"}"#).is_query());
+        assert!(!command(r#"{"type":"SET_DISPLAY_NAME","name":"Ana"}"#).is_query());
+    }
+
+    #[test]
+    fn test_forbidden_error_names_role_and_allowed_roles() {
+        let err = CommandError::not_permitted(Role::Spectator, STAFF);
+        assert_eq!(err.code, "FORBIDDEN");
+        assert_eq!(
+            err.details,
+            Some(serde_json::json!({ "role": "spectator", "allowed": ["host", "cohost"] }))
+        );
+    }
 }
@@ -0,0 +1,188 @@
+//! LAN discovery over mDNS/DNS-SD.
+//!
+//! Every open room is advertised as a `_karaokenatin._tcp` service pointing at
+//! the embedded web server, so Guest mode on another device can list the hosts
+//! nearby instead of scanning a QR code. Guests found this way have no invite
+//! token and join with the room PIN.
+
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use crate::network::NetworkSettings;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+pub const SERVICE_TYPE: &str = "_karaokenatin._tcp.local.";
+
+/// TXT record keys
+const TXT_ROOM: &str = "room";
+const TXT_PORT: &str = "port";
+/// `https` when the web server speaks TLS; absent means `http`
+const TXT_SCHEME: &str = "scheme";
+
+/// The process's one mDNS responder, started on first use and shared by the
+/// advertised rooms and discovery.
+static DAEMON: Mutex<Option<ServiceDaemon>> = Mutex::new(None);
+
+/// A room found on the LAN
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiscoveredHost {
+    /// The room's id, which is also its advertised name
+    #[serde(rename = "roomId")]
+    pub room_id: String,
+    /// `ip:port` of the host's web server
+    pub address: String,
+    /// Join link for the room, without an invite token
+    pub url: String,
+}
+
+impl DiscoveredHost {
+    /// Read a resolved service. IPv4 is preferred, as that is what the QR URL
+    /// uses, and link-local addresses are left out, as a link to one cannot
+    /// carry its scope; services without a room or an address are skipped.
+    fn from_service(info: &ServiceInfo) -> Option<Self> {
+        let room_id = info.get_property_val_str(TXT_ROOM)?.to_string();
+        if room_id.is_empty() {
+            return None;
+        }
+        let port = info.get_property_val_str(TXT_PORT)
+            .and_then(|p| p.parse().ok())
+            .unwrap_or_else(|| info.get_port());
+        let ip = info.get_addresses().iter()
+            .filter(|ip| !crate::network::is_link_local(ip))
+            .min_by_key(|ip| (ip.is_ipv6(), **ip))
+            .copied()?;
+        let scheme = match info.get_property_val_str(TXT_SCHEME) {
+            Some("https") => "https",
+            _ => "http",
+        };
+        let address = SocketAddr::new(ip, port).to_string();
+        let url = format!("{}://{}/?r={}", scheme, address, encode_query_value(&room_id));
+        Some(Self { room_id, address, url })
+    }
+}
+
+/// Percent-encode a query value, keeping only unreserved characters, as the
+/// room id comes off the network and must not add parameters to the link.
+fn encode_query_value(value: &str) -> String {
+    value.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+fn daemon() -> Result<ServiceDaemon, String> {
+    let mut slot = DAEMON.lock();
+    if let Some(daemon) = slot.as_ref() {
+        return Ok(daemon.clone());
+    }
+    let daemon = ServiceDaemon::new().map_err(|e| format!("Failed to start mDNS: {}", e))?;
+    *slot = Some(daemon.clone());
+    Ok(daemon)
+}
+
+fn instance_name(room_id: &str) -> String {
+    format!("KaraokeNatin {}", room_id)
+}
+
+/// Advertise a room served on `port`, at the host's chosen address. Unless
+/// the server listens there only, other addresses are announced as they come
+/// and go.
+pub fn advertise_room(room_id: &str, port: u16, network: &NetworkSettings) -> Result<(), String> {
+    let ip: IpAddr = crate::network::get_local_ip(network.preferred_ip)?
+        .parse()
+        .map_err(|e| format!("Failed to read local IP: {}", e))?;
+    // One SRV host per machine, however many rooms it runs
+    let host_name = format!("karaokenatin-{}.local.", ip.to_string().replace(['.', ':'], "-"));
+    let port_text = port.to_string();
+    let properties = [
+        (TXT_ROOM, room_id),
+        (TXT_PORT, port_text.as_str()),
+        (TXT_SCHEME, crate::web_server::scheme()),
+    ];
+    let mut info = ServiceInfo::new(SERVICE_TYPE, &instance_name(room_id), &host_name, ip, port, &properties[..])
+        .map_err(|e| format!("Invalid mDNS service: {}", e))?;
+    if network.bind_ip().is_none() {
+        info = info.enable_addr_auto();
+    }
+    daemon()?
+        .register(info)
+        .map_err(|e| format!("Failed to advertise room: {}", e))
+}
+
+/// Stop advertising a room that closed
+pub fn withdraw_room(room_id: &str) {
+    let Some(daemon) = DAEMON.lock().clone() else { return };
+    let fullname = format!("{}.{}", instance_name(room_id), SERVICE_TYPE);
+    if let Err(e) = daemon.unregister(&fullname) {
+        log::warn!("Failed to withdraw room {} from mDNS: {}", room_id, e);
+    }
+}
+
+/// Browse for rooms for `window`, then return what answered, this host's own
+/// rooms included.
+pub fn discover(window: Duration) -> Result<Vec<DiscoveredHost>, String> {
+    let daemon = daemon()?;
+    let events = daemon.browse(SERVICE_TYPE).map_err(|e| format!("Failed to browse: {}", e))?;
+    let deadline = Instant::now() + window;
+    let mut found: Vec<DiscoveredHost> = Vec::new();
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        match events.recv_timeout(left) {
+            Ok(ServiceEvent::ServiceResolved(info)) => {
+                if let Some(host) = DiscoveredHost::from_service(&info) {
+                    // A service re-resolves when its addresses change
+                    found.retain(|h| h.room_id != host.room_id || h.address == host.address);
+                    if !found.contains(&host) {
+                        found.push(host);
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    if let Err(e) = daemon.stop_browse(SERVICE_TYPE) {
+        log::warn!("Failed to stop mDNS browse: {}", e);
+    }
+    found.sort_by(|a, b| a.room_id.cmp(&b.room_id));
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(ips: &str, properties: &[(&str, &str)]) -> ServiceInfo {
+        ServiceInfo::new(SERVICE_TYPE, "KaraokeNatin ABCD2345", "karaokenatin-test.local.", ips, 8080, properties).unwrap()
+    }
+
+    #[test]
+    fn reads_room_and_port_from_txt() {
+        let host = DiscoveredHost::from_service(&service("192.168.1.20", &[("room", "ABCD2345"), ("port", "9000")])).unwrap();
+        assert_eq!(host.room_id, "ABCD2345");
+        assert_eq!(host.address, "192.168.1.20:9000");
+        assert_eq!(host.url, "http://192.168.1.20:9000/?r=ABCD2345");
+    }
+
+    #[test]
+    fn links_use_the_advertised_scheme_and_encode_the_room() {
+        let host = DiscoveredHost::from_service(&service("192.168.1.20", &[("room", "side room&x=1"), ("scheme", "https")])).unwrap();
+        assert_eq!(host.url, "https://192.168.1.20:8080/?r=side%20room%26x%3D1");
+        let host = DiscoveredHost::from_service(&service("192.168.1.20", &[("room", "ABCD2345"), ("scheme", "javascript")])).unwrap();
+        assert_eq!(host.url, "http://192.168.1.20:8080/?r=ABCD2345");
+    }
+
+    #[test]
+    fn prefers_ipv4_and_skips_services_without_a_room() {
+        let host = DiscoveredHost::from_service(&service("fe80::1,10.0.0.7", &[("room", "ABCD2345")])).unwrap();
+        assert_eq!(host.address, "10.0.0.7:8080");
+        assert!(DiscoveredHost::from_service(&service("10.0.0.7", &[])).is_none());
+    }
+
+    #[test]
+    fn brackets_ipv6_hosts_and_skips_link_local() {
+        let host = DiscoveredHost::from_service(&service("fe80::1,2001:db8::7", &[("room", "ABCD2345")])).unwrap();
+        assert_eq!(host.url, "http://[2001:db8::7]:8080/?r=ABCD2345");
+        assert!(DiscoveredHost::from_service(&service("fe80::1", &[("room", "ABCD2345")])).is_none());
+    }
+}
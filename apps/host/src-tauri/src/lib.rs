@@ -4,29 +4,28 @@ mod room_state;
 mod commands;
 mod metadata;
 mod network;
+mod discovery;
+mod tls;
 mod web_server;
 mod youtube;
 pub mod peer_server;
 mod signaling;
 
-use room_state::{RoomStateManager, PlaylistStore};
-use uuid::Uuid;
+use network::NetworkSettingsStore;
+use signaling::RoomManager;
+use tls::CertificateStore;
+use room_state::{RoomRegistry, PlaylistStore, SessionStore};
+use std::time::Duration;
 use tauri::Manager;
 
+/// How often the live room is written to `session.json`
+const SESSION_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // PlaylistStore is always available (both Host & Guest modes)
     let playlist_store = PlaylistStore::new();
 
-    // Room state manager — playlists injected from store
-    let initial_room_id = "pending".to_string();
-    let initial_peer_id = Uuid::new_v4().to_string();
-    let room_manager = RoomStateManager::new(
-        initial_room_id,
-        initial_peer_id,
-        playlist_store.get_all(),
-    );
-
     let mut builder = tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         // Backs `commands::save_collection_to_file` / `load_collection_from_file`:
@@ -44,7 +43,14 @@ pub fn run() {
 
     builder
         .manage(playlist_store)
-        .manage(room_manager)
+        // Rooms open as host windows call `create_room`; playlists are
+        // injected from the store then
+        .manage(RoomRegistry::new())
+        .manage(SessionStore::new())
+        .manage(NetworkSettingsStore::new())
+        .manage(CertificateStore::new())
+        // Signaling's rooms, shared with the web server when it starts
+        .manage(RoomManager::new())
         .setup(|app| {
             if cfg!(debug_assertions) {
                 app.handle().plugin(
@@ -57,33 +63,77 @@ pub fn run() {
             // Initialize PlaylistStore with persistent path
             let app_handle = app.handle();
             let playlist_store = app_handle.state::<PlaylistStore>();
-            let room_manager = app_handle.state::<RoomStateManager>();
-            
+
             match app.path().app_local_data_dir() {
                 Ok(path) => {
                     log::info!("Resolved app local data dir: {:?}", path);
-                    let loaded_playlists = playlist_store.initialize(path);
-                    
-                    // Sync initial playlists to RoomStateManager
-                    let mut state = room_manager.write();
-                    state.sync_playlists(loaded_playlists);
+                    let loaded_playlists = playlist_store.initialize(path.clone());
+
+                    // Sync initial playlists to any room already open
+                    app_handle.state::<RoomRegistry>().sync_playlists(&loaded_playlists);
+
+                    app_handle.state::<SessionStore>().initialize(path.clone());
+                    app_handle.state::<NetworkSettingsStore>().initialize(path.clone());
+                    app_handle.state::<CertificateStore>().initialize(path);
                 }
                 Err(e) => log::error!("Failed to resolve app local data dir: {}", e),
             }
 
+            // Snapshot the main room to disk so a crash or reboot mid-party
+            // does not lose the queue.
+            let saver_handle = app_handle.clone();
+            std::thread::spawn(move || loop {
+                std::thread::sleep(SESSION_SAVE_INTERVAL);
+                if let Some(room) = saver_handle.state::<RoomRegistry>().main() {
+                    saver_handle.state::<SessionStore>().sync(&room);
+                }
+            });
+
             // NOTE: Web server is now started lazily via start_host_server command
             // when the user picks Host Mode from the landing screen.
 
             Ok(())
         })
+        // A closed window never leaves Host Mode itself, so close its room
+        // here. Its guests get the signaling server's host grace period.
+        .on_window_event(|window, event| {
+            if let tauri::WindowEvent::Destroyed = event {
+                let closed = window.state::<RoomRegistry>().close_window(window.label());
+                if !closed.is_empty() {
+                    log::info!("Window {} closed, closing rooms {:?}", window.label(), closed);
+                }
+                for room_id in &closed {
+                    discovery::withdraw_room(room_id);
+                }
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             // Host-mode room commands
             commands::create_room,
+            commands::close_room,
+            commands::open_room_window,
             commands::get_qr_url,
             commands::get_server_port,
+            commands::discover_hosts,
+            commands::list_network_addresses,
+            commands::get_network_settings,
+            commands::set_network_settings,
+            commands::get_certificate_fingerprint,
             commands::get_room_state,
+            commands::get_public_state,
+            commands::verify_guest,
             commands::search_youtube,
             commands::process_command,
+            commands::set_queue_limits,
+            commands::set_auto_dj,
+            commands::set_skip_policy,
+            commands::set_guest_role,
+            commands::restore_session,
+            commands::import_handoff,
+            commands::discard_session,
+            commands::finish_song,
+            commands::get_play_history,
+            commands::save_history_to_file,
             commands::update_player_state,
             commands::export_collection,
             commands::start_host_server,
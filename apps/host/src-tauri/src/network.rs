@@ -1,35 +1,356 @@
-use local_ip_address::local_ip;
+use local_ip_address::{list_afinet_netifas, local_ip};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
 use crate::web_server;
 
-/// Get the local IP address of the host machine
-pub fn get_local_ip() -> Result<String, String> {
+/// Interface name prefixes of container bridges, VPNs and other adapters that
+/// guests on the Wi-Fi cannot reach
+const VIRTUAL_PREFIXES: &[&str] = &[
+    "docker", "br-", "veth", "virbr", "vmnet", "vboxnet", "tun", "tap", "utun", "wg", "zt", "ppp", "ipsec",
+    "llw", "awdl",
+];
+/// Substrings for adapters named by description, as on Windows
+const VIRTUAL_NAMES: &[&str] = &[
+    "virtualbox", "vmware", "vethernet", "hyper-v", "wsl", "tailscale", "zerotier", "hamachi", "vpn",
+];
+
+/// One address guests might reach the server on
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NetworkAddress {
+    pub interface: String,
+    pub ip: IpAddr,
+    pub url: String,
+    /// A container, VM or VPN adapter; rarely what guests are on
+    #[serde(rename = "likelyVirtual")]
+    pub likely_virtual: bool,
+}
+
+/// Ports the server may listen on, both ends included
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl PortRange {
+    pub fn contains(&self, port: u16) -> bool {
+        (self.start..=self.end).contains(&port)
+    }
+}
+
+/// The IANA ephemeral range, used when the host has not set a range
+pub const DEFAULT_PORT_RANGE: PortRange = PortRange { start: 49152, end: 65535 };
+
+/// Host choices for how guests reach the server, kept in `network.json`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NetworkSettings {
+    /// Address the QR code points at; `None` picks the likeliest one
+    #[serde(rename = "preferredIp", default)]
+    pub preferred_ip: Option<IpAddr>,
+    /// Listen on `preferred_ip` only (plus loopback for the host's own
+    /// window). Applies the next time the server starts.
+    #[serde(rename = "bindPreferredOnly", default)]
+    pub bind_preferred_only: bool,
+    /// Port to listen on when it is free. Remembered from the first start, so
+    /// bookmarked links and firewall rules keep working.
+    #[serde(rename = "preferredPort", default)]
+    pub preferred_port: Option<u16>,
+    /// Ports to pick from when the preferred one is taken
+    #[serde(rename = "portRange", default)]
+    pub port_range: Option<PortRange>,
+    /// Serve guests over HTTPS with the host's own certificate (see tls.rs)
+    #[serde(default)]
+    pub https: bool,
+}
+
+impl NetworkSettings {
+    /// The one address to listen on, if the host asked for that
+    pub fn bind_ip(&self) -> Option<IpAddr> {
+        self.preferred_ip.filter(|_| self.bind_preferred_only)
+    }
+}
+
+fn is_virtual_interface(name: &str) -> bool {
+    let name = name.to_lowercase();
+    VIRTUAL_PREFIXES.iter().any(|p| name.starts_with(p)) || VIRTUAL_NAMES.iter().any(|n| name.contains(n))
+}
+
+/// `fe80::/10`; only reachable with the interface it is on (its scope)
+fn is_ipv6_link_local(ip: &Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xffc0 == 0xfe80
+}
+
+/// Link-local in either family
+pub fn is_link_local(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => v4.is_link_local(),
+        IpAddr::V6(v6) => is_ipv6_link_local(v6),
+    }
+}
+
+/// `fc00::/7`, the IPv6 counterpart of the private IPv4 ranges
+fn is_ipv6_unique_local(ip: &Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xfe00 == 0xfc00
+}
+
+/// Sort key: real adapters before virtual ones, then home-router ranges
+/// before other private ones, with carrier-grade NAT (often a VPN) last.
+/// IPv6 comes after IPv4, so it is picked only on IPv6-only networks such as
+/// some phone hotspots.
+fn rank(address: &NetworkAddress) -> (bool, u8) {
+    let class = match address.ip {
+        IpAddr::V4(ip) => match ip.octets() {
+            [192, 168, ..] => 0,
+            [10, ..] => 1,
+            [172, b, ..] if (16..32).contains(&b) => 2,
+            [100, b, ..] if (64..128).contains(&b) => 4,
+            _ => 3,
+        },
+        IpAddr::V6(ip) if is_ipv6_unique_local(&ip) => 5,
+        IpAddr::V6(_) => 6,
+    };
+    (address.likely_virtual, class)
+}
+
+/// Link to the server at `ip`; IPv6 goes in brackets
+pub fn server_url(ip: IpAddr, port: u16) -> String {
+    format!("{}://{}", web_server::scheme(), SocketAddr::new(ip, port))
+}
+
+/// Turn `(interface, ip)` pairs into ranked candidates, dropping loopback,
+/// link-local and multicast addresses. A link to a link-local address cannot
+/// carry its scope, so browsers cannot open it; on an IPv6-only hotspot
+/// guests are reached on the global or unique local address the hotspot
+/// hands out, and a network with link-local addresses only is not supported.
+fn candidates(interfaces: Vec<(String, IpAddr)>, port: u16) -> Vec<NetworkAddress> {
+    let mut addresses: Vec<NetworkAddress> = interfaces
+        .into_iter()
+        .filter(|(_, ip)| match ip {
+            IpAddr::V4(v4) => !v4.is_loopback() && !v4.is_link_local() && !v4.is_unspecified(),
+            IpAddr::V6(v6) => {
+                !v6.is_loopback() && !v6.is_unspecified() && !v6.is_multicast() && !is_ipv6_link_local(v6)
+            }
+        })
+        .map(|(interface, ip)| NetworkAddress {
+            likely_virtual: is_virtual_interface(&interface),
+            url: server_url(ip, port),
+            interface,
+            ip,
+        })
+        .collect();
+    addresses.sort_by_key(rank);
+    addresses
+}
+
+/// Every address the server might be reached on, likeliest first
+pub fn list_addresses() -> Result<Vec<NetworkAddress>, String> {
+    let interfaces = list_afinet_netifas().map_err(|e| format!("Failed to list network interfaces: {}", e))?;
+    Ok(candidates(interfaces, web_server::get_server_port()))
+}
+
+/// The host's pick while it is still up, else the likeliest candidate
+fn choose(addresses: &[NetworkAddress], preferred: Option<IpAddr>) -> Option<IpAddr> {
+    preferred
+        .filter(|ip| addresses.iter().any(|a| a.ip == *ip))
+        .or_else(|| addresses.first().map(|a| a.ip))
+}
+
+/// Get the local IP address guests should use: `preferred` if it is still
+/// present, otherwise the likeliest LAN address
+pub fn get_local_ip(preferred: Option<IpAddr>) -> Result<String, String> {
+    let addresses = list_addresses().unwrap_or_default();
+    if let Some(ip) = choose(&addresses, preferred) {
+        return Ok(ip.to_string());
+    }
     local_ip()
         .map(|ip| ip.to_string())
         .map_err(|e| format!("Failed to get local IP: {}", e))
 }
 
 /// Generate the QR URL for clients to connect
-pub fn generate_qr_url() -> Result<String, String> {
-    let ip = get_local_ip()?;
-    let port = web_server::get_server_port();
-    Ok(format!("http://{}:{}", ip, port))
+pub fn generate_qr_url(preferred: Option<IpAddr>) -> Result<String, String> {
+    let ip = get_local_ip(preferred)?
+        .parse()
+        .map_err(|e| format!("Failed to read local IP: {}", e))?;
+    Ok(server_url(ip, web_server::get_server_port()))
+}
+
+/// Keeps `network.json` (next to `playlists.json`) with the host's network
+/// choices.
+pub struct NetworkSettingsStore {
+    path: Arc<RwLock<Option<PathBuf>>>,
+    settings: Arc<RwLock<NetworkSettings>>,
+}
+
+impl NetworkSettingsStore {
+    pub fn new() -> Self {
+        Self {
+            path: Arc::new(RwLock::new(None)),
+            settings: Arc::new(RwLock::new(NetworkSettings::default())),
+        }
+    }
+
+    /// Point the store at the app data dir and load what was saved there
+    pub fn initialize(&self, app_data_dir: PathBuf) {
+        let mut path = app_data_dir;
+        let _ = fs::create_dir_all(&path);
+        path.push("network.json");
+
+        if path.exists() {
+            match fs::read_to_string(&path).map_err(|e| e.to_string())
+                .and_then(|content| serde_json::from_str::<NetworkSettings>(&content).map_err(|e| e.to_string()))
+            {
+                Ok(settings) => *self.settings.write() = settings,
+                Err(e) => log::error!("Failed to load network settings {:?}: {}", path, e),
+            }
+        }
+        *self.path.write() = Some(path);
+    }
+
+    pub fn get(&self) -> NetworkSettings {
+        self.settings.read().clone()
+    }
+
+    pub fn set(&self, settings: NetworkSettings) -> Result<(), String> {
+        validate(&settings)?;
+        *self.settings.write() = settings.clone();
+        self.save(&settings)
+    }
+
+    /// Keep the port the server landed on for next time, unless the host
+    /// already chose one
+    pub fn remember_port(&self, port: u16) {
+        let settings = {
+            let mut settings = self.settings.write();
+            if settings.preferred_port.is_some() {
+                return;
+            }
+            settings.preferred_port = Some(port);
+            settings.clone()
+        };
+        if let Err(e) = self.save(&settings) {
+            log::error!("{}", e);
+        }
+    }
+
+    fn save(&self, settings: &NetworkSettings) -> Result<(), String> {
+        if let Some(path) = self.path.read().as_ref() {
+            let content = serde_json::to_string_pretty(settings)
+                .map_err(|e| format!("Failed to serialize network settings: {}", e))?;
+            fs::write(path, content).map_err(|e| format!("Failed to save network settings: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+fn validate(settings: &NetworkSettings) -> Result<(), String> {
+    if settings.bind_preferred_only && settings.preferred_ip.is_none() {
+        return Err("Pick an address before listening on it only".to_string());
+    }
+    if settings.preferred_ip.is_some_and(|ip| is_link_local(&ip)) {
+        return Err("Guests cannot be sent to a link-local address".to_string());
+    }
+    if settings.preferred_port == Some(0) {
+        return Err("Port 0 is not a port".to_string());
+    }
+    if let Some(range) = settings.port_range {
+        if range.start == 0 || range.start > range.end {
+            return Err(format!("{}-{} is not a port range", range.start, range.end));
+        }
+        if let Some(port) = settings.preferred_port.filter(|p| !range.contains(*p)) {
+            return Err(format!("Port {} is outside {}-{}", port, range.start, range.end));
+        }
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::net::Ipv4Addr;
+
+    fn v4(a: u8, b: u8, c: u8, d: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(a, b, c, d))
+    }
 
     #[test]
     fn test_get_local_ip() {
-        let ip = get_local_ip();
+        let ip = get_local_ip(None);
         assert!(ip.is_ok());
         println!("Local IP: {}", ip.unwrap());
     }
 
     #[test]
     fn test_generate_qr_url() {
-        let url = generate_qr_url();
+        let url = generate_qr_url(None);
         assert!(url.is_ok());
         assert!(url.unwrap().starts_with("http://"));
     }
+
+    #[test]
+    fn ranks_the_wifi_above_docker_and_vpn_adapters() {
+        let found = candidates(vec![
+            ("lo".to_string(), v4(127, 0, 0, 1)),
+            ("docker0".to_string(), v4(172, 17, 0, 1)),
+            ("tailscale0".to_string(), v4(100, 101, 2, 3)),
+            ("eth1".to_string(), v4(10, 0, 0, 4)),
+            ("wlan0".to_string(), v4(192, 168, 1, 20)),
+            ("eth0".to_string(), v4(169, 254, 3, 3)),
+        ], 8080);
+        let ips: Vec<IpAddr> = found.iter().map(|a| a.ip).collect();
+        assert_eq!(ips, vec![v4(192, 168, 1, 20), v4(10, 0, 0, 4), v4(172, 17, 0, 1), v4(100, 101, 2, 3)]);
+        assert_eq!(found[0].url, "http://192.168.1.20:8080");
+        assert!(found[2].likely_virtual && found[3].likely_virtual);
+    }
+
+    #[test]
+    fn brackets_ipv6_ranks_it_after_ipv4_and_drops_link_local() {
+        let found = candidates(vec![
+            ("wlan0".to_string(), "fe80::1c2:3ff:fe04:506".parse().unwrap()),
+            ("wlan0".to_string(), "2001:db8::20".parse().unwrap()),
+            ("lo".to_string(), "::1".parse().unwrap()),
+            ("wlan0".to_string(), "fd00::20".parse().unwrap()),
+            ("wlan0".to_string(), v4(192, 168, 1, 20)),
+        ], 8080);
+        let urls: Vec<&str> = found.iter().map(|a| a.url.as_str()).collect();
+        assert_eq!(urls, vec![
+            "http://192.168.1.20:8080",
+            "http://[fd00::20]:8080",
+            "http://[2001:db8::20]:8080",
+        ]);
+    }
+
+    #[test]
+    fn rejects_ports_outside_the_range() {
+        let range = Some(PortRange { start: 8000, end: 8010 });
+        let ok = NetworkSettings { preferred_port: Some(8005), port_range: range, ..Default::default() };
+        assert!(validate(&ok).is_ok());
+        assert!(validate(&NetworkSettings { preferred_port: Some(9000), ..ok.clone() }).is_err());
+        assert!(validate(&NetworkSettings { port_range: Some(PortRange { start: 8010, end: 8000 }), ..ok.clone() }).is_err());
+        assert!(validate(&NetworkSettings { preferred_port: Some(0), port_range: None, ..ok.clone() }).is_err());
+        assert!(validate(&NetworkSettings { preferred_ip: Some("fe80::1".parse().unwrap()), ..ok }).is_err());
+    }
+
+    #[test]
+    fn remembers_the_first_port_only() {
+        let store = NetworkSettingsStore::new();
+        store.remember_port(51234);
+        store.remember_port(50000);
+        assert_eq!(store.get().preferred_port, Some(51234));
+    }
+
+    #[test]
+    fn keeps_the_hosts_pick_only_while_it_exists() {
+        let found = candidates(vec![
+            ("wlan0".to_string(), v4(192, 168, 1, 20)),
+            ("eth0".to_string(), v4(10, 0, 0, 4)),
+        ], 8080);
+        assert_eq!(choose(&found, Some(v4(10, 0, 0, 4))), Some(v4(10, 0, 0, 4)));
+        assert_eq!(choose(&found, Some(v4(10, 9, 9, 9))), Some(v4(192, 168, 1, 20)));
+        assert_eq!(choose(&[], None), None);
+    }
 }
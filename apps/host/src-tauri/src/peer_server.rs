@@ -87,7 +87,7 @@ impl PeerRegistry {
     ///
     /// A reconnecting client reuses its id; dropping the stale sender keeps the
     /// map from accumulating dead entries that would swallow forwarded messages.
-    fn insert(&self, id: String, tx: Tx) {
+    pub(crate) fn insert(&self, id: String, tx: Tx) {
         self.peers.write().insert(id, tx);
     }
 
@@ -95,11 +95,33 @@ impl PeerRegistry {
         self.peers.write().remove(id);
     }
 
+    /// Remove a peer only while `tx` is still its registered sender. Guests
+    /// keep their id across reconnects, so the old socket closing late must
+    /// not unregister the one that replaced it.
+    fn remove_if_current(&self, id: &str, tx: &Tx) {
+        let mut peers = self.peers.write();
+        if peers.get(id).is_some_and(|current| current.same_channel(tx)) {
+            peers.remove(id);
+        }
+    }
+
+    /// Close a peer's relay socket and forget it, so it can no longer be
+    /// offered a connection. Returns whether the peer was connected.
+    pub fn disconnect(&self, id: &str) -> bool {
+        match self.peers.write().remove(id) {
+            Some(tx) => {
+                let _ = tx.send(Message::Close(None));
+                true
+            }
+            None => false,
+        }
+    }
+
     fn get(&self, id: &str) -> Option<Tx> {
         self.peers.read().get(id).cloned()
     }
 
-    fn contains(&self, id: &str) -> bool {
+    pub(crate) fn contains(&self, id: &str) -> bool {
         self.peers.read().contains_key(id)
     }
 
@@ -204,7 +226,7 @@ async fn handle_peer_socket(socket: WebSocket, peer_id: String, registry: PeerRe
         }
     }
 
-    registry.remove(&peer_id);
+    registry.remove_if_current(&peer_id, &tx);
     writer.abort();
     log::info!("[PeerServer] Peer disconnected: {}", peer_id);
 }
@@ -263,6 +285,32 @@ mod tests {
         assert!(rx1.try_recv().is_err());
     }
 
+    #[test]
+    fn stale_socket_closing_late_keeps_the_replacement() {
+        let reg = PeerRegistry::new();
+        let (old_tx, _old_rx) = mpsc::unbounded_channel();
+        let (new_tx, _new_rx) = mpsc::unbounded_channel();
+        reg.insert("a".into(), old_tx.clone());
+        reg.insert("a".into(), new_tx.clone());
+
+        reg.remove_if_current("a", &old_tx);
+        assert!(reg.contains("a"), "the replacement must stay registered");
+        reg.remove_if_current("a", &new_tx);
+        assert!(!reg.contains("a"));
+    }
+
+    #[test]
+    fn disconnect_closes_the_socket_and_drops_the_peer() {
+        let reg = PeerRegistry::new();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        reg.insert("a".into(), tx);
+
+        assert!(reg.disconnect("a"));
+        assert!(!reg.contains("a"));
+        assert!(matches!(rx.try_recv(), Ok(Message::Close(None))));
+        assert!(!reg.disconnect("a"));
+    }
+
     #[test]
     fn envelope_round_trips_and_omits_empty_fields() {
         let e = envelope("OFFER", Some("peer-b"));
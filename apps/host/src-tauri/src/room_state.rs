@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::path::PathBuf;
 use std::fs;
+use std::collections::{HashMap, VecDeque};
 
 /// Load playlists from file
 fn load_playlists_from_file(path: &PathBuf) -> Vec<PlaylistCollection> {
@@ -54,6 +55,86 @@ pub struct Song {
     pub added_by: String,
     #[serde(rename = "addedAt")]
     pub added_at: i64,
+    /// Extra singers on a duet or group song, besides `added_by`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub singers: Vec<SongSinger>,
+    /// Who upvoted this song while it was queued, one entry per guest. Host
+    /// only: guests see `votes` and whether they voted themselves.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub voters: Vec<String>,
+    /// Number of upvotes, filled in by `public_state` for guests
+    #[serde(default)]
+    pub votes: usize,
+    /// Queued by the Auto-DJ rather than by someone in the room
+    #[serde(rename = "autoFilled", default, skip_serializing_if = "std::ops::Not::not")]
+    pub auto_filled: bool,
+    /// Peer id of the guest who queued this song; `None` for the host and
+    /// the Auto-DJ
+    #[serde(rename = "ownerId", default, skip_serializing_if = "Option::is_none")]
+    pub owner_id: Option<String>,
+}
+
+/// Whether an invited singer has agreed to sing
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum InviteStatus {
+    Pending,
+    Accepted,
+}
+
+/// An extra singer on a duet or group song
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SongSinger {
+    pub name: String,
+    /// Who invited them; `None` when they joined on their own
+    #[serde(rename = "invitedBy")]
+    pub invited_by: Option<String>,
+    pub status: InviteStatus,
+    /// Peer id of the guest this entry belongs to, so only they can accept
+    /// or decline it. Host only; `None` for names the staff added.
+    #[serde(skip)]
+    pub client_id: Option<String>,
+}
+
+/// Who is changing a song's singers, by peer id. Staff (`None` for the host)
+/// may change anyone; a guest acts only as themselves or as the lead singer
+/// of a song they queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuetActor<'a> {
+    Staff(Option<&'a str>),
+    Guest(&'a str),
+}
+
+impl Song {
+    /// Strip who queued and who voted for it, leaving the vote count, for
+    /// sending to guests
+    fn make_public(&mut self) {
+        self.votes = self.voters.len();
+        self.voters.clear();
+        self.owner_id = None;
+    }
+
+    /// Key used to group songs by singer for fair rotation (the lead singer)
+    pub fn singer_key(&self) -> &str {
+        &self.added_by
+    }
+
+    /// Everyone who actually performs this song: the lead plus every accepted
+    /// duet partner. Pending invitees are not credited.
+    pub fn performers(&self) -> Vec<&str> {
+        let mut names = vec![self.added_by.as_str()];
+        for singer in &self.singers {
+            if singer.status == InviteStatus::Accepted && !names.contains(&singer.name.as_str()) {
+                names.push(&singer.name);
+            }
+        }
+        names
+    }
+
+    /// Whether `name` is the lead or is listed on this song in any state
+    fn has_singer(&self, name: &str) -> bool {
+        self.added_by == name || self.singers.iter().any(|s| s.name == name)
+    }
 }
 
 /// Collection visibility
@@ -301,6 +382,11 @@ impl PlaylistStore {
                 thumbnail_url: song.thumbnail_url.clone(),
                 added_by: song.added_by.clone(),
                 added_at: chrono::Utc::now().timestamp_millis(),
+                singers: Vec::new(),
+                voters: Vec::new(),
+                votes: 0,
+                auto_filled: false,
+                owner_id: None,
             })
     }
 
@@ -432,6 +518,176 @@ pub struct PlayerState {
     pub is_muted: bool,
 }
 
+/// How upcoming songs in the queue are ordered
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueMode {
+    /// First come, first served
+    #[default]
+    Fifo,
+    /// Round-robin between singers, keeping each singer's own order
+    Rotation,
+    /// Most upvoted first, then first come, first served
+    Votes,
+}
+
+/// Host-configurable limits on what guests may add to the queue.
+///
+/// `None` disables a limit. The host's own additions are never limited.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct QueueLimits {
+    /// Maximum songs a single guest may have waiting in the queue, counted by
+    /// who added them rather than the name they typed
+    #[serde(rename = "maxPendingPerSinger")]
+    pub max_pending_per_singer: Option<usize>,
+    /// Minimum seconds between two additions from the same guest
+    #[serde(rename = "addCooldownSecs")]
+    pub add_cooldown_secs: Option<u64>,
+    /// Maximum number of songs waiting in the queue overall
+    #[serde(rename = "maxQueueLength")]
+    pub max_queue_length: Option<usize>,
+}
+
+/// Why a guest's song was refused, in a form the remote UI can act on
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum QueueRejection {
+    SingerQuota { limit: usize },
+    Cooldown {
+        #[serde(rename = "retryAfterMs")]
+        retry_after_ms: i64,
+    },
+    QueueFull { limit: usize },
+}
+
+impl QueueRejection {
+    /// Stable machine-readable code for this rejection
+    pub fn code(&self) -> &'static str {
+        match self {
+            QueueRejection::SingerQuota { .. } => "SINGER_QUOTA_REACHED",
+            QueueRejection::Cooldown { .. } => "ADD_COOLDOWN",
+            QueueRejection::QueueFull { .. } => "QUEUE_FULL",
+        }
+    }
+
+    /// Human-readable explanation, for clients that just show the message
+    pub fn message(&self) -> String {
+        match self {
+            QueueRejection::SingerQuota { limit } => {
+                format!("You already have {} songs waiting in the queue", limit)
+            }
+            QueueRejection::Cooldown { retry_after_ms } => {
+                format!("Please wait {}s before adding another song", (retry_after_ms + 999) / 1000)
+            }
+            QueueRejection::QueueFull { limit } => {
+                format!("The queue is full ({} songs)", limit)
+            }
+        }
+    }
+}
+
+/// Host settings for skipping songs
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SkipPolicy {
+    /// Fraction of connected guests whose VOTE_SKIP ends the song (0.0–1.0)
+    #[serde(rename = "voteThreshold")]
+    pub vote_threshold: f64,
+    /// Refuse the raw SKIP from guests, leaving them VOTE_SKIP
+    #[serde(rename = "restrictSkip")]
+    pub restrict_skip: bool,
+}
+
+impl Default for SkipPolicy {
+    fn default() -> Self {
+        Self {
+            vote_threshold: 0.5,
+            restrict_skip: false,
+        }
+    }
+}
+
+/// Votes to skip the song on stage
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SkipVotes {
+    /// The song the votes are for; they reset when it changes
+    #[serde(rename = "songId")]
+    pub song_id: Option<String>,
+    /// Peer ids of the guests who voted. Host only: guests see `votes`.
+    pub voters: Vec<String>,
+    /// Number of votes, filled in by `public_state` for guests
+    #[serde(default)]
+    pub votes: usize,
+    /// Votes that end the song, given the guests connected right now
+    pub needed: usize,
+}
+
+/// Name the Auto-DJ's songs are queued under
+pub const AUTO_DJ_NAME: &str = "Auto-DJ";
+
+/// Order the Auto-DJ takes songs from its collection in
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoDjOrder {
+    /// Collection order, skipping songs already played
+    #[default]
+    Sequential,
+    /// Random pick among songs not played yet
+    Shuffle,
+}
+
+/// Keeps the room going when the queue runs dry by queueing songs from a
+/// host-chosen collection. Never repeats a song played this session.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AutoDjConfig {
+    #[serde(rename = "collectionId")]
+    pub collection_id: String,
+    #[serde(default)]
+    pub order: AutoDjOrder,
+}
+
+/// How many queue changes UNDO can walk back
+const MAX_UNDO_HISTORY: usize = 50;
+
+/// The queue and the song on stage, as they were before one queue change
+#[derive(Debug)]
+struct QueueSnapshot {
+    queue: Vec<Song>,
+    current_song: Option<Song>,
+}
+
+/// Bounded undo/redo stacks of queue changes.
+///
+/// Snapshots are immutable and shared through `Arc`, so cloning the room for a
+/// broadcast does not deep-copy the whole history. Only `canUndo`/`canRedo` are
+/// serialised; the snapshots themselves never leave the host.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct QueueHistory {
+    #[serde(skip)]
+    undo: VecDeque<Arc<QueueSnapshot>>,
+    #[serde(skip)]
+    redo: Vec<Arc<QueueSnapshot>>,
+}
+
+impl QueueHistory {
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+impl Serialize for QueueHistory {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut out = serializer.serialize_struct("QueueHistory", 2)?;
+        out.serialize_field("canUndo", &self.can_undo())?;
+        out.serialize_field("canRedo", &self.can_redo())?;
+        out.end()
+    }
+}
+
 /// Connected client information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectedClient {
@@ -440,6 +696,166 @@ pub struct ConnectedClient {
     pub display_name: String,
     #[serde(rename = "connectedAt")]
     pub connected_at: i64,
+    /// Let in past the room's capacity: a spectator who can watch but not
+    /// send commands, until the host gives them a role
+    #[serde(default)]
+    pub overflow: bool,
+    /// Token signaling issued this guest's device; their DataConnection
+    /// must present it before their commands are accepted
+    #[serde(skip)]
+    pub device_token: Option<String>,
+    /// Whether the guest's DataConnection presented `device_token`
+    #[serde(skip)]
+    pub verified: bool,
+}
+
+/// The parts of the state that differ per guest, sent to each guest only.
+/// The shared state carries no peer ids, so this is how a guest learns its
+/// own role and songs.
+#[derive(Debug, Clone, Serialize)]
+pub struct GuestView {
+    pub role: Role,
+    /// Let in past capacity and not yet given a role
+    pub overflow: bool,
+    /// Queued songs this guest added, which they may take back
+    #[serde(rename = "ownSongIds")]
+    pub own_song_ids: Vec<String>,
+    /// Queued songs this guest upvoted
+    #[serde(rename = "votedSongIds")]
+    pub voted_song_ids: Vec<String>,
+    /// Whether this guest voted to skip the song on stage
+    #[serde(rename = "votedSkip")]
+    pub voted_skip: bool,
+}
+
+/// What the host window forwards to guests: the shared public state, and
+/// each guest's own view keyed by their peer id
+#[derive(Debug, Clone, Serialize)]
+pub struct PublicBroadcast {
+    pub state: RoomState,
+    pub viewers: HashMap<String, GuestView>,
+}
+
+/// What a participant is allowed to do in the room. The host is whoever runs
+/// the app; guests get `Singer` until the host assigns them something else.
+/// See `ClientCommand::allowed_roles` for the permission table.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Host,
+    /// Trusted guest who runs the player and queue alongside the host (DJ)
+    CoHost,
+    /// Queues and performs songs
+    Singer,
+    /// Watches and votes, but cannot queue or control playback
+    Spectator,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Host => "host",
+            Role::CoHost => "cohost",
+            Role::Singer => "singer",
+            Role::Spectator => "spectator",
+        }
+    }
+}
+
+/// Role given to guests the host has not assigned one
+pub const DEFAULT_GUEST_ROLE: Role = Role::Singer;
+
+/// How a song left the stage
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlayOutcome {
+    /// Played through to the end
+    Completed,
+    /// Skipped before the end
+    Skipped,
+}
+
+impl PlayOutcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            PlayOutcome::Completed => "completed",
+            PlayOutcome::Skipped => "skipped",
+        }
+    }
+}
+
+/// One entry in the session's play history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayedSong {
+    #[serde(rename = "youtubeId")]
+    pub youtube_id: String,
+    pub title: String,
+    pub artist: String,
+    /// Everyone who performed it, lead singer first
+    pub singers: Vec<String>,
+    #[serde(rename = "startedAt")]
+    pub started_at: i64,
+    #[serde(rename = "endedAt")]
+    pub ended_at: i64,
+    pub outcome: PlayOutcome,
+}
+
+/// File format for exporting the play history
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryFormat {
+    Json,
+    Csv,
+}
+
+impl HistoryFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            HistoryFormat::Json => "json",
+            HistoryFormat::Csv => "csv",
+        }
+    }
+}
+
+/// Render the play history as a JSON document or a CSV sheet (one row per
+/// song, singers joined with `"; "`, times in RFC 3339 UTC).
+pub fn export_history(history: &[PlayedSong], format: HistoryFormat) -> Result<String, String> {
+    match format {
+        HistoryFormat::Json => serde_json::to_string_pretty(history)
+            .map_err(|e| format!("Failed to serialize: {}", e)),
+        HistoryFormat::Csv => {
+            let mut csv = String::from("started_at,ended_at,title,artist,singers,youtube_id,outcome\n");
+            for entry in history {
+                let row = [
+                    format_timestamp(entry.started_at),
+                    format_timestamp(entry.ended_at),
+                    csv_field(&entry.title),
+                    csv_field(&entry.artist),
+                    csv_field(&entry.singers.join("; ")),
+                    csv_field(&entry.youtube_id),
+                    entry.outcome.as_str().to_string(),
+                ];
+                csv.push_str(&row.join(","));
+                csv.push('\n');
+            }
+            Ok(csv)
+        }
+    }
+}
+
+fn format_timestamp(millis: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(millis)
+        .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_default()
+}
+
+/// Quote a CSV field if it contains a delimiter, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
 }
 
 /// Main room state (for Host Mode broadcasting)
@@ -451,8 +867,38 @@ pub struct RoomState {
     pub host_peer_id: String,
     #[serde(rename = "connectedClients")]
     pub connected_clients: Vec<ConnectedClient>,
+    /// Roles the host assigned, keyed by guest peer id. Guests not listed
+    /// have `DEFAULT_GUEST_ROLE`.
+    #[serde(default)]
+    pub roles: HashMap<String, Role>,
     pub player: PlayerState,
     pub queue: Vec<Song>,
+    #[serde(rename = "queueMode")]
+    pub queue_mode: QueueMode,
+    #[serde(rename = "queueLimits")]
+    pub queue_limits: QueueLimits,
+    /// `None` when the Auto-DJ is off
+    #[serde(rename = "autoDj", default)]
+    pub auto_dj: Option<AutoDjConfig>,
+    #[serde(rename = "skipPolicy", default)]
+    pub skip_policy: SkipPolicy,
+    #[serde(rename = "skipVotes", default)]
+    pub skip_votes: SkipVotes,
+    /// Time of each guest's last accepted addition, for the add cooldown
+    #[serde(skip)]
+    last_add_at: HashMap<String, i64>,
+    #[serde(default)]
+    pub history: QueueHistory,
+    /// Songs that have left the stage, fetched on demand rather than broadcast
+    #[serde(skip)]
+    play_history: Vec<PlayedSong>,
+    /// Id of the song on stage and when it started playing
+    #[serde(skip)]
+    stage_started: Option<(String, i64)>,
+    /// Roles guests had on the host this room was handed off from, by
+    /// lowercased display name, waiting for them to rejoin
+    #[serde(skip)]
+    handoff_roles: HashMap<String, Role>,
     pub playlists: Vec<PlaylistCollection>,
     #[serde(rename = "createdAt")]
     pub created_at: i64,
@@ -468,6 +914,7 @@ impl RoomState {
             room_id,
             host_peer_id,
             connected_clients: Vec::new(),
+            roles: HashMap::new(),
             player: PlayerState {
                 status: PlayerStatus::Idle,
                 current_song: None,
@@ -477,6 +924,16 @@ impl RoomState {
                 is_muted: false,
             },
             queue: Vec::new(),
+            queue_mode: QueueMode::default(),
+            queue_limits: QueueLimits::default(),
+            auto_dj: None,
+            skip_policy: SkipPolicy::default(),
+            skip_votes: SkipVotes { needed: 1, ..SkipVotes::default() },
+            last_add_at: HashMap::new(),
+            history: QueueHistory::default(),
+            play_history: Vec::new(),
+            stage_started: None,
+            handoff_roles: HashMap::new(),
             playlists,
             created_at: now,
             updated_at: now,
@@ -491,19 +948,262 @@ impl RoomState {
 
     /// Add a song to the queue
     pub fn add_song(&mut self, song: Song) {
+        self.push_undo(self.snapshot());
         if self.player.current_song.is_none() {
             self.player.current_song = Some(song);
             self.player.status = PlayerStatus::Loading;
             self.player.current_time = 0.0;
         } else {
-            self.queue.push(song);
+            // Songs from the room always go ahead of the Auto-DJ's
+            let pos = self.queue.iter().position(|s| s.auto_filled).unwrap_or(self.queue.len());
+            self.queue.insert(pos, song);
+            self.arrange_queue();
+        }
+        self.touch();
+    }
+
+    /// Check whether `guest_id` may queue a song right now.
+    ///
+    /// Called before fetching metadata so a refused guest does not cost a
+    /// network round-trip, and again by `add_guest_song` at insertion time.
+    pub fn check_add_limits(&self, guest_id: &str, now: i64) -> Result<(), QueueRejection> {
+        let limits = &self.queue_limits;
+
+        if let Some(limit) = limits.max_queue_length {
+            let queued = self.queue.iter().filter(|s| !s.auto_filled).count();
+            if queued >= limit {
+                return Err(QueueRejection::QueueFull { limit });
+            }
+        }
+
+        if let Some(limit) = limits.max_pending_per_singer {
+            let pending = self.queue.iter().filter(|s| s.owner_id.as_deref() == Some(guest_id)).count();
+            if pending >= limit {
+                return Err(QueueRejection::SingerQuota { limit });
+            }
+        }
+
+        if let (Some(secs), Some(last)) = (limits.add_cooldown_secs, self.last_add_at.get(guest_id)) {
+            let ready_at = last + (secs as i64) * 1000;
+            if now < ready_at {
+                return Err(QueueRejection::Cooldown { retry_after_ms: ready_at - now });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add a song on behalf of a guest, enforcing the queue limits
+    pub fn add_guest_song(&mut self, song: Song, guest_id: &str, now: i64) -> Result<(), QueueRejection> {
+        self.check_add_limits(guest_id, now)?;
+        self.last_add_at.insert(guest_id.to_string(), now);
+        self.add_song(Song { owner_id: Some(guest_id.to_string()), ..song });
+        Ok(())
+    }
+
+    /// Find a song in the queue or on stage by ID
+    fn find_song_mut(&mut self, song_id: &str) -> Option<&mut Song> {
+        if let Some(current) = self.player.current_song.as_mut().filter(|s| s.id == song_id) {
+            return Some(current);
         }
+        self.queue.iter_mut().find(|s| s.id == song_id)
+    }
+
+    /// Invite someone to sing `song_id` with its current singers: the guest
+    /// `client_id` when given, as display names are not unique, else `name`.
+    /// Guests may only invite someone in the room to a song they queued;
+    /// staff may also invite a name that is not.
+    pub fn invite_to_duet(&mut self, song_id: &str, name: &str, client_id: Option<&str>, actor: DuetActor) -> Result<(), String> {
+        let invited_by = match actor {
+            DuetActor::Staff(id) => id.and_then(|id| self.client_name(id)).unwrap_or("Host").to_string(),
+            DuetActor::Guest(id) => self.client_name(id).ok_or("Not connected")?.to_string(),
+        };
+        let (name, client_id) = match client_id {
+            Some(id) => {
+                let name = self.client_name(id).ok_or("That guest is not in the room")?;
+                (name.to_string(), Some(id.to_string()))
+            }
+            None if matches!(actor, DuetActor::Guest(_)) => {
+                return Err("Pick someone in the room to invite".to_string());
+            }
+            None => match name.trim() {
+                "" => return Err("Say who to invite".to_string()),
+                name => (name.to_string(), None),
+            },
+        };
+        let before = self.snapshot();
+        let song = self.find_song_mut(song_id).ok_or("Song not found")?;
+        if let DuetActor::Guest(id) = actor {
+            if song.owner_id.as_deref() != Some(id) {
+                return Err("Only the lead singer can invite others to this song".to_string());
+            }
+        }
+        let already_on = match client_id.as_deref() {
+            Some(id) => song.owner_id.as_deref() == Some(id)
+                || song.singers.iter().any(|s| s.client_id.as_deref() == Some(id)),
+            None => song.has_singer(&name),
+        };
+        if already_on {
+            return Err(format!("{} is already on this song", name));
+        }
+        song.singers.push(SongSinger {
+            name,
+            invited_by: Some(invited_by),
+            status: InviteStatus::Pending,
+            client_id,
+        });
+        self.push_undo(before);
+        self.touch();
+        Ok(())
+    }
+
+    /// Join a song as an extra singer. A guest accepts an invite made to
+    /// them; staff may add `name` whether invited or not.
+    pub fn join_duet(&mut self, song_id: &str, name: &str, actor: DuetActor) -> Result<(), String> {
+        let before = self.snapshot();
+        // Staff name who they add; it is only tied to a guest when no one
+        // else in the room goes by that name
+        let mut named = self.connected_clients.iter().filter(|c| c.display_name == name);
+        let client_id = match (named.next(), named.next()) {
+            (Some(client), None) => Some(client.id.clone()),
+            _ => None,
+        };
+        let song = self.find_song_mut(song_id).ok_or("Song not found")?;
+        match actor {
+            DuetActor::Guest(id) => {
+                let singer = song.singers.iter_mut()
+                    .find(|s| s.client_id.as_deref() == Some(id))
+                    .ok_or("You have not been invited to this song")?;
+                if singer.status == InviteStatus::Accepted {
+                    return Err("You have already joined this song".to_string());
+                }
+                singer.status = InviteStatus::Accepted;
+            }
+            DuetActor::Staff(_) => {
+                if song.added_by == name {
+                    return Err(format!("{} is already the lead singer on this song", name));
+                }
+                match song.singers.iter_mut().find(|s| s.name == name) {
+                    Some(singer) if singer.status == InviteStatus::Accepted => {
+                        return Err(format!("{} has already joined this song", name));
+                    }
+                    Some(singer) => singer.status = InviteStatus::Accepted,
+                    None => song.singers.push(SongSinger {
+                        name: name.to_string(),
+                        invited_by: None,
+                        status: InviteStatus::Accepted,
+                        client_id,
+                    }),
+                }
+            }
+        }
+        self.push_undo(before);
+        self.arrange_queue();
+        self.touch();
+        Ok(())
+    }
+
+    /// Take `name` off a song, or decline an invite to it. A guest can only
+    /// take themselves off, unless they are the song's lead singer. The lead
+    /// cannot leave; they remove the song instead.
+    pub fn leave_duet(&mut self, song_id: &str, name: &str, actor: DuetActor) -> Result<(), String> {
+        let before = self.snapshot();
+        let song = self.find_song_mut(song_id).ok_or("Song not found")?;
+        let count = song.singers.len();
+        match actor {
+            DuetActor::Guest(id) if song.owner_id.as_deref() != Some(id) => {
+                song.singers.retain(|s| s.client_id.as_deref() != Some(id));
+                if song.singers.len() == count {
+                    return Err("You are not on this song".to_string());
+                }
+            }
+            _ => {
+                if song.added_by == name {
+                    return Err("The lead singer cannot leave; remove the song instead".to_string());
+                }
+                song.singers.retain(|s| s.name != name);
+                if song.singers.len() == count {
+                    return Err(format!("{} is not on this song", name));
+                }
+            }
+        }
+        self.push_undo(before);
+        self.arrange_queue();
+        self.touch();
+        Ok(())
+    }
+
+    /// Replace the queue limits
+    pub fn set_queue_limits(&mut self, limits: QueueLimits) {
+        self.queue_limits = limits;
+        self.touch();
+    }
+
+    /// Upvote a queued song. Each voter counts once per song.
+    pub fn upvote(&mut self, song_id: &str, voter: &str) -> Result<(), String> {
+        let before = self.snapshot();
+        let song = self.queue.iter_mut().find(|s| s.id == song_id)
+            .ok_or_else(|| "Song not found in queue".to_string())?;
+        if song.voters.iter().any(|v| v == voter) {
+            return Err("You already voted for this song".to_string());
+        }
+        song.voters.push(voter.to_string());
+        self.push_undo(before);
+        self.arrange_queue();
+        self.touch();
+        Ok(())
+    }
+
+    /// Take back an upvote
+    pub fn unvote(&mut self, song_id: &str, voter: &str) -> Result<(), String> {
+        let before = self.snapshot();
+        let song = self.queue.iter_mut().find(|s| s.id == song_id)
+            .ok_or_else(|| "Song not found in queue".to_string())?;
+        let pos = song.voters.iter().position(|v| v == voter)
+            .ok_or_else(|| "You have not voted for this song".to_string())?;
+        song.voters.remove(pos);
+        self.push_undo(before);
+        self.arrange_queue();
+        self.touch();
+        Ok(())
+    }
+
+    /// Switch how upcoming songs are ordered, re-arranging the current queue
+    pub fn set_queue_mode(&mut self, mode: QueueMode) {
+        self.push_undo(self.snapshot());
+        self.queue_mode = mode;
+        self.arrange_queue();
         self.touch();
     }
 
+    /// Re-apply the active queue mode to the upcoming songs.
+    ///
+    /// FIFO leaves the queue alone, so manual reorders stick. Rotation rebuilds
+    /// the order from scratch on every add, and Votes re-sorts on every add or
+    /// vote; a manual move in either mode therefore only lasts until the next
+    /// change. Auto-DJ songs are left out and stay at the back.
+    fn arrange_queue(&mut self) {
+        if self.queue_mode == QueueMode::Fifo {
+            return;
+        }
+        let (mut queue, mut auto_filled): (Vec<Song>, Vec<Song>) =
+            std::mem::take(&mut self.queue).into_iter().partition(|s| !s.auto_filled);
+        if self.queue_mode == QueueMode::Rotation {
+            let on_stage: Vec<String> = self.player.current_song.as_ref()
+                .map(|s| s.performers().into_iter().map(str::to_string).collect())
+                .unwrap_or_default();
+            queue = round_robin(queue, &on_stage);
+        } else {
+            queue.sort_by_key(|s| (std::cmp::Reverse(s.voters.len()), s.added_at));
+        }
+        queue.append(&mut auto_filled);
+        self.queue = queue;
+    }
+
     /// Remove a song from the queue by ID
     pub fn remove_song(&mut self, song_id: &str) -> bool {
         if let Some(pos) = self.queue.iter().position(|s| s.id == song_id) {
+            self.push_undo(self.snapshot());
             self.queue.remove(pos);
             self.touch();
             true
@@ -516,6 +1216,7 @@ impl RoomState {
     pub fn reorder_queue(&mut self, song_id: &str, new_index: usize) -> bool {
         if let Some(current_pos) = self.queue.iter().position(|s| s.id == song_id) {
             if new_index < self.queue.len() {
+                self.push_undo(self.snapshot());
                 let song = self.queue.remove(current_pos);
                 self.queue.insert(new_index, song);
                 self.touch();
@@ -529,6 +1230,7 @@ impl RoomState {
     pub fn move_song_up(&mut self, song_id: &str) -> bool {
         if let Some(pos) = self.queue.iter().position(|s| s.id == song_id) {
             if pos > 0 {
+                self.push_undo(self.snapshot());
                 self.queue.swap(pos, pos - 1);
                 self.touch();
                 return true;
@@ -541,6 +1243,7 @@ impl RoomState {
     pub fn move_song_down(&mut self, song_id: &str) -> bool {
         if let Some(pos) = self.queue.iter().position(|s| s.id == song_id) {
             if pos < self.queue.len() - 1 {
+                self.push_undo(self.snapshot());
                 self.queue.swap(pos, pos + 1);
                 self.touch();
                 return true;
@@ -553,6 +1256,7 @@ impl RoomState {
     pub fn move_song_to_top(&mut self, song_id: &str) -> bool {
         if let Some(pos) = self.queue.iter().position(|s| s.id == song_id) {
             if pos > 0 {
+                self.push_undo(self.snapshot());
                 let song = self.queue.remove(pos);
                 self.queue.insert(0, song);
                 self.touch();
@@ -566,6 +1270,7 @@ impl RoomState {
     pub fn move_song_to_bottom(&mut self, song_id: &str) -> bool {
         if let Some(pos) = self.queue.iter().position(|s| s.id == song_id) {
             if pos < self.queue.len() - 1 {
+                self.push_undo(self.snapshot());
                 let song = self.queue.remove(pos);
                 self.queue.push(song);
                 self.touch();
@@ -578,6 +1283,9 @@ impl RoomState {
     /// Update player state
     pub fn update_player(&mut self, status: Option<PlayerStatus>, current_time: Option<f64>, duration: Option<f64>) {
         if let Some(s) = status {
+            if matches!(s, PlayerStatus::Playing) {
+                self.mark_started();
+            }
             self.player.status = s;
         }
         if let Some(t) = current_time {
@@ -603,6 +1311,21 @@ impl RoomState {
 
     /// Skip to next song
     pub fn skip_song(&mut self) {
+        self.record_played(PlayOutcome::Skipped);
+        self.advance();
+    }
+
+    /// The song on stage played to the end; move on to the next one
+    pub fn finish_song(&mut self) {
+        self.record_played(PlayOutcome::Completed);
+        self.advance();
+    }
+
+    /// Put the next queued song on stage, or go idle if there is none
+    fn advance(&mut self) {
+        if self.player.current_song.is_some() || !self.queue.is_empty() {
+            self.push_undo(self.snapshot());
+        }
         if !self.queue.is_empty() {
             let next_song = self.queue.remove(0);
             self.player.current_song = Some(next_song);
@@ -613,15 +1336,94 @@ impl RoomState {
             self.player.current_time = 0.0;
             self.player.status = PlayerStatus::Idle;
         }
+        self.auto_fill();
+        self.touch();
+    }
+
+    /// Turn the Auto-DJ on or off. Turning it on in a silent room starts
+    /// playing straight away; turning it off drops its queued songs but lets
+    /// the one on stage finish.
+    pub fn set_auto_dj(&mut self, config: Option<AutoDjConfig>) {
+        let before = self.snapshot();
+        let count = self.queue.len();
+        self.auto_dj = config;
+        // Songs lined up under the previous setting make way for the new one
+        self.queue.retain(|s| !s.auto_filled);
+        let mut changed = self.queue.len() != count;
+        if self.auto_dj.is_some() {
+            changed |= self.auto_fill();
+        }
+        if changed {
+            self.push_undo(before);
+        }
         self.touch();
     }
 
+    /// Keep a song on stage and one lined up while the Auto-DJ is on and the
+    /// room has nothing queued. Returns whether anything was added.
+    fn auto_fill(&mut self) -> bool {
+        let mut filled = false;
+        if self.player.current_song.is_none() && self.queue.is_empty() {
+            let Some(song) = self.next_auto_song() else {
+                return false;
+            };
+            self.player.current_song = Some(song);
+            self.player.current_time = 0.0;
+            self.player.status = PlayerStatus::Loading;
+            filled = true;
+        }
+        if self.queue.is_empty() {
+            if let Some(song) = self.next_auto_song() {
+                self.queue.push(song);
+                filled = true;
+            }
+        }
+        filled
+    }
+
+    /// Next song from the Auto-DJ's collection that has not been played,
+    /// queued or put on stage this session
+    fn next_auto_song(&self) -> Option<Song> {
+        use rand::seq::SliceRandom;
+
+        let config = self.auto_dj.as_ref()?;
+        let collection = self.playlists.iter().find(|c| c.id == config.collection_id)?;
+        let used: Vec<&str> = self.play_history.iter().map(|p| p.youtube_id.as_str())
+            .chain(self.player.current_song.iter().map(|s| s.youtube_id.as_str()))
+            .chain(self.queue.iter().map(|s| s.youtube_id.as_str()))
+            .collect();
+        let candidates: Vec<&Song> = collection.songs.iter()
+            .filter(|s| !used.contains(&s.youtube_id.as_str()))
+            .collect();
+        let pick = match config.order {
+            AutoDjOrder::Sequential => candidates.first(),
+            AutoDjOrder::Shuffle => candidates.choose(&mut rand::thread_rng()),
+        }?;
+
+        Some(Song {
+            id: uuid::Uuid::new_v4().to_string(),
+            youtube_id: pick.youtube_id.clone(),
+            title: pick.title.clone(),
+            artist: pick.artist.clone(),
+            duration: pick.duration,
+            thumbnail_url: pick.thumbnail_url.clone(),
+            added_by: AUTO_DJ_NAME.to_string(),
+            added_at: chrono::Utc::now().timestamp_millis(),
+            singers: Vec::new(),
+            voters: Vec::new(),
+            votes: 0,
+            auto_filled: true,
+            owner_id: None,
+        })
+    }
+
     /// Play current song
     pub fn play(&mut self) {
         if self.player.current_song.is_some() {
             self.player.status = PlayerStatus::Playing;
             self.touch();
         } else if !self.queue.is_empty() {
+            self.push_undo(self.snapshot());
             let next_song = self.queue.remove(0);
             self.player.current_song = Some(next_song);
             self.player.status = PlayerStatus::Loading;
@@ -641,63 +1443,1685 @@ impl RoomState {
         self.touch();
     }
 
-    /// Add a connected client
-    #[allow(dead_code)]
+    /// Capture the queue and the song on stage, for the undo history
+    fn snapshot(&self) -> QueueSnapshot {
+        QueueSnapshot {
+            queue: self.queue.clone(),
+            current_song: self.player.current_song.clone(),
+        }
+    }
+
+    /// Record the state from before a queue change. Any new change discards
+    /// the redo stack, as in every editor.
+    fn push_undo(&mut self, before: QueueSnapshot) {
+        let history = &mut self.history;
+        history.undo.push_back(Arc::new(before));
+        if history.undo.len() > MAX_UNDO_HISTORY {
+            history.undo.pop_front();
+        }
+        history.redo.clear();
+    }
+
+    /// Put back a snapshot's queue and song on stage. If that changes the
+    /// song on stage, the player reloads it from the start.
+    fn restore(&mut self, snapshot: &QueueSnapshot) {
+        let current_id = self.player.current_song.as_ref().map(|s| s.id.as_str());
+        let restored_id = snapshot.current_song.as_ref().map(|s| s.id.as_str());
+        if current_id != restored_id {
+            self.player.current_time = 0.0;
+            self.player.status = if snapshot.current_song.is_some() {
+                PlayerStatus::Loading
+            } else {
+                PlayerStatus::Idle
+            };
+        }
+        self.queue = snapshot.queue.clone();
+        self.player.current_song = snapshot.current_song.clone();
+        self.touch();
+    }
+
+    /// Revert the most recent queue change. Returns false if there is none.
+    pub fn undo(&mut self) -> bool {
+        let Some(previous) = self.history.undo.pop_back() else {
+            return false;
+        };
+        let now = Arc::new(self.snapshot());
+        self.history.redo.push(now);
+        self.restore(&previous);
+        true
+    }
+
+    /// Re-apply the most recently undone change. Returns false if there is none.
+    pub fn redo(&mut self) -> bool {
+        let Some(next) = self.history.redo.pop() else {
+            return false;
+        };
+        let now = Arc::new(self.snapshot());
+        self.history.undo.push_back(now);
+        self.restore(&next);
+        true
+    }
+
+    /// Add a connected client, replacing an earlier entry with the same ID
+    /// (a guest reclaiming its identity after a reconnect)
     pub fn add_client(&mut self, client: ConnectedClient) {
+        if let Some(role) = self.handoff_roles.remove(&client.display_name.trim().to_lowercase()) {
+            if role != DEFAULT_GUEST_ROLE {
+                self.roles.insert(client.id.clone(), role);
+            }
+        }
+        self.connected_clients.retain(|c| c.id != client.id);
         self.connected_clients.push(client);
         self.touch();
     }
 
     /// Remove a connected client by ID
-    #[allow(dead_code)]
     pub fn remove_client(&mut self, client_id: &str) {
         self.connected_clients.retain(|c| c.id != client_id);
         self.touch();
     }
 
-    /// Get a copy of the state with only public playlists (for broadcasting to remote clients)
-    pub fn public_state(&self) -> RoomState {
-        let mut state = self.clone();
-        state.playlists.retain(|c| c.visibility == CollectionVisibility::Public);
-        state
+    /// Check the device token a guest's DataConnection presented against the
+    /// one signaling issued them, and remember the outcome
+    pub fn verify_client(&mut self, client_id: &str, device_token: &str) -> bool {
+        let Some(client) = self.connected_clients.iter_mut().find(|c| c.id == client_id) else {
+            return false;
+        };
+        client.verified = client.device_token.as_deref() == Some(device_token);
+        client.verified
     }
 
-    /// Update the timestamp
-    fn touch(&mut self) {
-        self.updated_at = chrono::Utc::now().timestamp_millis();
+    /// Whether a guest's DataConnection proved it belongs to them
+    pub fn is_verified(&self, client_id: &str) -> bool {
+        self.connected_clients.iter().any(|c| c.id == client_id && c.verified)
     }
-}
 
-/// Thread-safe room state manager
-pub struct RoomStateManager {
-    state: Arc<RwLock<RoomState>>,
-}
+    /// Display name of a connected client
+    pub fn client_name(&self, client_id: &str) -> Option<&str> {
+        self.connected_clients.iter()
+            .find(|c| c.id == client_id)
+            .map(|c| c.display_name.as_str())
+    }
 
-impl RoomStateManager {
-    /// Create a new room state manager
-    pub fn new(room_id: String, host_peer_id: String, playlists: Vec<PlaylistCollection>) -> Self {
-        Self {
-            state: Arc::new(RwLock::new(RoomState::new(room_id, host_peer_id, playlists))),
+    /// Change a connected client's display name
+    pub fn rename_client(&mut self, client_id: &str, name: &str) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Name cannot be empty".to_string());
         }
+        let client = self.connected_clients.iter_mut()
+            .find(|c| c.id == client_id)
+            .ok_or("Not connected")?;
+        client.display_name = name.to_string();
+        self.touch();
+        Ok(())
     }
 
-    /// Get a write lock on the state
-    pub fn write(&self) -> parking_lot::RwLockWriteGuard<'_, RoomState> {
-        self.state.write()
+    /// Role of a command sender: `None` is the host, anyone else is a guest
+    pub fn role_of(&self, sender_id: Option<&str>) -> Role {
+        match sender_id {
+            None => Role::Host,
+            Some(id) if self.is_overflow(id) => Role::Spectator,
+            Some(id) => self.roles.get(id).copied().unwrap_or(DEFAULT_GUEST_ROLE),
+        }
     }
 
-    /// Clone the current state (full, including personal collections — for host UI)
-    /// Clone only the player slice, for high-frequency progress broadcasts.
-    pub fn clone_player(&self) -> PlayerState {
-        self.state.read().player.clone()
+    /// Whether a guest joining now would be past `capacity` and so only
+    /// spectate. Overflow spectators hold no seat, and a guest the host gave
+    /// a role has one. The joining guest's own earlier connection is not
+    /// counted against them.
+    pub fn is_at_capacity(&self, capacity: usize, joining_id: &str) -> bool {
+        let seated = self.connected_clients.iter()
+            .filter(|c| !c.overflow && c.id != joining_id)
+            .count();
+        seated >= capacity
     }
 
-    pub fn clone_state(&self) -> RoomState {
-        self.state.read().clone()
+    /// Whether a guest came in past capacity and has not been given a role since
+    pub fn is_overflow(&self, client_id: &str) -> bool {
+        self.connected_clients.iter().any(|c| c.id == client_id && c.overflow)
     }
 
-    /// Clone a filtered state (public only — for broadcast to remote clients)
-    pub fn clone_public_state(&self) -> RoomState {
-        self.state.read().public_state()
+    /// Assign a guest's role. There is only one host, so guests cannot be
+    /// given that role. Giving an overflow spectator any role seats them.
+    pub fn set_guest_role(&mut self, guest_id: &str, role: Role) -> Result<(), String> {
+        if role == Role::Host {
+            return Err("Guests cannot be made host".to_string());
+        }
+        if let Some(client) = self.connected_clients.iter_mut().find(|c| c.id == guest_id) {
+            client.overflow = false;
+        }
+        if role == DEFAULT_GUEST_ROLE {
+            self.roles.remove(guest_id);
+        } else {
+            self.roles.insert(guest_id.to_string(), role);
+        }
+        self.touch();
+        Ok(())
+    }
+
+    /// Whether a guest queued this song themselves
+    pub fn is_song_owner(&self, song_id: &str, guest_id: &str) -> bool {
+        self.queue
+            .iter()
+            .any(|s| s.id == song_id && s.owner_id.as_deref() == Some(guest_id))
+    }
+
+    /// Get a copy of the state with only public playlists (for broadcasting to remote clients).
+    /// Peer ids are left out (roles, clients, song owners) and votes reduced
+    /// to counts; each guest's own part is in `guest_view`.
+    pub fn public_state(&self) -> RoomState {
+        let mut state = self.clone();
+        state.playlists.retain(|c| c.visibility == CollectionVisibility::Public);
+        state.roles.clear();
+        state.connected_clients.clear();
+        state.queue.iter_mut().for_each(Song::make_public);
+        state.player.current_song.iter_mut().for_each(Song::make_public);
+        state.skip_votes.votes = state.skip_votes.voters.len();
+        state.skip_votes.voters.clear();
+        state
+    }
+
+    /// What one guest is told about themselves alongside the public state
+    pub fn guest_view(&self, guest_id: &str) -> GuestView {
+        GuestView {
+            role: self.role_of(Some(guest_id)),
+            overflow: self.is_overflow(guest_id),
+            own_song_ids: self
+                .queue
+                .iter()
+                .filter(|s| s.owner_id.as_deref() == Some(guest_id))
+                .map(|s| s.id.clone())
+                .collect(),
+            voted_song_ids: self
+                .queue
+                .iter()
+                .filter(|s| s.voters.iter().any(|v| v == guest_id))
+                .map(|s| s.id.clone())
+                .collect(),
+            voted_skip: self.skip_votes.voters.iter().any(|v| v == guest_id),
+        }
+    }
+
+    /// The public state plus each connected guest's own view
+    pub fn public_broadcast(&self) -> PublicBroadcast {
+        PublicBroadcast {
+            state: self.public_state(),
+            viewers: self
+                .connected_clients
+                .iter()
+                .map(|c| (c.id.clone(), self.guest_view(&c.id)))
+                .collect(),
+        }
+    }
+
+    /// Update the timestamp
+    fn touch(&mut self) {
+        self.updated_at = chrono::Utc::now().timestamp_millis();
+        self.sync_skip_votes();
+    }
+
+    /// Drop skip votes left over from a previous song or from guests who
+    /// left, and recount how many votes are needed for the guests connected
+    /// now. Overflow spectators cannot vote, so they do not count.
+    fn sync_skip_votes(&mut self) {
+        let current_id = self.player.current_song.as_ref().map(|s| s.id.clone());
+        if self.skip_votes.song_id != current_id {
+            self.skip_votes.song_id = current_id;
+            self.skip_votes.voters.clear();
+        }
+        let clients = &self.connected_clients;
+        self.skip_votes.voters.retain(|v| clients.iter().any(|c| &c.id == v && !c.overflow));
+        let threshold = self.skip_policy.vote_threshold.clamp(0.0, 1.0);
+        let guests = clients.iter().filter(|c| !c.overflow).count() as f64;
+        self.skip_votes.needed = ((threshold * guests).ceil() as usize).max(1);
+    }
+
+    /// Vote to skip the song on stage. Skips once enough guests agree and
+    /// returns whether it did.
+    pub fn vote_skip(&mut self, voter: &str) -> Result<bool, String> {
+        if self.player.current_song.is_none() {
+            return Err("Nothing is playing".to_string());
+        }
+        self.sync_skip_votes();
+        if !self.connected_clients.iter().any(|c| c.id == voter && !c.overflow) {
+            return Err("Only guests in the room can vote to skip".to_string());
+        }
+        if self.skip_votes.voters.iter().any(|v| v == voter) {
+            return Err("You already voted to skip this song".to_string());
+        }
+        self.skip_votes.voters.push(voter.to_string());
+        if self.skip_votes.voters.len() >= self.skip_votes.needed {
+            self.skip_song();
+            return Ok(true);
+        }
+        self.touch();
+        Ok(false)
+    }
+
+    /// Replace the host's skip settings
+    pub fn set_skip_policy(&mut self, policy: SkipPolicy) {
+        self.skip_policy = policy;
+        self.touch();
+    }
+
+    /// Note when the song on stage first starts playing, for the play history
+    fn mark_started(&mut self) {
+        let Some(song) = &self.player.current_song else {
+            return;
+        };
+        if self.stage_started.as_ref().map(|(id, _)| id) != Some(&song.id) {
+            self.stage_started = Some((song.id.clone(), chrono::Utc::now().timestamp_millis()));
+        }
+    }
+
+    /// Add the song leaving the stage to the play history
+    fn record_played(&mut self, outcome: PlayOutcome) {
+        let Some(song) = &self.player.current_song else {
+            return;
+        };
+        let ended_at = chrono::Utc::now().timestamp_millis();
+        // A song skipped before it ever played started and ended at once
+        let started_at = match self.stage_started.take() {
+            Some((id, at)) if id == song.id => at,
+            _ => ended_at,
+        };
+        self.play_history.push(PlayedSong {
+            youtube_id: song.youtube_id.clone(),
+            title: song.title.clone(),
+            artist: song.artist.clone(),
+            singers: song.performers().into_iter().map(String::from).collect(),
+            started_at,
+            ended_at,
+            outcome,
+        });
+    }
+
+    /// Songs that have left the stage this session, oldest first
+    pub fn play_history(&self) -> &[PlayedSong] {
+        &self.play_history
+    }
+
+    /// Capture the queue and player position for crash recovery
+    pub fn session_snapshot(&self) -> SessionSnapshot {
+        SessionSnapshot {
+            queue: self.queue.clone(),
+            current_song: self.player.current_song.clone(),
+            current_time: self.player.current_time,
+            queue_mode: self.queue_mode,
+            queue_limits: self.queue_limits.clone(),
+            auto_dj: self.auto_dj.clone(),
+            history: self.play_history.clone(),
+            saved_at: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+
+    /// Everything another host needs to carry on with this room
+    pub fn handoff_package(&self) -> HandoffPackage {
+        HandoffPackage {
+            session: self.session_snapshot(),
+            skip_policy: self.skip_policy.clone(),
+            roster: self.connected_clients.iter()
+                .map(|c| HandoffGuest {
+                    display_name: c.display_name.clone(),
+                    role: self.roles.get(&c.id).copied().unwrap_or(DEFAULT_GUEST_ROLE),
+                })
+                .collect(),
+        }
+    }
+
+    /// Take over a room handed off by another host. Guests come back with new
+    /// IDs, so their roles wait under their display names until they rejoin.
+    /// Anyone can type a name, so only roles that take something away come
+    /// back that way; the new host grants co-hosts again themselves.
+    pub fn import_handoff(&mut self, package: HandoffPackage) {
+        self.restore_session(package.session);
+        self.skip_policy = package.skip_policy;
+        self.handoff_roles = package.roster.into_iter()
+            .filter(|g| g.role == Role::Spectator)
+            .map(|g| (g.display_name.trim().to_lowercase(), g.role))
+            .collect();
+    }
+
+    /// Bring back a session saved by a previous run.
+    ///
+    /// Songs added since start-up are kept behind the restored queue. If
+    /// something is already playing, the restored song goes to the front of
+    /// the queue instead of interrupting it.
+    pub fn restore_session(&mut self, session: SessionSnapshot) {
+        let before = self.snapshot();
+        let mut queue = session.queue;
+        match session.current_song {
+            Some(song) if self.player.current_song.is_none() => {
+                self.player.duration = song.duration as f64;
+                self.player.current_song = Some(song);
+                self.player.current_time = session.current_time;
+                self.player.status = PlayerStatus::Loading;
+            }
+            Some(song) => queue.insert(0, song),
+            None => {}
+        }
+        queue.append(&mut self.queue);
+        self.queue = queue;
+        self.queue_mode = session.queue_mode;
+        self.queue_limits = session.queue_limits;
+        self.auto_dj = session.auto_dj;
+        let mut history = session.history;
+        history.append(&mut self.play_history);
+        self.play_history = history;
+        self.arrange_queue();
+        self.push_undo(before);
+        self.touch();
+    }
+}
+
+/// Interleave songs so each singer gets one turn per round.
+///
+/// Songs are picked one at a time. Each lead singer offers their earliest
+/// remaining song, and the song whose performers have had the fewest turns
+/// goes next. Ties go to whoever sang least recently, then to whoever queued
+/// first. Every accepted duet partner is credited with the turn, so joining a
+/// duet counts against your place in the rotation just like leading one.
+///
+/// Each singer's own songs keep their relative order. `on_stage` are the
+/// performers of the song playing now: they count as having just sung, so
+/// they do not go again straight away because their next song was queued early.
+fn round_robin(queue: Vec<Song>, on_stage: &[String]) -> Vec<Song> {
+    // Last turn per singer: absent = never sang, -1 = singing right now,
+    // n = the n-th song of the arranged queue.
+    let mut turns: HashMap<String, usize> = HashMap::new();
+    let mut last_turn: HashMap<String, i64> = HashMap::new();
+    for name in on_stage {
+        *turns.entry(name.clone()).or_default() += 1;
+        last_turn.insert(name.clone(), -1);
+    }
+
+    let mut order: Vec<String> = Vec::new();
+    let mut by_singer: HashMap<String, VecDeque<Song>> = HashMap::new();
+    for song in queue {
+        let key = song.singer_key().to_string();
+        if !by_singer.contains_key(&key) {
+            order.push(key.clone());
+        }
+        by_singer.entry(key).or_default().push_back(song);
+    }
+
+    let mut arranged: Vec<Song> = Vec::new();
+    loop {
+        let next = order.iter()
+            .enumerate()
+            .filter_map(|(appearance, lead)| {
+                let song = by_singer.get(lead)?.front()?;
+                let performers = song.performers();
+                let most_turns = performers.iter()
+                    .map(|p| turns.get(*p).copied().unwrap_or(0))
+                    .max()
+                    .unwrap_or(0);
+                let latest_turn = performers.iter()
+                    .map(|p| last_turn.get(*p).copied().unwrap_or(i64::MIN))
+                    .max()
+                    .unwrap_or(i64::MIN);
+                Some(((most_turns, latest_turn, appearance), lead))
+            })
+            .min_by_key(|(rank, _)| *rank)
+            .map(|(_, lead)| lead.clone());
+
+        let Some(lead) = next else { break };
+        let Some(song) = by_singer.get_mut(&lead).and_then(|q| q.pop_front()) else { break };
+        let position = arranged.len() as i64;
+        for performer in song.performers() {
+            *turns.entry(performer.to_string()).or_default() += 1;
+            last_turn.insert(performer.to_string(), position);
+        }
+        arranged.push(song);
+    }
+    arranged
+}
+
+/// Thread-safe room state manager
+#[derive(Clone)]
+pub struct RoomStateManager {
+    state: Arc<RwLock<RoomState>>,
+}
+
+impl RoomStateManager {
+    /// Create a new room state manager
+    pub fn new(room_id: String, host_peer_id: String, playlists: Vec<PlaylistCollection>) -> Self {
+        Self {
+            state: Arc::new(RwLock::new(RoomState::new(room_id, host_peer_id, playlists))),
+        }
+    }
+
+    /// Get a read lock on the state
+    pub fn read(&self) -> parking_lot::RwLockReadGuard<'_, RoomState> {
+        self.state.read()
+    }
+
+    /// Get a write lock on the state
+    pub fn write(&self) -> parking_lot::RwLockWriteGuard<'_, RoomState> {
+        self.state.write()
+    }
+
+    /// Clone only the player slice, for high-frequency progress broadcasts to
+    /// guests; the song on stage is stripped like in `public_state`.
+    pub fn clone_public_player(&self) -> PlayerState {
+        let mut player = self.state.read().player.clone();
+        player.current_song.iter_mut().for_each(Song::make_public);
+        player
+    }
+
+    /// Clone the current state (full, including personal collections — for host UI)
+    pub fn clone_state(&self) -> RoomState {
+        self.state.read().clone()
+    }
+
+    /// Clone the session's play history
+    pub fn clone_play_history(&self) -> Vec<PlayedSong> {
+        self.state.read().play_history().to_vec()
+    }
+
+    pub fn handoff_package(&self) -> HandoffPackage {
+        self.state.read().handoff_package()
+    }
+
+    /// Clone a filtered state (public only — for broadcast to remote clients)
+    /// with each connected guest's own view
+    pub fn clone_public_broadcast(&self) -> PublicBroadcast {
+        self.state.read().public_broadcast()
+    }
+}
+
+// ============================================================
+// RoomRegistry — every room this process hosts
+// ============================================================
+
+/// A room and the host window driving it
+#[derive(Clone)]
+pub struct HostedRoom {
+    pub state: RoomStateManager,
+    /// Label of the webview window that plays this room and relays to its
+    /// guests; the room's state events go to it alone
+    pub window: String,
+}
+
+/// Every room this process hosts, e.g. a main stage and a side room, by room
+/// ID. Each has its own queue, player and guests; the playlist library is
+/// shared. Rooms are kept in the order they opened, and the first is the main
+/// room, the one `SessionStore` snapshots.
+pub struct RoomRegistry {
+    rooms: Arc<RwLock<Vec<(String, HostedRoom)>>>,
+}
+
+impl RoomRegistry {
+    pub fn new() -> Self {
+        Self {
+            rooms: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    /// Open a room hosted by `window`, or hand back the one already open under
+    /// `room_id` (a host window reloading). A reopened room moves to `window`.
+    pub fn open(&self, room_id: &str, window: &str, playlists: Vec<PlaylistCollection>) -> RoomStateManager {
+        let mut rooms = self.rooms.write();
+        if let Some((_, hosted)) = rooms.iter_mut().find(|(id, _)| id == room_id) {
+            hosted.window = window.to_string();
+            return hosted.state.clone();
+        }
+        let state = RoomStateManager::new(room_id.to_string(), uuid::Uuid::new_v4().to_string(), playlists);
+        rooms.push((room_id.to_string(), HostedRoom { state: state.clone(), window: window.to_string() }));
+        state
+    }
+
+    pub fn get(&self, room_id: &str) -> Option<HostedRoom> {
+        self.rooms.read().iter()
+            .find(|(id, _)| id == room_id)
+            .map(|(_, hosted)| hosted.clone())
+    }
+
+    /// The room opened first, if it is still open
+    pub fn main(&self) -> Option<RoomStateManager> {
+        self.rooms.read().first().map(|(_, hosted)| hosted.state.clone())
+    }
+
+    pub fn is_main(&self, room_id: &str) -> bool {
+        self.rooms.read().first().is_some_and(|(id, _)| id == room_id)
+    }
+
+    pub fn close(&self, room_id: &str) -> bool {
+        let mut rooms = self.rooms.write();
+        let before = rooms.len();
+        rooms.retain(|(id, _)| id != room_id);
+        rooms.len() != before
+    }
+
+    /// Close whatever rooms a window was hosting, once the window is gone
+    pub fn close_window(&self, window: &str) -> Vec<String> {
+        let mut rooms = self.rooms.write();
+        let (closed, open): (Vec<_>, Vec<_>) = rooms.drain(..).partition(|(_, hosted)| hosted.window == window);
+        *rooms = open;
+        closed.into_iter().map(|(id, _)| id).collect()
+    }
+
+    /// Push the shared playlist library into every room
+    pub fn sync_playlists(&self, playlists: &[PlaylistCollection]) {
+        for (_, hosted) in self.rooms.read().iter() {
+            hosted.state.write().sync_playlists(playlists.to_vec());
+        }
+    }
+}
+
+// ============================================================
+// SessionStore — crash recovery for the live room
+// ============================================================
+
+/// The part of a live room worth surviving a crash: what is queued, what is
+/// playing and how far into it the player got.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    pub queue: Vec<Song>,
+    #[serde(rename = "currentSong")]
+    pub current_song: Option<Song>,
+    #[serde(rename = "currentTime")]
+    pub current_time: f64,
+    #[serde(rename = "queueMode", default)]
+    pub queue_mode: QueueMode,
+    #[serde(rename = "queueLimits", default)]
+    pub queue_limits: QueueLimits,
+    #[serde(rename = "autoDj", default)]
+    pub auto_dj: Option<AutoDjConfig>,
+    #[serde(default)]
+    pub history: Vec<PlayedSong>,
+    #[serde(rename = "savedAt")]
+    pub saved_at: i64,
+}
+
+impl SessionSnapshot {
+    /// Nothing queued and nothing playing
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty() && self.current_song.is_none()
+    }
+
+    /// Short description shown when offering to restore the session
+    pub fn summary(&self) -> RestorableSession {
+        RestorableSession {
+            saved_at: self.saved_at,
+            queue_length: self.queue.len(),
+            current_song: self.current_song.as_ref().map(|s| s.title.clone()),
+        }
+    }
+}
+
+/// A room moving to another host: its session plus who was in it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandoffPackage {
+    pub session: SessionSnapshot,
+    #[serde(rename = "skipPolicy", default)]
+    pub skip_policy: SkipPolicy,
+    #[serde(default)]
+    pub roster: Vec<HandoffGuest>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandoffGuest {
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    pub role: Role,
+}
+
+/// What `create_room` reports about a session left over from the last run
+#[derive(Debug, Clone, Serialize)]
+pub struct RestorableSession {
+    #[serde(rename = "savedAt")]
+    pub saved_at: i64,
+    #[serde(rename = "queueLength")]
+    pub queue_length: usize,
+    /// Title of the song that was playing, if any
+    #[serde(rename = "currentSong")]
+    pub current_song: Option<String>,
+}
+
+/// Keeps `session.json` (next to `playlists.json`) in step with the live room.
+///
+/// A session found at startup is held as `pending` until the host restores or
+/// discards it. Until this run has something of its own to save, the file is
+/// left alone, so crashing again before answering does not lose it.
+pub struct SessionStore {
+    path: Arc<RwLock<Option<PathBuf>>>,
+    pending: Arc<RwLock<Option<SessionSnapshot>>>,
+    /// `updated_at` of the room when it was last synced
+    last_synced: Arc<RwLock<Option<i64>>>,
+    /// Whether this run has written the file yet
+    written: Arc<RwLock<bool>>,
+}
+
+impl SessionStore {
+    pub fn new() -> Self {
+        Self {
+            path: Arc::new(RwLock::new(None)),
+            pending: Arc::new(RwLock::new(None)),
+            last_synced: Arc::new(RwLock::new(None)),
+            written: Arc::new(RwLock::new(false)),
+        }
+    }
+
+    /// Point the store at the app data dir and pick up any session left there
+    pub fn initialize(&self, app_data_dir: PathBuf) {
+        let mut path = app_data_dir;
+        let _ = fs::create_dir_all(&path);
+        path.push("session.json");
+
+        let previous = load_session_from_file(&path).filter(|s| !s.is_empty());
+        if let Some(session) = &previous {
+            log::info!(
+                "Found previous session from {} ({} queued) in {:?}",
+                session.saved_at, session.queue.len(), path
+            );
+        }
+
+        *self.path.write() = Some(path);
+        *self.pending.write() = previous;
+    }
+
+    /// The previous session, if the host has not restored or discarded it yet
+    pub fn pending(&self) -> Option<RestorableSession> {
+        self.pending.read().as_ref().map(SessionSnapshot::summary)
+    }
+
+    /// Hand over the previous session for restoring
+    pub fn take_pending(&self) -> Option<SessionSnapshot> {
+        self.pending.write().take()
+    }
+
+    /// Forget the previous session, removing it from disk unless this run has
+    /// already replaced it.
+    pub fn discard_pending(&self) {
+        if self.pending.write().take().is_some() && !*self.written.read() {
+            self.clear();
+        }
+    }
+
+    /// Save the room if it changed since the last sync.
+    ///
+    /// An empty room only clears the file once this run has written it;
+    /// before that the file may still hold the pending session.
+    pub fn sync(&self, room: &RoomStateManager) {
+        let (updated_at, snapshot) = {
+            let state = room.state.read();
+            (state.updated_at, state.session_snapshot())
+        };
+        if *self.last_synced.read() == Some(updated_at) {
+            return;
+        }
+        *self.last_synced.write() = Some(updated_at);
+
+        if !snapshot.is_empty() {
+            self.save(&snapshot);
+        } else if *self.written.read() {
+            self.clear();
+        }
+    }
+
+    fn save(&self, snapshot: &SessionSnapshot) {
+        if let Some(path) = self.path.read().as_ref() {
+            if save_session_to_file(path, snapshot) {
+                *self.written.write() = true;
+            }
+        }
+    }
+
+    fn clear(&self) {
+        if let Some(path) = self.path.read().as_ref() {
+            if path.exists() {
+                if let Err(e) = fs::remove_file(path) {
+                    log::error!("Failed to remove session file {:?}: {}", path, e);
+                }
+            }
+        }
+    }
+}
+
+/// Load a saved session, if there is a readable one
+fn load_session_from_file(path: &PathBuf) -> Option<SessionSnapshot> {
+    if !path.exists() {
+        return None;
+    }
+    match fs::read_to_string(path) {
+        Ok(content) => match serde_json::from_str::<SessionSnapshot>(&content) {
+            Ok(session) => Some(session),
+            Err(e) => {
+                log::error!("Failed to parse session file {:?}: {}", path, e);
+                None
+            }
+        },
+        Err(e) => {
+            log::error!("Failed to read session file {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Save a session through a temp file and rename, so a crash mid-write never
+/// leaves a truncated `session.json` behind.
+fn save_session_to_file(path: &PathBuf, session: &SessionSnapshot) -> bool {
+    let content = match serde_json::to_string(session) {
+        Ok(content) => content,
+        Err(e) => {
+            log::error!("Failed to serialize session: {}", e);
+            return false;
+        }
+    };
+    let tmp = path.with_extension("json.tmp");
+    if let Err(e) = fs::write(&tmp, content).and_then(|_| fs::rename(&tmp, path)) {
+        log::error!("Failed to write session file {:?}: {}", path, e);
+        return false;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(id: &str, added_by: &str) -> Song {
+        Song {
+            id: id.to_string(),
+            youtube_id: format!("yt-{}", id),
+            title: id.to_string(),
+            artist: "Artist".to_string(),
+            duration: 180,
+            thumbnail_url: String::new(),
+            added_by: added_by.to_string(),
+            added_at: 0,
+            singers: Vec::new(),
+            voters: Vec::new(),
+            votes: 0,
+            auto_filled: false,
+            owner_id: None,
+        }
+    }
+
+    fn room() -> RoomState {
+        RoomState::new("room-1".to_string(), "host-peer".to_string(), Vec::new())
+    }
+
+    fn guest(id: &str, name: &str) -> ConnectedClient {
+        ConnectedClient {
+            id: id.to_string(),
+            display_name: name.to_string(),
+            connected_at: 0,
+            overflow: false,
+            device_token: None,
+            verified: false,
+        }
+    }
+
+    fn queue_ids(state: &RoomState) -> Vec<&str> {
+        state.queue.iter().map(|s| s.id.as_str()).collect()
+    }
+
+    #[test]
+    fn fifo_keeps_arrival_order() {
+        let mut state = room();
+        for (id, by) in [("now", "C"), ("a1", "A"), ("a2", "A"), ("b1", "B")] {
+            state.add_song(song(id, by));
+        }
+        assert_eq!(queue_ids(&state), ["a1", "a2", "b1"]);
+    }
+
+    #[test]
+    fn rotation_interleaves_singers_and_keeps_their_own_order() {
+        let mut state = room();
+        state.set_queue_mode(QueueMode::Rotation);
+        for (id, by) in [("now", "C"), ("a1", "A"), ("a2", "A"), ("a3", "A"), ("b1", "B"), ("b2", "B")] {
+            state.add_song(song(id, by));
+        }
+        assert_eq!(queue_ids(&state), ["a1", "b1", "a2", "b2", "a3"]);
+    }
+
+    #[test]
+    fn rotation_puts_the_current_singer_last_in_the_round() {
+        let mut state = room();
+        state.set_queue_mode(QueueMode::Rotation);
+        state.add_song(song("now", "A"));
+        state.add_song(song("a1", "A"));
+        state.add_song(song("b1", "B"));
+        assert_eq!(queue_ids(&state), ["b1", "a1"]);
+    }
+
+    #[test]
+    fn switching_to_rotation_rearranges_an_existing_queue() {
+        let mut state = room();
+        for (id, by) in [("now", "C"), ("a1", "A"), ("a2", "A"), ("b1", "B")] {
+            state.add_song(song(id, by));
+        }
+        state.set_queue_mode(QueueMode::Rotation);
+        assert_eq!(queue_ids(&state), ["a1", "b1", "a2"]);
+
+        // Switching back leaves the rotated order in place.
+        state.set_queue_mode(QueueMode::Fifo);
+        assert_eq!(queue_ids(&state), ["a1", "b1", "a2"]);
+    }
+
+    fn duet(id: &str, lead: &str, partner: &str) -> Song {
+        let mut s = song(id, lead);
+        s.singers.push(SongSinger {
+            name: partner.to_string(),
+            invited_by: None,
+            status: InviteStatus::Accepted,
+            client_id: None,
+        });
+        s
+    }
+
+    #[test]
+    fn rotation_credits_every_duet_partner_with_a_turn() {
+        let mut state = room();
+        state.set_queue_mode(QueueMode::Rotation);
+        state.add_song(song("now", "C"));
+        // B sings on A's duet, so B's own song waits until C has had another
+        // turn. Crediting only the lead would put b1 straight after the duet.
+        for s in [duet("ab", "A", "B"), song("b1", "B"), song("c1", "C")] {
+            state.add_song(s);
+        }
+        assert_eq!(queue_ids(&state), ["ab", "c1", "b1"]);
+    }
+
+    const HOST: DuetActor = DuetActor::Staff(None);
+
+    #[test]
+    fn duet_invites_are_pending_until_joined() {
+        let mut state = room();
+        state.add_client(guest("pa", "A"));
+        state.add_client(guest("pb", "B"));
+        state.add_song(song("now", "Host"));
+        state.add_guest_song(song("a1", "A"), "pa", 0).unwrap();
+
+        state.invite_to_duet("a1", "B", Some("pb"), DuetActor::Guest("pa")).unwrap();
+        assert_eq!(state.queue[0].performers(), ["A"], "pending invitees are not credited");
+        assert!(state.invite_to_duet("a1", "B", Some("pb"), DuetActor::Guest("pa")).is_err(), "no duplicate invites");
+
+        state.join_duet("a1", "ignored", DuetActor::Guest("pb")).unwrap();
+        assert_eq!(state.queue[0].singers[0].status, InviteStatus::Accepted);
+        assert_eq!(state.queue[0].singers[0].invited_by.as_deref(), Some("A"));
+        assert_eq!(state.queue[0].performers(), ["A", "B"]);
+        assert!(state.join_duet("a1", "B", DuetActor::Guest("pb")).is_err());
+    }
+
+    #[test]
+    fn duet_invites_go_to_the_guest_picked_not_the_first_with_the_name() {
+        let mut state = room();
+        for (id, name) in [("pa", "A"), ("pb1", "B"), ("pb2", "B")] {
+            state.add_client(guest(id, name));
+        }
+        state.add_song(song("now", "Host"));
+        state.add_guest_song(song("a1", "A"), "pa", 0).unwrap();
+
+        state.invite_to_duet("a1", "B", Some("pb2"), DuetActor::Guest("pa")).unwrap();
+        assert!(state.join_duet("a1", "B", DuetActor::Guest("pb1")).is_err());
+        state.join_duet("a1", "B", DuetActor::Guest("pb2")).unwrap();
+
+        // The other B is someone else, and may be invited too
+        state.invite_to_duet("a1", "B", Some("pb1"), DuetActor::Guest("pa")).unwrap();
+        assert!(state.invite_to_duet("a1", "B", Some("p9"), HOST).is_err());
+    }
+
+    #[test]
+    fn guests_act_only_as_themselves_on_duets() {
+        let mut state = room();
+        for (id, name) in [("pa", "A"), ("pb", "B"), ("pc", "C")] {
+            state.add_client(guest(id, name));
+        }
+        state.add_song(song("now", "Host"));
+        state.add_guest_song(song("a1", "A"), "pa", 0).unwrap();
+
+        // Only the lead invites, and the invite names who sent it
+        assert!(state.invite_to_duet("a1", "C", Some("pc"), DuetActor::Guest("pb")).is_err());
+        assert!(state.invite_to_duet("a1", "B", None, DuetActor::Guest("pa")).is_err(), "guests invite someone in the room");
+        state.invite_to_duet("a1", "B", Some("pb"), DuetActor::Guest("pa")).unwrap();
+
+        // C cannot accept B's invite by naming B, nor join uninvited
+        assert!(state.join_duet("a1", "B", DuetActor::Guest("pc")).is_err());
+        assert_eq!(state.queue[0].singers[0].status, InviteStatus::Pending);
+
+        // ...nor take B off the song
+        assert!(state.leave_duet("a1", "B", DuetActor::Guest("pc")).is_err());
+        state.leave_duet("a1", "ignored", DuetActor::Guest("pb")).unwrap();
+        assert!(state.queue[0].singers.is_empty());
+
+        // The lead may take someone off their own song
+        state.invite_to_duet("a1", "C", Some("pc"), DuetActor::Guest("pa")).unwrap();
+        state.leave_duet("a1", "C", DuetActor::Guest("pa")).unwrap();
+        assert!(state.queue[0].singers.is_empty());
+    }
+
+    #[test]
+    fn staff_can_add_and_remove_anyone_but_the_lead_cannot_leave() {
+        let mut state = room();
+        state.add_song(song("now", "A"));
+
+        state.join_duet("now", "B", HOST).unwrap();
+        assert_eq!(state.player.current_song.as_ref().unwrap().singers[0].invited_by, None);
+        state.leave_duet("now", "B", HOST).unwrap();
+        assert!(state.player.current_song.as_ref().unwrap().singers.is_empty());
+
+        assert!(state.leave_duet("now", "B", HOST).is_err());
+        assert!(state.leave_duet("now", "A", HOST).is_err());
+        assert!(state.join_duet("missing", "B", HOST).is_err());
+    }
+
+    #[test]
+    fn solo_songs_omit_the_singers_field() {
+        let json = serde_json::to_value(song("a1", "A")).unwrap();
+        assert!(json.get("singers").is_none());
+        let back: Song = serde_json::from_value(json).unwrap();
+        assert!(back.singers.is_empty());
+    }
+
+    fn current_id(state: &RoomState) -> Option<&str> {
+        state.player.current_song.as_ref().map(|s| s.id.as_str())
+    }
+
+    #[test]
+    fn undo_restores_a_removed_song_in_place() {
+        let mut state = room();
+        for (id, by) in [("now", "A"), ("a1", "A"), ("b1", "B"), ("c1", "C")] {
+            state.add_song(song(id, by));
+        }
+        state.remove_song("b1");
+        state.move_song_to_bottom("a1");
+        assert_eq!(queue_ids(&state), ["c1", "a1"]);
+
+        assert!(state.undo());
+        assert_eq!(queue_ids(&state), ["a1", "c1"]);
+        assert!(state.undo());
+        assert_eq!(queue_ids(&state), ["a1", "b1", "c1"]);
+
+        assert!(state.redo());
+        assert_eq!(queue_ids(&state), ["a1", "c1"]);
+    }
+
+    #[test]
+    fn undoing_a_skip_puts_the_previous_song_back_on_stage() {
+        let mut state = room();
+        state.add_song(song("now", "A"));
+        state.add_song(song("next", "B"));
+        state.update_player(Some(PlayerStatus::Playing), Some(42.0), None);
+
+        state.skip_song();
+        assert_eq!(current_id(&state), Some("next"));
+
+        assert!(state.undo());
+        assert_eq!(current_id(&state), Some("now"));
+        assert_eq!(queue_ids(&state), ["next"]);
+        assert!(matches!(state.player.status, PlayerStatus::Loading));
+        assert_eq!(state.player.current_time, 0.0);
+    }
+
+    #[test]
+    fn a_new_change_clears_redo_and_no_ops_are_not_recorded() {
+        let mut state = room();
+        state.add_song(song("now", "A"));
+        state.add_song(song("a1", "A"));
+        state.undo();
+        assert!(state.history.can_redo());
+
+        state.add_song(song("b1", "B"));
+        assert!(!state.history.can_redo());
+
+        // Moving the top song up changes nothing and leaves nothing to undo.
+        let depth = state.history.undo.len();
+        assert!(!state.move_song_up("b1"));
+        assert_eq!(state.history.undo.len(), depth);
+    }
+
+    #[test]
+    fn undo_history_is_bounded() {
+        let mut state = room();
+        for i in 0..(MAX_UNDO_HISTORY + 10) {
+            state.add_song(song(&format!("s{}", i), "A"));
+        }
+        let mut undone = 0;
+        while state.undo() {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_UNDO_HISTORY);
+        assert!(!state.undo());
+    }
+
+    #[test]
+    fn history_serializes_as_flags_only() {
+        let mut state = room();
+        state.add_song(song("now", "A"));
+        let json = serde_json::to_value(&state).unwrap();
+        assert_eq!(json["history"], serde_json::json!({ "canUndo": true, "canRedo": false }));
+    }
+
+    fn limited_room(limits: QueueLimits) -> RoomState {
+        let mut state = room();
+        state.set_queue_limits(limits);
+        state.add_song(song("now", "Host"));
+        state
+    }
+
+    #[test]
+    fn singer_quota_counts_only_that_singers_pending_songs() {
+        let mut state = limited_room(QueueLimits { max_pending_per_singer: Some(2), ..Default::default() });
+        state.add_guest_song(song("a1", "A"), "peer-a", 0).unwrap();
+        state.add_guest_song(song("a2", "A"), "peer-a", 0).unwrap();
+        assert_eq!(
+            state.add_guest_song(song("a3", "A"), "peer-a", 0),
+            Err(QueueRejection::SingerQuota { limit: 2 })
+        );
+        assert!(state.add_guest_song(song("b1", "B"), "peer-b", 0).is_ok());
+        assert_eq!(queue_ids(&state), ["a1", "a2", "b1"]);
+    }
+
+    #[test]
+    fn singer_quota_follows_the_guest_not_the_typed_name() {
+        let mut state = limited_room(QueueLimits { max_pending_per_singer: Some(1), ..Default::default() });
+        state.add_guest_song(song("a1", "A"), "peer-a", 0).unwrap();
+        assert_eq!(
+            state.add_guest_song(song("a2", "Someone else"), "peer-a", 0),
+            Err(QueueRejection::SingerQuota { limit: 1 })
+        );
+    }
+
+    #[test]
+    fn cooldown_applies_per_guest_and_expires() {
+        let mut state = limited_room(QueueLimits { add_cooldown_secs: Some(30), ..Default::default() });
+        state.add_guest_song(song("a1", "A"), "peer-a", 1_000).unwrap();
+        assert_eq!(
+            state.add_guest_song(song("a2", "A"), "peer-a", 11_000),
+            Err(QueueRejection::Cooldown { retry_after_ms: 20_000 })
+        );
+        assert!(state.add_guest_song(song("b1", "B"), "peer-b", 11_000).is_ok());
+        assert!(state.add_guest_song(song("a2", "A"), "peer-a", 31_000).is_ok());
+    }
+
+    #[test]
+    fn rejected_adds_do_not_restart_the_cooldown() {
+        let mut state = limited_room(QueueLimits { add_cooldown_secs: Some(10), ..Default::default() });
+        state.add_guest_song(song("a1", "A"), "peer-a", 0).unwrap();
+        assert!(state.add_guest_song(song("a2", "A"), "peer-a", 5_000).is_err());
+        assert!(state.add_guest_song(song("a2", "A"), "peer-a", 10_000).is_ok());
+    }
+
+    #[test]
+    fn queue_cap_rejects_everyone_once_reached() {
+        let mut state = limited_room(QueueLimits { max_queue_length: Some(1), ..Default::default() });
+        state.add_guest_song(song("a1", "A"), "peer-a", 0).unwrap();
+        let err = state.add_guest_song(song("b1", "B"), "peer-b", 0).unwrap_err();
+        assert_eq!(err, QueueRejection::QueueFull { limit: 1 });
+        assert_eq!(err.code(), "QUEUE_FULL");
+    }
+
+    #[test]
+    fn rejections_serialize_their_details() {
+        let json = serde_json::to_value(QueueRejection::Cooldown { retry_after_ms: 1500 }).unwrap();
+        assert_eq!(json, serde_json::json!({ "retryAfterMs": 1500 }));
+        assert!(QueueRejection::Cooldown { retry_after_ms: 1500 }.message().contains("2s"));
+    }
+
+    #[test]
+    fn queue_mode_serializes_in_lowercase() {
+        let json = serde_json::to_value(room()).unwrap();
+        assert_eq!(json["queueMode"], "fifo");
+        let mode: QueueMode = serde_json::from_str("\"rotation\"").unwrap();
+        assert_eq!(mode, QueueMode::Rotation);
+    }
+
+    fn temp_data_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("karaokenatin-{}-{}", name, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn manager_with(songs: &[&str]) -> RoomStateManager {
+        let manager = RoomStateManager::new("room-1".to_string(), "host-peer".to_string(), Vec::new());
+        for id in songs {
+            manager.write().add_song(song(id, "A"));
+        }
+        manager
+    }
+
+    #[test]
+    fn handoff_carries_the_queue_but_not_privileged_roles() {
+        let mut old = room();
+        old.add_song(song("a1", "A"));
+        old.add_song(song("b1", "B"));
+        old.add_client(guest("p1", "Ana"));
+        old.add_client(guest("p2", "Ben"));
+        old.set_guest_role("p1", Role::CoHost).unwrap();
+        old.set_guest_role("p2", Role::Spectator).unwrap();
+        let package: HandoffPackage = serde_json::from_value(serde_json::to_value(old.handoff_package()).unwrap()).unwrap();
+
+        let mut new = room();
+        new.import_handoff(package);
+        assert_eq!(new.player.current_song.as_ref().unwrap().id, "a1");
+        assert_eq!(new.queue.len(), 1);
+
+        // Whoever turns up as Ana is an ordinary singer until the new host says otherwise
+        new.add_client(guest("q7", " ana "));
+        assert_eq!(new.role_of(Some("q7")), DEFAULT_GUEST_ROLE);
+
+        // Ben stays a spectator under a new peer id; the role follows one guest only
+        new.add_client(guest("q8", "Ben"));
+        assert_eq!(new.role_of(Some("q8")), Role::Spectator);
+        new.add_client(guest("q9", "Ben"));
+        assert_eq!(new.role_of(Some("q9")), DEFAULT_GUEST_ROLE);
+    }
+
+    #[test]
+    fn registry_keeps_rooms_apart_and_reopens_by_id() {
+        let rooms = RoomRegistry::new();
+        let main = rooms.open("main", "main", Vec::new());
+        let side = rooms.open("side", "room-2", Vec::new());
+        main.write().add_song(song("a1", "A"));
+        assert!(side.clone_state().player.current_song.is_none());
+
+        // A reloaded window gets its room back, queue and all
+        rooms.open("main", "main", Vec::new());
+        assert!(rooms.get("main").unwrap().state.clone_state().player.current_song.is_some());
+        assert!(rooms.is_main("main"));
+
+        assert_eq!(rooms.close_window("room-2"), vec!["side".to_string()]);
+        assert!(rooms.get("side").is_none());
+        assert!(rooms.get("main").is_some());
+    }
+
+    #[test]
+    fn restore_brings_back_queue_and_position() {
+        let mut state = room();
+        state.add_song(song("a1", "A"));
+        state.add_song(song("b1", "B"));
+        state.play();
+        state.update_player(Some(PlayerStatus::Playing), Some(95.0), None);
+        state.set_queue_mode(QueueMode::Rotation);
+        let saved = state.session_snapshot();
+
+        let mut restored = room();
+        restored.restore_session(saved);
+        assert_eq!(current_id(&restored), Some("a1"));
+        assert_eq!(queue_ids(&restored), vec!["b1"]);
+        assert_eq!(restored.player.current_time, 95.0);
+        assert!(matches!(restored.player.status, PlayerStatus::Loading));
+        assert_eq!(restored.queue_mode, QueueMode::Rotation);
+    }
+
+    #[test]
+    fn restore_keeps_songs_added_since_and_what_is_playing() {
+        let mut old = room();
+        old.add_song(song("a1", "A"));
+        old.add_song(song("a2", "A"));
+        old.play();
+        let saved = old.session_snapshot();
+
+        let mut state = room();
+        state.add_song(song("new", "B"));
+        state.play();
+        state.add_song(song("later", "B"));
+        state.restore_session(saved);
+        assert_eq!(current_id(&state), Some("new"));
+        assert_eq!(queue_ids(&state), vec!["a1", "a2", "later"]);
+
+        assert!(state.undo());
+        assert_eq!(queue_ids(&state), vec!["later"]);
+    }
+
+    #[test]
+    fn session_store_offers_previous_session_once() {
+        let dir = temp_data_dir("restore");
+        let store = SessionStore::new();
+        store.initialize(dir.clone());
+        store.sync(&manager_with(&["a1", "a2"]));
+
+        let next_run = SessionStore::new();
+        next_run.initialize(dir.clone());
+        let offered = next_run.pending().unwrap();
+        assert_eq!(offered.current_song.as_deref(), Some("a1"));
+        assert_eq!(offered.queue_length, 1);
+        assert_eq!(next_run.take_pending().map(|s| s.queue.len()), Some(1));
+        assert!(next_run.pending().is_none());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn empty_room_leaves_unanswered_session_on_disk() {
+        let dir = temp_data_dir("unanswered");
+        let store = SessionStore::new();
+        store.initialize(dir.clone());
+        store.sync(&manager_with(&["a1"]));
+
+        let next_run = SessionStore::new();
+        next_run.initialize(dir.clone());
+        next_run.sync(&manager_with(&[]));
+        assert!(dir.join("session.json").exists());
+
+        next_run.discard_pending();
+        assert!(!dir.join("session.json").exists());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn emptied_room_clears_its_own_session() {
+        let dir = temp_data_dir("emptied");
+        let store = SessionStore::new();
+        store.initialize(dir.clone());
+        let manager = manager_with(&["a1"]);
+        store.sync(&manager);
+        assert!(dir.join("session.json").exists());
+
+        // Make sure the removal gets a newer `updated_at` than the save
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        manager.write().skip_song();
+        store.sync(&manager);
+        assert!(!dir.join("session.json").exists());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn history_records_how_each_song_left_the_stage() {
+        let mut state = room();
+        state.add_song(song("a1", "A"));
+        state.add_song(song("b1", "B"));
+        state.update_player(Some(PlayerStatus::Playing), None, None);
+        state.finish_song();
+        state.skip_song();
+        state.skip_song();
+
+        let history = state.play_history();
+        assert_eq!(history.len(), 2);
+        assert_eq!((history[0].title.as_str(), history[0].outcome), ("a1", PlayOutcome::Completed));
+        assert_eq!((history[1].title.as_str(), history[1].outcome), ("b1", PlayOutcome::Skipped));
+        assert!(history[0].started_at <= history[0].ended_at);
+        // b1 never reached Playing
+        assert_eq!(history[1].started_at, history[1].ended_at);
+    }
+
+    #[test]
+    fn history_credits_every_performer() {
+        let mut state = room();
+        state.add_song(song("ab", "A"));
+        state.join_duet("ab", "B", HOST).unwrap();
+        state.invite_to_duet("ab", "C", None, HOST).unwrap();
+        state.finish_song();
+        assert_eq!(state.play_history()[0].singers, vec!["A", "B"]);
+    }
+
+    #[test]
+    fn history_exports_as_csv_with_quoting() {
+        let entry = PlayedSong {
+            youtube_id: "yt-1".to_string(),
+            title: "Hello, \"World\"".to_string(),
+            artist: "Artist".to_string(),
+            singers: vec!["A".to_string(), "B".to_string()],
+            started_at: 0,
+            ended_at: 180_000,
+            outcome: PlayOutcome::Completed,
+        };
+        let csv = export_history(&[entry], HistoryFormat::Csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("started_at,ended_at,title,artist,singers,youtube_id,outcome"));
+        assert_eq!(
+            lines.next(),
+            Some("1970-01-01T00:00:00Z,1970-01-01T00:03:00Z,\"Hello, \"\"World\"\"\",Artist,A; B,yt-1,completed")
+        );
+    }
+
+    #[test]
+    fn restored_session_keeps_earlier_history() {
+        let mut old = room();
+        old.add_song(song("a1", "A"));
+        old.add_song(song("a2", "A"));
+        old.finish_song();
+        let saved = old.session_snapshot();
+
+        let mut state = room();
+        state.restore_session(saved);
+        state.skip_song();
+        let titles: Vec<&str> = state.play_history().iter().map(|p| p.title.as_str()).collect();
+        assert_eq!(titles, vec!["a1", "a2"]);
+    }
+
+    fn auto_dj_room(songs: &[&str], order: AutoDjOrder) -> RoomState {
+        let collection = PlaylistCollection {
+            id: "party".to_string(),
+            name: "Party".to_string(),
+            visibility: CollectionVisibility::Public,
+            songs: songs.iter().map(|id| song(id, "Host")).collect(),
+            created_at: 0,
+            updated_at: 0,
+        };
+        let mut state = RoomState::new("room-1".to_string(), "host-peer".to_string(), vec![collection]);
+        state.set_auto_dj(Some(AutoDjConfig { collection_id: "party".to_string(), order }));
+        state
+    }
+
+    fn current_title(state: &RoomState) -> Option<&str> {
+        state.player.current_song.as_ref().map(|s| s.title.as_str())
+    }
+
+    fn queue_titles(state: &RoomState) -> Vec<&str> {
+        state.queue.iter().map(|s| s.title.as_str()).collect()
+    }
+
+    #[test]
+    fn auto_dj_starts_a_silent_room_and_lines_up_the_next_song() {
+        let state = auto_dj_room(&["p1", "p2", "p3"], AutoDjOrder::Sequential);
+        assert_eq!(current_title(&state), Some("p1"));
+        assert_eq!(queue_titles(&state), vec!["p2"]);
+        assert!(state.queue[0].auto_filled);
+        assert_eq!(state.queue[0].added_by, AUTO_DJ_NAME);
+    }
+
+    #[test]
+    fn auto_dj_never_repeats_a_song_played_this_session() {
+        let mut state = auto_dj_room(&["p1", "p2"], AutoDjOrder::Shuffle);
+        state.finish_song();
+        state.finish_song();
+        assert!(current_title(&state).is_none());
+        assert!(state.queue.is_empty());
+        let mut played: Vec<&str> = state.play_history().iter().map(|p| p.title.as_str()).collect();
+        played.sort_unstable();
+        assert_eq!(played, vec!["p1", "p2"]);
+    }
+
+    #[test]
+    fn auto_dj_skips_songs_the_room_already_sang() {
+        let mut state = room();
+        state.playlists.push(PlaylistCollection {
+            id: "party".to_string(),
+            name: "Party".to_string(),
+            visibility: CollectionVisibility::Public,
+            songs: vec![song("p1", "Host"), song("p2", "Host")],
+            created_at: 0,
+            updated_at: 0,
+        });
+        state.add_song(song("p1", "A"));
+        state.finish_song();
+        state.set_auto_dj(Some(AutoDjConfig { collection_id: "party".to_string(), order: AutoDjOrder::Sequential }));
+        assert_eq!(current_title(&state), Some("p2"));
+    }
+
+    #[test]
+    fn room_songs_jump_ahead_of_auto_dj_songs() {
+        let mut state = auto_dj_room(&["p1", "p2", "p3"], AutoDjOrder::Sequential);
+        state.add_song(song("a1", "A"));
+        state.add_song(song("b1", "B"));
+        assert_eq!(queue_titles(&state), vec!["a1", "b1", "p2"]);
+
+        state.set_queue_mode(QueueMode::Rotation);
+        state.add_song(song("a2", "A"));
+        assert_eq!(queue_titles(&state), vec!["a1", "b1", "a2", "p2"]);
+    }
+
+    #[test]
+    fn auto_dj_songs_do_not_count_against_the_queue_cap() {
+        let mut state = auto_dj_room(&["p1", "p2"], AutoDjOrder::Sequential);
+        state.set_queue_limits(QueueLimits { max_queue_length: Some(1), ..Default::default() });
+        assert!(state.add_guest_song(song("a1", "A"), "peer-a", 0).is_ok());
+        assert!(state.add_guest_song(song("b1", "B"), "peer-b", 0).is_err());
+    }
+
+    #[test]
+    fn turning_auto_dj_off_drops_its_queued_songs() {
+        let mut state = auto_dj_room(&["p1", "p2"], AutoDjOrder::Sequential);
+        state.add_song(song("a1", "A"));
+        state.set_auto_dj(None);
+        assert_eq!(current_title(&state), Some("p1"));
+        assert_eq!(queue_titles(&state), vec!["a1"]);
+        state.finish_song();
+        state.finish_song();
+        assert!(current_title(&state).is_none());
+    }
+
+    #[test]
+    fn votes_mode_sorts_by_votes_then_add_time() {
+        let mut state = room();
+        state.set_queue_mode(QueueMode::Votes);
+        state.add_song(song("now", "A"));
+        for (i, id) in ["a1", "b1", "c1"].iter().enumerate() {
+            let mut s = song(id, "A");
+            s.added_at = i as i64;
+            state.add_song(s);
+        }
+        state.upvote("c1", "peer-1").unwrap();
+        state.upvote("c1", "peer-2").unwrap();
+        state.upvote("b1", "peer-1").unwrap();
+        assert_eq!(queue_ids(&state), vec!["c1", "b1", "a1"]);
+
+        state.unvote("c1", "peer-1").unwrap();
+        state.unvote("c1", "peer-2").unwrap();
+        assert_eq!(queue_ids(&state), vec!["b1", "a1", "c1"]);
+    }
+
+    #[test]
+    fn votes_are_undone_like_other_queue_changes() {
+        let mut state = room();
+        state.set_queue_mode(QueueMode::Votes);
+        state.add_song(song("now", "A"));
+        state.add_song(song("a1", "A"));
+        state.add_song(song("b1", "B"));
+        state.upvote("b1", "peer-1").unwrap();
+        state.remove_song("a1");
+        assert_eq!(queue_ids(&state), vec!["b1"]);
+
+        assert!(state.undo());
+        assert_eq!(queue_ids(&state), vec!["b1", "a1"], "the vote and its order outlive undoing a later change");
+        assert_eq!(state.queue[0].voters, vec!["peer-1"]);
+        assert!(state.undo());
+        assert_eq!(queue_ids(&state), vec!["a1", "b1"]);
+        assert!(state.queue[1].voters.is_empty());
+    }
+
+    #[test]
+    fn each_guest_votes_once_per_song() {
+        let mut state = room();
+        state.add_song(song("now", "A"));
+        state.add_song(song("a1", "A"));
+        state.upvote("a1", "peer-1").unwrap();
+        assert!(state.upvote("a1", "peer-1").is_err());
+        assert!(state.unvote("a1", "peer-2").is_err());
+        assert!(state.upvote("now", "peer-1").is_err());
+        assert_eq!(state.queue[0].voters, vec!["peer-1"]);
+    }
+
+    #[test]
+    fn vote_counts_are_broadcast_without_voters() {
+        let mut state = room();
+        state.add_song(song("now", "A"));
+        state.add_song(song("a1", "A"));
+        state.add_song(song("b1", "B"));
+        state.add_client(guest("peer-1", "peer-1"));
+        state.add_client(guest("peer-2", "peer-2"));
+        state.upvote("a1", "peer-1").unwrap();
+        state.upvote("a1", "peer-2").unwrap();
+        state.upvote("b1", "peer-2").unwrap();
+
+        let broadcast = serde_json::to_value(state.public_broadcast()).unwrap();
+        assert_eq!(broadcast["state"]["queue"][0]["votes"], 2);
+        assert!(broadcast["state"]["queue"][0].get("voters").is_none());
+        assert_eq!(broadcast["viewers"]["peer-1"]["votedSongIds"], serde_json::json!(["a1"]));
+        assert_eq!(broadcast["viewers"]["peer-2"]["votedSongIds"], serde_json::json!(["a1", "b1"]));
+        assert_eq!(state.queue[0].voters, vec!["peer-1", "peer-2"], "the host keeps who voted");
+    }
+
+    #[test]
+    fn vote_skip_waits_for_the_threshold() {
+        let mut state = room();
+        for id in ["g1", "g2", "g3", "g4"] {
+            state.add_client(guest(id, id));
+        }
+        state.add_song(song("a1", "A"));
+        state.add_song(song("b1", "B"));
+        assert_eq!(state.skip_votes.needed, 2);
+
+        assert_eq!(state.vote_skip("g1"), Ok(false));
+        assert!(state.vote_skip("g1").is_err());
+        assert_eq!(current_id(&state), Some("a1"));
+        assert_eq!(state.vote_skip("g2"), Ok(true));
+        assert_eq!(current_id(&state), Some("b1"));
+        assert_eq!(state.play_history()[0].outcome, PlayOutcome::Skipped);
+    }
+
+    #[test]
+    fn skip_votes_reset_when_the_song_changes() {
+        let mut state = room();
+        for id in ["g1", "g2", "g3"] {
+            state.add_client(guest(id, id));
+        }
+        state.add_song(song("a1", "A"));
+        state.add_song(song("b1", "B"));
+        state.vote_skip("g1").unwrap();
+        state.skip_song();
+        assert!(state.skip_votes.voters.is_empty());
+        assert_eq!(state.skip_votes.song_id.as_deref(), Some("b1"));
+    }
+
+    #[test]
+    fn skip_threshold_follows_the_policy_and_the_room() {
+        let mut state = room();
+        state.add_song(song("a1", "A"));
+        assert_eq!(state.skip_votes.needed, 1);
+        for id in ["g1", "g2", "g3", "g4", "g5"] {
+            state.add_client(guest(id, id));
+        }
+        state.set_skip_policy(SkipPolicy { vote_threshold: 0.75, restrict_skip: true });
+        assert_eq!(state.skip_votes.needed, 4);
+        state.remove_client("g5");
+        assert_eq!(state.skip_votes.needed, 3);
+    }
+
+    #[test]
+    fn skip_votes_count_only_guests_in_the_room() {
+        let mut state = room();
+        for id in ["g1", "g2", "g3", "g4", "g5", "g6"] {
+            state.add_client(guest(id, id));
+        }
+        state.add_song(song("a1", "A"));
+        assert_eq!(state.skip_votes.needed, 3);
+        assert!(state.vote_skip("stranger").is_err());
+        assert!(state.vote_skip("host").is_err());
+        assert_eq!(state.vote_skip("g1"), Ok(false));
+        assert_eq!(state.vote_skip("g2"), Ok(false));
+
+        let broadcast = serde_json::to_value(state.public_broadcast()).unwrap();
+        assert_eq!(broadcast["state"]["skipVotes"]["votes"], 2);
+        assert_eq!(broadcast["state"]["skipVotes"]["voters"], serde_json::json!([]));
+        assert_eq!(broadcast["viewers"]["g1"]["votedSkip"], true);
+        assert_eq!(broadcast["viewers"]["g3"]["votedSkip"], false);
+
+        // A guest who leaves takes their vote with them
+        state.remove_client("g2");
+        assert_eq!(state.skip_votes.voters, vec!["g1"]);
+        assert_eq!(state.skip_votes.needed, 3);
+        assert_eq!(state.vote_skip("g3"), Ok(false));
+        assert_eq!(state.vote_skip("g4"), Ok(true));
+        assert_eq!(current_id(&state), None);
+    }
+
+    #[test]
+    fn guests_prove_their_device_before_commands_count() {
+        let mut state = room();
+        state.add_client(ConnectedClient { device_token: Some("secret".into()), ..guest("g1", "g1") });
+        assert!(!state.is_verified("g1"));
+        assert!(!state.verify_client("g1", "guess"));
+        assert!(!state.verify_client("g2", "secret"));
+        assert!(state.verify_client("g1", "secret"));
+        assert!(state.is_verified("g1"));
+
+        // Rejoining starts over until the new connection shows the token
+        state.add_client(ConnectedClient { device_token: Some("secret".into()), ..guest("g1", "g1") });
+        assert!(!state.is_verified("g1"));
+    }
+
+    #[test]
+    fn the_broadcast_carries_no_peer_ids() {
+        let mut state = room();
+        state.add_client(guest("g1", "g1"));
+        state.add_client(ConnectedClient { overflow: true, ..guest("g2", "g2") });
+        state.set_guest_role("g1", Role::CoHost).unwrap();
+        state.add_song(Song { owner_id: Some("g1".into()), ..song("now", "A") });
+        state.add_song(Song { owner_id: Some("g1".into()), ..song("a1", "A") });
+        state.add_song(song("b1", "B"));
+
+        let broadcast = serde_json::to_value(state.public_broadcast()).unwrap();
+        let shared = broadcast["state"].to_string();
+        assert!(!shared.contains("g1") && !shared.contains("g2"), "{}", shared);
+        assert_eq!(broadcast["viewers"]["g1"]["role"], "cohost");
+        assert_eq!(broadcast["viewers"]["g1"]["ownSongIds"], serde_json::json!(["a1"]));
+        assert_eq!(broadcast["viewers"]["g2"]["role"], "spectator");
+        assert_eq!(broadcast["viewers"]["g2"]["overflow"], true);
+        assert_eq!(state.roles.get("g1"), Some(&Role::CoHost), "the host keeps the roles");
+    }
+
+    #[test]
+    fn roles_default_to_singer_and_cannot_be_host() {
+        let mut state = room();
+        assert_eq!(state.role_of(None), Role::Host);
+        assert_eq!(state.role_of(Some("g1")), Role::Singer);
+
+        state.set_guest_role("g1", Role::CoHost).unwrap();
+        assert_eq!(state.role_of(Some("g1")), Role::CoHost);
+        assert!(state.set_guest_role("g1", Role::Host).is_err());
+
+        state.set_guest_role("g1", Role::Singer).unwrap();
+        assert!(state.roles.is_empty());
+    }
+
+    #[test]
+    fn overflow_guests_watch_until_given_a_role() {
+        let mut state = room();
+        state.add_client(guest("g1", "g1"));
+        state.add_client(ConnectedClient { overflow: true, ..guest("g2", "g2") });
+        state.add_client(ConnectedClient { overflow: true, ..guest("g3", "g3") });
+        assert!(state.is_overflow("g2"));
+        assert_eq!(state.role_of(Some("g2")), Role::Spectator);
+        state.add_song(song("a1", "A"));
+        assert_eq!(state.skip_votes.needed, 1, "overflow spectators cannot vote, so they are not counted");
+
+        state.set_guest_role("g2", Role::Singer).unwrap();
+        assert!(!state.is_overflow("g2"));
+        assert_eq!(state.role_of(Some("g2")), Role::Singer);
+    }
+
+    #[test]
+    fn seated_spectators_take_a_seat_from_later_guests() {
+        let mut state = room();
+        state.add_client(guest("g1", "g1"));
+        assert!(state.is_at_capacity(1, "g2"));
+        assert!(!state.is_at_capacity(1, "g1"), "rejoining keeps your own seat");
+        state.add_client(ConnectedClient { overflow: true, ..guest("g2", "g2") });
+        assert!(!state.is_at_capacity(2, "g3"), "spectators hold no seat");
+
+        state.set_guest_role("g2", Role::Singer).unwrap();
+        state.remove_client("g1");
+        assert!(state.is_at_capacity(1, "g3"), "the seated spectator holds the only seat");
+    }
+
+    #[test]
+    fn guest_songs_remember_who_queued_them() {
+        let mut state = room();
+        state.add_song(song("a1", "A"));
+        state.add_guest_song(song("b1", "B"), "g1", 0).unwrap();
+        state.add_song(song("c1", "C"));
+        assert!(state.is_song_owner("b1", "g1"));
+        assert!(!state.is_song_owner("b1", "g2"));
+        assert!(!state.is_song_owner("c1", "g1"));
+    }
+
+    #[test]
+    fn reconnecting_clients_replace_their_old_entry() {
+        let mut state = room();
+        state.add_client(guest("g1", "g1"));
+        state.add_client(guest("g2", "g2"));
+        state.add_client(guest("g1", "g1"));
+        let ids: Vec<&str> = state.connected_clients.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["g2", "g1"]);
+
+        state.rename_client("g1", "  Ana ").unwrap();
+        assert_eq!(state.connected_clients[1].display_name, "Ana");
+        assert!(state.rename_client("g1", " ").is_err());
+        assert!(state.rename_client("g9", "Bo").is_err());
     }
 }
@@ -1,11 +1,102 @@
+use axum::extract::ConnectInfo;
 use serde::{Deserialize, Serialize};
 use socketioxide::extract::{Data, SocketRef, State};
+use socketioxide::socket::Sid;
 use std::sync::Arc;
 use parking_lot::RwLock;
-use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::peer_server::PeerRegistry;
+use crate::room_state::{ConnectedClient, HandoffPackage};
+use tauri::AppHandle;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-const MAX_CLIENTS_PER_ROOM: usize = 10;
+/// Guests a room seats unless the host picks another capacity. Anyone past
+/// it comes in as a read-only spectator.
+const DEFAULT_ROOM_CAPACITY: usize = 10;
+
+/// Hard ceiling on guests in a room, seated or spectating
+const MAX_CLIENTS_PER_ROOM: usize = 100;
+
+/// Guests who may wait in one room's lobby at a time
+const MAX_LOBBY_PER_ROOM: usize = 20;
+
+/// How long a disconnected guest's identity is kept for them to reclaim with
+/// their device token (a locked phone screen, a Wi-Fi blip)
+const IDENTITY_GRACE_MS: u64 = 10 * 60 * 1000;
+
+/// How long a room outlives its host's socket (a webview reload, a Wi-Fi
+/// blip) unless the host asks for something else in CREATE_ROOM
+const DEFAULT_HOST_GRACE_SECS: u64 = 60;
+const MAX_HOST_GRACE_SECS: u64 = 600;
+
+const MAX_INVITE_LABEL_LEN: usize = 40;
+
+/// PIN guessing limits. A 6-digit PIN is a million guesses, which a script
+/// on the LAN would get through in minutes without these. Each address gets
+/// `PIN_MAX_FAILURES` wrong PINs per window; the whole room gets
+/// `PIN_ROOM_MAX_FAILURES`, for a guesser hopping between addresses. Either
+/// limit locks PIN entry for `PIN_LOCKOUT_MS`.
+const PIN_MAX_FAILURES: u32 = 5;
+const PIN_ROOM_MAX_FAILURES: u32 = 30;
+const PIN_FAILURE_WINDOW_MS: u64 = 10 * 60 * 1000;
+const PIN_LOCKOUT_MS: u64 = 15 * 60 * 1000;
+
+/// How long a hand-off code works. Wrong codes lock out the claiming address
+/// like wrong PINs do.
+const HANDOFF_TTL_MS: u64 = 10 * 60 * 1000;
+
+/// Why a reclaim was refused when the key was right; the host window tries
+/// again once the server notices its old socket is gone
+const HOST_STILL_CONNECTED: &str = "The room's host is still connected";
+
+/// An invite link the host handed out. Its token rides in the QR code like
+/// the room's join token used to, but it can run out and be revoked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invite {
+    #[serde(rename = "inviteId")]
+    pub invite_id: String,
+    /// SHA-256 of the invite token; never sent back to anyone
+    #[serde(rename = "tokenHash", skip_serializing)]
+    pub token_hash: String,
+    /// What the host wrote on it, e.g. "table 3"
+    pub label: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<u64>,
+    #[serde(rename = "maxUses")]
+    pub max_uses: Option<u32>,
+    pub uses: u32,
+}
+
+impl Invite {
+    pub fn new(invite_id: String, token_hash: String, label: Option<String>, expires_in_secs: Option<u64>, max_uses: Option<u32>, now: u64) -> Self {
+        let label = label
+            .map(|l| l.trim().chars().take(MAX_INVITE_LABEL_LEN).collect::<String>())
+            .filter(|l| !l.is_empty());
+        Self {
+            invite_id,
+            token_hash,
+            label,
+            created_at: now,
+            expires_at: expires_in_secs.map(|secs| now + secs * 1000),
+            max_uses,
+            uses: 0,
+        }
+    }
+
+    /// Why this invite no longer lets anyone in, if it doesn't
+    fn refusal(&self, now: u64) -> Option<&'static str> {
+        if self.expires_at.is_some_and(|t| now >= t) {
+            Some("This invite has expired")
+        } else if self.max_uses.is_some_and(|max| self.uses >= max) {
+            Some("This invite has already been used")
+        } else {
+            None
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoomMetadata {
@@ -13,8 +104,118 @@ pub struct RoomMetadata {
     pub host_socket_id: String,
     pub host_peer_id: Option<String>,
     pub join_token_hash: String,
+    /// SHA-256 of a secret only the host knows, which it reclaims the room
+    /// with; the join token cannot be used since guests have it too
+    #[serde(skip)]
+    pub host_key_hash: String,
     pub created_at: u64,
     pub client_count: usize,
+    /// Device ids the host banned; cleared when the room is deleted
+    #[serde(default)]
+    pub banned_devices: HashSet<String>,
+    /// When the host's socket dropped; `None` while the host is connected
+    #[serde(default)]
+    pub host_away_since: Option<u64>,
+    /// How long the room waits for its host before it is torn down
+    pub host_grace_secs: u64,
+    /// Invites guests join with; revoking one removes it
+    #[serde(default)]
+    pub invites: Vec<Invite>,
+    /// Park joining guests until the host approves them
+    #[serde(default)]
+    pub lobby: bool,
+    /// Seated guests; later arrivals watch as overflow spectators
+    pub capacity: usize,
+    /// SHA-256 of the PIN guests can type instead of scanning a QR code;
+    /// `None` turns PIN entry off
+    #[serde(default)]
+    pub pin_hash: Option<String>,
+    /// The code another host can take this room over with, while offered
+    #[serde(skip)]
+    pub handoff: Option<HandoffOffer>,
+}
+
+/// A room on offer to another host
+#[derive(Debug, Clone)]
+pub struct HandoffOffer {
+    code_hash: String,
+    expires_at: u64,
+}
+
+/// What the server knows about a joined guest beyond its socket id
+#[derive(Debug, Clone, Default)]
+pub struct GuestIdentity {
+    /// The guest's client id, which it also uses as its PeerJS id
+    pub peer_id: Option<String>,
+    /// Stable per-browser id the guest sends with JOIN_ROOM
+    pub device_id: Option<String>,
+    /// Token the guest presents to reclaim this identity after a reconnect
+    pub device_token: Option<String>,
+}
+
+/// A guest identity that outlives its socket, keyed by device token
+#[derive(Debug, Clone)]
+struct SavedIdentity {
+    room_id: String,
+    client_id: String,
+    display_name: String,
+    /// When its last socket disconnected; `None` while connected
+    left_at: Option<u64>,
+}
+
+/// Wrong PINs from one address, or against one room
+#[derive(Debug, Clone, Default)]
+struct PinAttempts {
+    failures: u32,
+    window_start: u64,
+    locked_until: u64,
+}
+
+impl PinAttempts {
+    /// How much longer PIN entry is locked, if it is
+    fn lockout_left(&self, now: u64) -> Option<u64> {
+        (now < self.locked_until).then(|| self.locked_until - now)
+    }
+
+    fn fail(&mut self, max_failures: u32, now: u64) {
+        if now.saturating_sub(self.window_start) > PIN_FAILURE_WINDOW_MS {
+            self.failures = 0;
+            self.window_start = now;
+        }
+        self.failures += 1;
+        if self.failures >= max_failures {
+            self.locked_until = now + PIN_LOCKOUT_MS;
+            self.failures = 0;
+            self.window_start = now;
+        }
+    }
+
+    fn is_stale(&self, now: u64) -> bool {
+        now >= self.locked_until && now.saturating_sub(self.window_start) > PIN_FAILURE_WINDOW_MS
+    }
+}
+
+/// A guest with a valid token waiting in the lobby for the host
+#[derive(Debug, Clone)]
+pub struct PendingGuest {
+    pub room_id: String,
+    pub display_name: String,
+    pub device_id: Option<String>,
+    pub device_token: Option<String>,
+    /// Invite they came with; a use is counted only once they are let in
+    pub invite_id: Option<String>,
+    pub requested_at: u64,
+}
+
+/// Identity a joining guest ends up with
+#[derive(Debug, Clone)]
+pub struct ClaimedIdentity {
+    pub device_token: String,
+    pub client_id: String,
+    pub display_name: String,
+    pub restored: bool,
+    /// Socket still holding the identity, which the new one replaces
+    pub replaced_socket: Option<String>,
 }
 
 #[derive(Clone)]
@@ -22,6 +223,15 @@ pub struct RoomManager {
     rooms: Arc<RwLock<HashMap<String, RoomMetadata>>>,
     /// Map socket IDs to room IDs for client disconnect tracking
     socket_rooms: Arc<RwLock<HashMap<String, String>>>,
+    /// Joined guests by socket ID
+    guests: Arc<RwLock<HashMap<String, GuestIdentity>>>,
+    /// Guest identities by device token
+    identities: Arc<RwLock<HashMap<String, SavedIdentity>>>,
+    /// Guests waiting for approval by socket ID, which is also the request
+    /// ID the host approves or rejects
+    lobby: Arc<RwLock<HashMap<String, PendingGuest>>>,
+    /// Wrong-PIN counts by client address, and by "room:<id>" for rooms
+    pin_attempts: Arc<RwLock<HashMap<String, PinAttempts>>>,
 }
 
 /// Compare two byte strings without short-circuiting on the first difference.
@@ -45,19 +255,20 @@ impl RoomManager {
         Self {
             rooms: Arc::new(RwLock::new(HashMap::new())),
             socket_rooms: Arc::new(RwLock::new(HashMap::new())),
+            guests: Arc::new(RwLock::new(HashMap::new())),
+            identities: Arc::new(RwLock::new(HashMap::new())),
+            lobby: Arc::new(RwLock::new(HashMap::new())),
+            pin_attempts: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
-    pub fn create_room(&self, room_id: String, host_socket_id: String, join_token_hash: String, host_peer_id: Option<String>) -> Result<(), String> {
+    pub fn create_room(&self, room_id: String, host_socket_id: String, join_token_hash: String, host_key_hash: String, host_peer_id: Option<String>) -> Result<(), String> {
         let mut rooms = self.rooms.write();
         if rooms.contains_key(&room_id) {
             return Err("Room already exists".to_string());
         }
 
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis() as u64;
+        let now = now_millis();
 
         let rid = room_id.clone();
         let hsid = host_socket_id.clone();
@@ -66,33 +277,326 @@ impl RoomManager {
             host_socket_id,
             host_peer_id,
             join_token_hash,
+            host_key_hash,
             created_at: now,
             client_count: 0,
+            banned_devices: HashSet::new(),
+            host_away_since: None,
+            host_grace_secs: DEFAULT_HOST_GRACE_SECS,
+            invites: Vec::new(),
+            lobby: false,
+            capacity: DEFAULT_ROOM_CAPACITY,
+            pin_hash: None,
+            handoff: None,
         });
 
         log::info!("[Signaling] Room created: {} (host_socket: {})", rid, hsid);
         Ok(())
     }
 
+    /// Hand an existing room to a new host socket. The host proves it owns
+    /// the room with the same host key hash it created it with, and only
+    /// once its old socket has gone: a room with its host still connected is
+    /// never taken over.
+    pub fn reclaim_room(&self, room_id: &str, host_socket_id: String, host_key_hash: &str, host_peer_id: Option<String>) -> Result<RoomMetadata, String> {
+        let mut rooms = self.rooms.write();
+        let room = rooms.get_mut(room_id).ok_or("Room not found")?;
+        if room.host_key_hash.is_empty() || !constant_time_eq(host_key_hash.as_bytes(), room.host_key_hash.as_bytes()) {
+            return Err("Room already exists".to_string());
+        }
+        if room.host_away_since.is_none() {
+            return Err(HOST_STILL_CONNECTED.to_string());
+        }
+        room.host_socket_id = host_socket_id;
+        room.host_peer_id = host_peer_id;
+        room.host_away_since = None;
+        log::info!("[Signaling] Host reclaimed room {}", room_id);
+        Ok(room.clone())
+    }
+
+    pub fn set_host_grace(&self, room_id: &str, secs: u64) {
+        if let Some(room) = self.rooms.write().get_mut(room_id) {
+            room.host_grace_secs = secs.min(MAX_HOST_GRACE_SECS);
+        }
+    }
+
+    /// Put the room in the "host away" state. Returns when that started, to
+    /// hand to `expire_host` once the grace period is over.
+    pub fn mark_host_away(&self, room_id: &str, now: u64) -> Option<u64> {
+        let mut rooms = self.rooms.write();
+        let room = rooms.get_mut(room_id)?;
+        room.host_away_since = Some(now);
+        Some(now)
+    }
+
+    /// Delete the room if its host is still away since `away_since`, i.e.
+    /// did not come back during the grace period. Returns whether it did.
+    pub fn expire_host(&self, room_id: &str, away_since: u64) -> bool {
+        let still_away = self.rooms.read()
+            .get(room_id)
+            .is_some_and(|r| r.host_away_since == Some(away_since));
+        if still_away {
+            self.delete_room(room_id);
+        }
+        still_away
+    }
+
     pub fn get_room(&self, room_id: &str) -> Option<RoomMetadata> {
         self.rooms.read().get(room_id).cloned()
     }
 
-    pub fn verify_room(&self, room_id: &str, join_token: &str) -> Result<RoomMetadata, String> {
-        let room = self.get_room(room_id).ok_or("Room not found")?;
+    /// Check a joining guest's token. Guests come in on an invite, which has
+    /// to be unexpired and not used up, and admitting them counts as a use.
+    /// The room's own join token is accepted too; it stays with the host
+    /// and is no longer put in any QR code.
+    ///
+    /// A room with a lobby skips the full-room check and the invite use here;
+    /// the guest only waits, and `approve_guest` checks and counts when it
+    /// lets them in. The invite's id is returned for that. Past the
+    /// host's capacity but below `MAX_CLIENTS_PER_ROOM`, guests still get in
+    /// as spectators (see `RoomState::is_at_capacity`).
+    pub fn verify_room(&self, room_id: &str, join_token: &str) -> Result<(RoomMetadata, Option<String>), String> {
+        let mut rooms = self.rooms.write();
+        let room = rooms.get_mut(room_id).ok_or("Room not found")?;
 
         let token_hash = hash_token(join_token);
-        if !constant_time_eq(token_hash.as_bytes(), room.join_token_hash.as_bytes()) {
-            return Err("Invalid token".to_string());
-        }
+        let invite = if constant_time_eq(token_hash.as_bytes(), room.join_token_hash.as_bytes()) {
+            None
+        } else {
+            let now = now_millis();
+            let invite = room.invites.iter()
+                .position(|i| constant_time_eq(token_hash.as_bytes(), i.token_hash.as_bytes()))
+                .ok_or("Invalid token")?;
+            if let Some(reason) = room.invites[invite].refusal(now) {
+                return Err(reason.to_string());
+            }
+            Some(invite)
+        };
 
+        if room.lobby {
+            let invite_id = invite.map(|i| room.invites[i].invite_id.clone());
+            return Ok((room.clone(), invite_id));
+        }
         if room.client_count >= MAX_CLIENTS_PER_ROOM {
             return Err("Room is full".to_string());
         }
+        if let Some(invite) = invite {
+            room.invites[invite].uses += 1;
+        }
+        Ok((room.clone(), None))
+    }
+
+    /// Check a PIN typed by a guest without a QR code. With `room_id` it has
+    /// to be that room's PIN; without, the guest is after whichever room has
+    /// this PIN, and gets it back. Wrong PINs count against `client` (its
+    /// address), which is checked before any room is looked at, and against
+    /// every room the PIN was tried on. Past the limits PIN entry is locked
+    /// for a while, even for the right PIN.
+    pub fn verify_pin(&self, room_id: Option<&str>, pin: &str, client: &str, now: u64) -> Result<RoomMetadata, String> {
+        let locked = |left: u64| format!("Too many wrong PINs. Try again in {} min", left.div_ceil(60_000));
+        let mut attempts = self.pin_attempts.write();
+        attempts.retain(|_, a| !a.is_stale(now));
+        if let Some(left) = attempts.get(client).and_then(|a| a.lockout_left(now)) {
+            return Err(locked(left));
+        }
+
+        let candidates: Vec<RoomMetadata> = match room_id {
+            Some(room_id) => {
+                let room = self.get_room(room_id).ok_or("Room not found")?;
+                if room.pin_hash.is_none() {
+                    return Err("This room does not take PINs".to_string());
+                }
+                vec![room]
+            }
+            None => self.rooms.read().values().filter(|r| r.pin_hash.is_some()).cloned().collect(),
+        };
+        let room_key = |room: &RoomMetadata| format!("room:{}", room.room_id);
+        let room_lockout = |attempts: &HashMap<String, PinAttempts>, room: &RoomMetadata| {
+            attempts.get(&room_key(room)).and_then(|a| a.lockout_left(now))
+        };
+        // A locked room cannot be found by PIN at all
+        let open: Vec<&RoomMetadata> = candidates.iter().filter(|r| room_lockout(&attempts, r).is_none()).collect();
+        if open.is_empty() {
+            if let Some(left) = candidates.iter().filter_map(|r| room_lockout(&attempts, r)).max() {
+                return Err(locked(left));
+            }
+        }
+
+        let digits: String = pin.chars().filter(char::is_ascii_digit).collect();
+        let pin_hash = hash_token(&digits);
+        let matched = open.iter().find(|r| {
+            r.pin_hash.as_deref().is_some_and(|h| constant_time_eq(pin_hash.as_bytes(), h.as_bytes()))
+        });
+        let Some(room) = matched.map(|r| (*r).clone()) else {
+            attempts.entry(client.to_string()).or_default().fail(PIN_MAX_FAILURES, now);
+            for room in &open {
+                attempts.entry(room_key(room)).or_default().fail(PIN_ROOM_MAX_FAILURES, now);
+            }
+            log::warn!("[Signaling] Wrong PIN for {} from {}", room_id.unwrap_or("any room"), client);
+            return Err("Wrong PIN".to_string());
+        };
+        attempts.remove(client);
+        drop(attempts);
+
+        if !room.lobby && room.client_count >= MAX_CLIENTS_PER_ROOM {
+            return Err("Room is full".to_string());
+        }
+        Ok(room)
+    }
+
+    /// Offer the room to another host; `None` withdraws the offer
+    pub fn offer_handoff(&self, room_id: &str, code_hash: Option<String>, now: u64) {
+        if let Some(room) = self.rooms.write().get_mut(room_id) {
+            room.handoff = code_hash.map(|code_hash| HandoffOffer { code_hash, expires_at: now + HANDOFF_TTL_MS });
+        }
+    }
+
+    /// Find the room a hand-off code is for, taking it off offer. Wrong codes
+    /// count against `client` the way wrong PINs do.
+    pub fn claim_handoff(&self, code: &str, client: &str, now: u64) -> Result<RoomMetadata, String> {
+        let key = format!("handoff:{}", client);
+        {
+            let mut attempts = self.pin_attempts.write();
+            attempts.retain(|_, a| !a.is_stale(now));
+            if let Some(left) = attempts.get(&key).and_then(|a| a.lockout_left(now)) {
+                return Err(format!("Too many wrong codes. Try again in {} min", left.div_ceil(60_000)));
+            }
+        }
+
+        let code: String = code.chars().filter(char::is_ascii_alphanumeric).collect();
+        let code_hash = hash_token(&code.to_ascii_uppercase());
+        let claimed = self.rooms.write().values_mut()
+            .find(|r| r.handoff.as_ref().is_some_and(|offer| {
+                now < offer.expires_at && constant_time_eq(offer.code_hash.as_bytes(), code_hash.as_bytes())
+            }))
+            .map(|room| {
+                room.handoff = None;
+                room.clone()
+            });
+
+        match claimed {
+            Some(room) => {
+                self.pin_attempts.write().remove(&key);
+                Ok(room)
+            }
+            None => {
+                self.pin_attempts.write().entry(key).or_default().fail(PIN_MAX_FAILURES, now);
+                log::warn!("[Signaling] Wrong hand-off code from {}", client);
+                Err("Wrong or expired hand-off code".to_string())
+            }
+        }
+    }
+
+    pub fn set_pin(&self, room_id: &str, pin_hash: Option<String>) {
+        if let Some(room) = self.rooms.write().get_mut(room_id) {
+            room.pin_hash = pin_hash;
+        }
+    }
 
+    /// Let a guest holding a live device token back in without its invite,
+    /// which may have run out since it was used
+    pub fn verify_rejoin(&self, room_id: &str) -> Result<RoomMetadata, String> {
+        let room = self.get_room(room_id).ok_or("Room not found")?;
+        if room.client_count >= MAX_CLIENTS_PER_ROOM {
+            return Err("Room is full".to_string());
+        }
         Ok(room)
     }
 
+    pub fn set_capacity(&self, room_id: &str, capacity: usize) {
+        if let Some(room) = self.rooms.write().get_mut(room_id) {
+            room.capacity = capacity.clamp(1, MAX_CLIENTS_PER_ROOM);
+        }
+    }
+
+    pub fn set_lobby(&self, room_id: &str, enabled: bool) {
+        if let Some(room) = self.rooms.write().get_mut(room_id) {
+            room.lobby = enabled;
+        }
+    }
+
+    /// Put a guest whose token checked out in the lobby, unless it is full
+    pub fn park_guest(&self, socket_id: &str, guest: PendingGuest) -> Result<(), String> {
+        let mut lobby = self.lobby.write();
+        if lobby.values().filter(|g| g.room_id == guest.room_id).count() >= MAX_LOBBY_PER_ROOM {
+            return Err("Too many guests are waiting to get in; try again later".to_string());
+        }
+        lobby.insert(socket_id.to_string(), guest);
+        Ok(())
+    }
+
+    /// Guests waiting in a room's lobby, oldest first, with their request IDs
+    pub fn pending_guests(&self, room_id: &str) -> Vec<(String, PendingGuest)> {
+        let mut pending: Vec<_> = self.lobby.read().iter()
+            .filter(|(_, g)| g.room_id == room_id)
+            .map(|(sid, g)| (sid.clone(), g.clone()))
+            .collect();
+        pending.sort_by_key(|(_, g)| g.requested_at);
+        pending
+    }
+
+    /// Take a guest out of the lobby to be let in, if the room has space,
+    /// counting a use of the invite they came with. A full room leaves them
+    /// waiting, as does an invite that ran out or was revoked meanwhile.
+    pub fn approve_guest(&self, room_id: &str, request_id: &str) -> Result<(PendingGuest, RoomMetadata), String> {
+        let mut rooms = self.rooms.write();
+        let room = rooms.get_mut(room_id).ok_or("Room not found")?;
+        let mut lobby = self.lobby.write();
+        let guest = lobby.get(request_id)
+            .filter(|g| g.room_id == room_id)
+            .ok_or("No such guest waiting")?;
+        if room.client_count >= MAX_CLIENTS_PER_ROOM {
+            return Err("Room is full".to_string());
+        }
+        if let Some(invite_id) = guest.invite_id.as_deref() {
+            let invite = room.invites.iter_mut()
+                .find(|i| i.invite_id == invite_id)
+                .ok_or("The invite they came with was revoked")?;
+            if let Some(reason) = invite.refusal(now_millis()) {
+                return Err(reason.to_string());
+            }
+            invite.uses += 1;
+        }
+        let guest = lobby.remove(request_id).ok_or("No such guest waiting")?;
+        Ok((guest, room.clone()))
+    }
+
+    /// Drop a lobby request, whether rejected or abandoned. Returns the
+    /// room it was waiting for.
+    pub fn remove_pending(&self, request_id: &str) -> Option<String> {
+        self.lobby.write().remove(request_id).map(|g| g.room_id)
+    }
+
+    pub fn add_invite(&self, room_id: &str, invite: Invite) -> Result<(), String> {
+        let mut rooms = self.rooms.write();
+        let room = rooms.get_mut(room_id).ok_or("Room not found")?;
+        if room.invites.iter().any(|i| i.invite_id == invite.invite_id) {
+            return Err("Invite already exists".to_string());
+        }
+        log::info!("[Signaling] Invite {} added to room {}", invite.invite_id, room_id);
+        room.invites.push(invite);
+        Ok(())
+    }
+
+    /// Drop an invite so its token stops working. Guests who already joined
+    /// with it stay.
+    pub fn revoke_invite(&self, room_id: &str, invite_id: &str) -> bool {
+        let mut rooms = self.rooms.write();
+        let Some(room) = rooms.get_mut(room_id) else {
+            return false;
+        };
+        let before = room.invites.len();
+        room.invites.retain(|i| i.invite_id != invite_id);
+        room.invites.len() != before
+    }
+
+    pub fn list_invites(&self, room_id: &str) -> Vec<Invite> {
+        self.rooms.read()
+            .get(room_id)
+            .map(|r| r.invites.clone())
+            .unwrap_or_default()
+    }
+
     pub fn add_client(&self, room_id: &str) {
         if let Some(room) = self.rooms.write().get_mut(room_id) {
             room.client_count += 1;
@@ -108,6 +612,8 @@ impl RoomManager {
     }
 
     pub fn delete_room(&self, room_id: &str) {
+        self.identities.write().retain(|_, id| id.room_id != room_id);
+        self.lobby.write().retain(|_, g| g.room_id != room_id);
         if self.rooms.write().remove(room_id).is_some() {
             log::info!("[Signaling] Room deleted: {}", room_id);
         }
@@ -119,9 +625,12 @@ impl RoomManager {
             .cloned()
     }
 
+    /// The room a guest lands in when its link names none: the oldest one,
+    /// i.e. the main stage when the host runs several
     pub fn get_first_active_room(&self) -> Option<RoomMetadata> {
         self.rooms.read().values()
-            .find(|r| r.client_count < MAX_CLIENTS_PER_ROOM)
+            .filter(|r| r.client_count < MAX_CLIENTS_PER_ROOM)
+            .min_by_key(|r| r.created_at)
             .cloned()
     }
 
@@ -139,6 +648,142 @@ impl RoomManager {
     pub fn remove_socket_room(&self, socket_id: &str) {
         self.socket_rooms.write().remove(socket_id);
     }
+
+    /// Record who a joined guest socket is
+    pub fn set_guest(&self, socket_id: &str, guest: GuestIdentity) {
+        self.guests.write().insert(socket_id.to_string(), guest);
+    }
+
+    pub fn get_guest(&self, socket_id: &str) -> Option<GuestIdentity> {
+        self.guests.read().get(socket_id).cloned()
+    }
+
+    pub fn remove_guest(&self, socket_id: &str) {
+        self.guests.write().remove(socket_id);
+    }
+
+    /// Give a joining guest its identity. A device token issued for this room
+    /// whose guest is still connected or left within `IDENTITY_GRACE_MS`
+    /// restores the same client id and display name; anything else gets a
+    /// fresh identity and token.
+    pub fn claim_identity(&self, room_id: &str, device_token: Option<&str>, display_name: &str, now: u64) -> ClaimedIdentity {
+        let mut identities = self.identities.write();
+        identities.retain(|_, id| !matches!(id.left_at, Some(t) if now.saturating_sub(t) > IDENTITY_GRACE_MS));
+
+        if let Some(token) = device_token {
+            if let Some(saved) = identities.get_mut(token).filter(|id| id.room_id == room_id) {
+                saved.left_at = None;
+                let replaced_socket = self.guests.read().iter()
+                    .find(|(_, g)| g.device_token.as_deref() == Some(token))
+                    .map(|(sid, _)| sid.clone());
+                return ClaimedIdentity {
+                    device_token: token.to_string(),
+                    client_id: saved.client_id.clone(),
+                    display_name: saved.display_name.clone(),
+                    restored: true,
+                    replaced_socket,
+                };
+            }
+        }
+
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        let client_id = uuid::Uuid::new_v4().to_string();
+        identities.insert(token.clone(), SavedIdentity {
+            room_id: room_id.to_string(),
+            client_id: client_id.clone(),
+            display_name: display_name.to_string(),
+            left_at: None,
+        });
+        ClaimedIdentity {
+            device_token: token,
+            client_id,
+            display_name: display_name.to_string(),
+            restored: false,
+            replaced_socket: None,
+        }
+    }
+
+    /// Whether this device token still holds an identity in the room
+    pub fn has_identity(&self, room_id: &str, device_token: &str, now: u64) -> bool {
+        self.identities.read()
+            .get(device_token)
+            .is_some_and(|id| id.room_id == room_id
+                && !matches!(id.left_at, Some(t) if now.saturating_sub(t) > IDENTITY_GRACE_MS))
+    }
+
+    /// Keep a guest's new display name, so a reconnect restores it rather
+    /// than the name they joined with
+    pub fn rename_identity(&self, room_id: &str, client_id: &str, display_name: &str) {
+        if let Some(saved) = self.identities.write().values_mut()
+            .find(|id| id.room_id == room_id && id.client_id == client_id)
+        {
+            saved.display_name = display_name.to_string();
+        }
+    }
+
+    /// Start the grace window for an identity whose socket went away
+    pub fn release_identity(&self, device_token: &str, now: u64) {
+        if let Some(saved) = self.identities.write().get_mut(device_token) {
+            saved.left_at = Some(now);
+        }
+    }
+
+    /// Whether the host banned this device from the room
+    pub fn is_banned(&self, room_id: &str, device_id: Option<&str>) -> bool {
+        let Some(device_id) = device_id else {
+            return false;
+        };
+        self.rooms.read()
+            .get(room_id)
+            .is_some_and(|r| r.banned_devices.contains(device_id))
+    }
+
+    /// Find the socket of the guest in `room_id` with this PeerJS id and, if
+    /// `ban` is set, ban its device for the rest of the session. Returns the
+    /// socket ID to disconnect.
+    pub fn kick_guest(&self, room_id: &str, peer_id: &str, ban: bool) -> Option<String> {
+        let (socket_id, device_id, device_token) = {
+            let socket_rooms = self.socket_rooms.read();
+            let guests = self.guests.read();
+            let (socket_id, guest) = guests.iter().find(|(sid, g)| {
+                g.peer_id.as_deref() == Some(peer_id)
+                    && socket_rooms.get(*sid).map(String::as_str) == Some(room_id)
+            })?;
+            (socket_id.clone(), guest.device_id.clone(), guest.device_token.clone())
+        };
+
+        // A kicked guest comes back, if at all, as a stranger
+        if let Some(token) = device_token {
+            self.identities.write().remove(&token);
+        }
+
+        if ban {
+            match device_id {
+                Some(device_id) => {
+                    if let Some(room) = self.rooms.write().get_mut(room_id) {
+                        room.banned_devices.insert(device_id);
+                    }
+                }
+                None => log::warn!("[Signaling] Guest {} sent no device id; kicked but not banned", socket_id),
+            }
+        }
+        Some(socket_id)
+    }
+
+    /// Kick a guest and close their relay socket, so they cannot renegotiate.
+    /// A peer that is not in `room_id` is left alone.
+    pub fn kick_guest_from(&self, peers: &PeerRegistry, room_id: &str, peer_id: &str, ban: bool) -> Option<String> {
+        let socket_id = self.kick_guest(room_id, peer_id, ban)?;
+        peers.disconnect(peer_id);
+        Some(socket_id)
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
 }
 
 fn hash_token(token: &str) -> String {
@@ -156,24 +801,158 @@ pub struct CreateRoomPayload {
     pub room_id: String,
     #[serde(rename = "joinTokenHash")]
     pub join_token_hash: String,
+    /// Hash of the host's own secret, to reclaim the room with later
+    #[serde(rename = "hostKeyHash")]
+    pub host_key_hash: String,
     #[serde(rename = "hostPeerId")]
     pub host_peer_id: Option<String>,
+    /// How long guests wait for the host to come back before the room closes
+    #[serde(rename = "hostGraceSecs")]
+    pub host_grace_secs: Option<u64>,
+    /// Seated guests before later ones come in as spectators
+    pub capacity: Option<usize>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct JoinRoomPayload {
     #[serde(rename = "roomId")]
     pub room_id: Option<String>,
-    #[serde(rename = "joinToken")]
+    /// Empty when the guest typed a PIN instead
+    #[serde(rename = "joinToken", default)]
     pub join_token: String,
+    /// PIN the host shows, for guests who cannot scan the QR code
+    pub pin: Option<String>,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    #[serde(rename = "deviceId")]
+    pub device_id: Option<String>,
+    /// Token from an earlier JOIN_SUCCESS, to reclaim that identity
+    #[serde(rename = "deviceToken")]
+    pub device_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KickClientPayload {
+    #[serde(rename = "peerId")]
+    pub peer_id: String,
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub ban: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OfferHandoffPayload {
+    /// SHA-256 of the code the new host will type; `null` withdraws the offer
+    #[serde(rename = "codeHash")]
+    pub code_hash: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClaimHandoffPayload {
+    pub code: String,
+    /// Invite link on the new host that the room's guests are sent to
+    #[serde(rename = "hostUrl")]
+    pub host_url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HandoffPackagePayload {
+    pub room: HandoffPackage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HostMovedPayload {
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetPinPayload {
+    /// SHA-256 of the new PIN; `null` turns PIN entry off
+    #[serde(rename = "pinHash")]
+    pub pin_hash: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetCapacityPayload {
+    pub capacity: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CapacityUpdatedPayload {
+    pub capacity: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetLobbyPayload {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LobbyDecisionPayload {
+    #[serde(rename = "requestId")]
+    pub request_id: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LobbyEntry {
+    #[serde(rename = "requestId")]
+    pub request_id: String,
     #[serde(rename = "displayName")]
     pub display_name: String,
+    #[serde(rename = "requestedAt")]
+    pub requested_at: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LobbyUpdatedPayload {
+    pub enabled: bool,
+    pub pending: Vec<LobbyEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInvitePayload {
+    /// Chosen by the host, which keeps the matching token to show in a QR code
+    #[serde(rename = "inviteId")]
+    pub invite_id: String,
+    #[serde(rename = "tokenHash")]
+    pub token_hash: String,
+    pub label: Option<String>,
+    #[serde(rename = "expiresInSecs")]
+    pub expires_in_secs: Option<u64>,
+    #[serde(rename = "maxUses")]
+    pub max_uses: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevokeInvitePayload {
+    #[serde(rename = "inviteId")]
+    pub invite_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InvitesUpdatedPayload {
+    pub invites: Vec<Invite>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct RoomCreatedPayload {
     #[serde(rename = "roomId")]
     pub room_id: String,
+    /// The room already existed and this host took it back
+    pub reclaimed: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HostAwayPayload {
+    #[serde(rename = "graceSecs")]
+    pub grace_secs: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HostReconnectedPayload {
+    #[serde(rename = "hostPeerId")]
+    pub host_peer_id: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -182,6 +961,21 @@ pub struct JoinSuccessPayload {
     pub room_id: String,
     #[serde(rename = "hostPeerId")]
     pub host_peer_id: String,
+    /// Present this with the next JOIN_ROOM to come back as the same guest
+    #[serde(rename = "deviceToken")]
+    pub device_token: String,
+    /// The guest's stable id; it opens its `Peer` with this as its PeerJS id
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    /// Whether the device token restored an earlier identity
+    pub restored: bool,
+    /// The host is reconnecting; wait for HOST_RECONNECTED before connecting
+    #[serde(rename = "hostAway")]
+    pub host_away: bool,
+    /// The room was at capacity: the guest can watch but not send commands
+    pub spectator: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -195,6 +989,12 @@ pub struct JoinRejectedPayload {
     pub reason: String,
 }
 
+#[derive(Debug, Serialize)]
+pub struct KickedPayload {
+    pub reason: String,
+    pub banned: bool,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ClientJoinedPayload {
     #[serde(rename = "clientId")]
@@ -211,21 +1011,165 @@ pub struct ClientLeftPayload {
     pub client_id: String,
 }
 
-// Socket handler
+/// Let a guest into the room: give it its identity, tell the host and the
+/// app, and send JOIN_SUCCESS. `socket` is the guest's own socket.
+fn admit_guest(socket: &SocketRef, state: &RoomManager, app: &AppHandle, room: RoomMetadata, guest: PendingGuest) {
+    let room_id = guest.room_id;
+    let identity = state.claim_identity(
+        &room_id,
+        guest.device_token.as_deref(),
+        &guest.display_name,
+        now_millis(),
+    );
+
+    // The same guest on a fresh socket: retire the old one
+    // quietly so its disconnect does not report them gone
+    if let Some(old_socket_id) = &identity.replaced_socket {
+        state.remove_socket_room(old_socket_id);
+        state.remove_guest(old_socket_id);
+        state.remove_client(&room_id);
+        let _ = socket.within(old_socket_id.clone()).disconnect();
+    }
+
+    // Past capacity, guests still get in but only to watch. The room state
+    // counts the seats, since that is where the host seats spectators.
+    let spectator = crate::commands::is_at_capacity(app, &room_id, room.capacity, &identity.client_id);
+
+    let _ = socket.join(room_id.clone());
+    state.add_client(&room_id);
+
+    // Store socket-to-room mapping
+    state.set_socket_room(&socket.id.to_string(), &room_id);
+    state.set_guest(&socket.id.to_string(), GuestIdentity {
+        peer_id: Some(identity.client_id.clone()),
+        device_id: guest.device_id,
+        device_token: Some(identity.device_token.clone()),
+    });
+
+    // Notify host. The client id doubles as the guest's
+    // PeerJS id (the guest opens its `Peer` with it), so
+    // it matches `conn.peer` on the host's DataConnection.
+    let host_socket_id = room.host_socket_id.clone();
+    let host_peer_id = room.host_peer_id.unwrap_or_else(|| room.host_socket_id.clone());
+    let _ = socket.to(host_socket_id.clone()).emit("CLIENT_JOINED", ClientJoinedPayload {
+        client_id: identity.client_id.clone(),
+        display_name: identity.display_name.clone(),
+        peer_id: identity.client_id.clone(),
+    });
+    // Use counts moved
+    let _ = socket.to(host_socket_id).emit("INVITES_UPDATED", InvitesUpdatedPayload {
+        invites: room.invites,
+    });
+
+    crate::commands::client_joined(app, &room_id, ConnectedClient {
+        id: identity.client_id.clone(),
+        display_name: identity.display_name.clone(),
+        connected_at: now_millis() as i64,
+        overflow: spectator,
+        device_token: Some(identity.device_token.clone()),
+        verified: false,
+    });
+
+    // Confirm to client
+    let _ = socket.emit("JOIN_SUCCESS", JoinSuccessPayload {
+        room_id: room_id.clone(),
+        host_peer_id,
+        device_token: identity.device_token,
+        client_id: identity.client_id,
+        display_name: identity.display_name,
+        restored: identity.restored,
+        host_away: room.host_away_since.is_some(),
+        spectator,
+    });
+
+    log::info!("[Signaling] Client {} joined room {}{}", socket.id, room_id, if spectator { " as a spectator" } else { "" });
+}
+
+fn lobby_payload(state: &RoomManager, room_id: &str) -> LobbyUpdatedPayload {
+    LobbyUpdatedPayload {
+        enabled: state.get_room(room_id).is_some_and(|r| r.lobby),
+        pending: state.pending_guests(room_id).into_iter()
+            .map(|(request_id, g)| LobbyEntry {
+                request_id,
+                display_name: g.display_name,
+                requested_at: g.requested_at,
+            })
+            .collect(),
+    }
+}
+
+/// Where a socket connects from, to count wrong PINs against. Falls back to
+/// the socket ID, which leaves only the room-wide limit in force.
+fn client_address(socket: &SocketRef) -> String {
+    socket.req_parts().extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| address_key(addr.ip()))
+        .unwrap_or_else(|| socket.id.to_string())
+}
+
+/// The address a client's PIN guesses are charged to. An IPv6 client usually
+/// holds a whole /64 and can pick a new address in it for every guess, so it
+/// is keyed by the prefix.
+fn address_key(ip: IpAddr) -> String {
+    // The dual-stack listener sees IPv4 guests as ::ffff:a.b.c.d
+    match ip.to_canonical() {
+        IpAddr::V4(v4) => v4.to_string(),
+        IpAddr::V6(v6) => {
+            let s = v6.segments();
+            format!("{:x}:{:x}:{:x}:{:x}::/64", s[0], s[1], s[2], s[3])
+        }
+    }
+}
+
+/// Turn away everyone still waiting in the lobby of a room that is closing
+fn close_lobby(socket: &SocketRef, state: &RoomManager, room_id: &str) {
+    for (request_id, _) in state.pending_guests(room_id) {
+        let _ = socket.within(request_id).emit("JOIN_REJECTED", JoinRejectedPayload {
+            reason: "The host closed the room".to_string(),
+        });
+    }
+}
+
+// Socket handler
 pub async fn on_connect(socket: SocketRef, _state: State<RoomManager>) {
     log::info!("[Signaling] Client connected: {}", socket.id);
 
-    // Host creates a room
+    // Host creates a room, or takes back its own room after a reconnect
     socket.on("CREATE_ROOM", |socket: SocketRef, Data::<CreateRoomPayload>(data), state: State<RoomManager>| async move {
         let room_id = data.room_id.clone();
-        match state.create_room(room_id.clone(), socket.id.to_string(), data.join_token_hash, data.host_peer_id) {
-            Ok(_) => {
+        let result = if state.get_room(&room_id).is_some() {
+            state.reclaim_room(&room_id, socket.id.to_string(), &data.host_key_hash, data.host_peer_id.clone())
+                .map(|_| true)
+        } else {
+            state.create_room(room_id.clone(), socket.id.to_string(), data.join_token_hash, data.host_key_hash, data.host_peer_id.clone())
+                .map(|_| false)
+        };
+        match result {
+            Ok(reclaimed) => {
+                if let Some(secs) = data.host_grace_secs {
+                    state.set_host_grace(&room_id, secs);
+                }
+                if let Some(capacity) = data.capacity {
+                    state.set_capacity(&room_id, capacity);
+                }
                 let _ = socket.join(room_id.clone());
-                let _ = socket.emit("ROOM_CREATED", RoomCreatedPayload { room_id });
+                if reclaimed {
+                    // Guests drop their channel to the old host peer and dial this one
+                    let host_peer_id = data.host_peer_id.unwrap_or_else(|| socket.id.to_string());
+                    let _ = socket.to(room_id.clone()).emit("HOST_RECONNECTED", HostReconnectedPayload { host_peer_id });
+                }
+                let invites = state.list_invites(&room_id);
+                let lobby = lobby_payload(&state, &room_id);
+                let capacity = state.get_room(&room_id).map_or(DEFAULT_ROOM_CAPACITY, |r| r.capacity);
+                let _ = socket.emit("ROOM_CREATED", RoomCreatedPayload { room_id, reclaimed });
+                let _ = socket.emit("INVITES_UPDATED", InvitesUpdatedPayload { invites });
+                let _ = socket.emit("LOBBY_UPDATED", lobby);
+                let _ = socket.emit("CAPACITY_UPDATED", CapacityUpdatedPayload { capacity });
             }
             Err(e) => {
+                let code = if e == HOST_STILL_CONNECTED { "HOST_STILL_CONNECTED" } else { "CREATE_ROOM_FAILED" };
                 let _ = socket.emit("ERROR", ErrorPayload {
-                    code: "CREATE_ROOM_FAILED".to_string(),
+                    code: code.to_string(),
                     message: e,
                 });
             }
@@ -233,70 +1177,83 @@ pub async fn on_connect(socket: SocketRef, _state: State<RoomManager>) {
     });
 
     // Client joins a room
-    socket.on("JOIN_ROOM", |socket: SocketRef, Data::<JoinRoomPayload>(data), state: State<RoomManager>| async move {
-        // Resolve target room ID
-        let target_room_id = if let Some(rid) = &data.room_id {
-            if rid.is_empty() || rid == "default" {
-                // Standalone mode: find first active room
-                state.get_first_active_room().map(|r| r.room_id)
-            } else {
-                Some(rid.clone())
-            }
-        } else {
-            state.get_first_active_room().map(|r| r.room_id)
+    socket.on("JOIN_ROOM", |socket: SocketRef, Data::<JoinRoomPayload>(data), state: State<RoomManager>, app: State<AppHandle>| async move {
+        // Resolve target room ID. Links name their room; a guest with only a
+        // PIN goes to the room it belongs to, which checking the PIN finds
+        // (so wrong guesses are charged before any room is revealed), and
+        // anyone else to the main room.
+        let named_room = data.room_id.clone().filter(|rid| !rid.is_empty() && rid != "default");
+        let pin = data.pin.clone().filter(|_| data.join_token.is_empty());
+        let mut pin_room = None;
+        let target_room_id = match (named_room, pin.as_deref()) {
+            (Some(room_id), _) => Some(room_id),
+            (None, Some(pin)) => match state.verify_pin(None, pin, &client_address(&socket), now_millis()) {
+                Ok(room) => {
+                    let room_id = room.room_id.clone();
+                    pin_room = Some(room);
+                    Some(room_id)
+                }
+                Err(reason) => {
+                    let _ = socket.emit("JOIN_REJECTED", JoinRejectedPayload { reason });
+                    return;
+                }
+            },
+            (None, None) => state.get_first_active_room().map(|r| r.room_id),
         };
 
         match target_room_id {
             Some(room_id) => {
-                // Always verify the join token.
-                //
-                // This previously skipped verification whenever room_id was
-                // empty or "default", which is exactly what the QR-code guest
-                // client sent — so the token was never actually checked and any
-                // device on the LAN could join. Room *resolution* still falls
-                // back to the first active room (a guest scanning the QR does
-                // not know the room id), but resolution and authorisation are
-                // now separate: you may be pointed at the room without being
-                // let into it.
-                let room_res = state.verify_room(&room_id, &data.join_token);
+                // Resolving the room above is not letting the guest in; every
+                // guest is checked, in this order:
+                // - a banned device is turned away;
+                // - a guest reconnecting with a live device token already got
+                //   in once, so its (possibly single-use) invite is not asked
+                //   again, and it skips the lobby;
+                // - a PIN, already checked if it is what found the room;
+                // - otherwise an invite, or the room's own join token. When
+                //   the room has a lobby an invite's use is counted only once
+                //   the host lets the guest in.
+                let returning = data.device_token.as_deref()
+                    .is_some_and(|t| state.has_identity(&room_id, t, now_millis()));
+                let room_res = if state.is_banned(&room_id, data.device_id.as_deref()) {
+                    Err("You have been removed from this room".to_string())
+                } else if returning {
+                    state.verify_rejoin(&room_id).map(|room| (room, None))
+                } else if let Some(room) = pin_room {
+                    Ok((room, None))
+                } else if let Some(pin) = pin.as_deref() {
+                    state.verify_pin(Some(&room_id), pin, &client_address(&socket), now_millis()).map(|room| (room, None))
+                } else {
+                    state.verify_room(&room_id, &data.join_token)
+                };
 
                 match room_res {
-                    Ok(room) => {
-                        let _ = socket.join(room_id.clone());
-                        state.add_client(&room_id);
-
-                        // Store socket-to-room mapping
-                        state.set_socket_room(&socket.id.to_string(), &room_id);
-
-                        // Notify host
-                        let host_socket_id = room.host_socket_id.clone();
-                        let host_peer_id = room.host_peer_id.unwrap_or_else(|| room.host_socket_id.clone());
-                        let _ = socket.to(host_socket_id).emit("CLIENT_JOINED", ClientJoinedPayload {
-                            client_id: socket.id.to_string(),
+                    Ok((room, invite_id)) if room.lobby && !returning => {
+                        let parked = state.park_guest(&socket.id.to_string(), PendingGuest {
+                            room_id: room_id.clone(),
                             display_name: data.display_name,
-                            // The guest's real PeerJS id does not exist yet at this point in
-                            // the handshake: `remote-ui/index.html` only constructs its `Peer`
-                            // object (and receives an id from the embedded broker) *after*
-                            // JOIN_SUCCESS hands it the host's peer id — see `initPeer()` /
-                            // `peer.on('open', ...)` there. So JOIN_ROOM cannot carry it, and
-                            // this field is filled with the socket.io id as a placeholder.
-                            // In practice this is harmless: the host never reads
-                            // `CLIENT_JOINED.peerId` (grep confirms no consumer in
-                            // apps/host/src), because it identifies connected guests by
-                            // `conn.peer` from the actual WebRTC DataConnection instead
-                            // (`usePeerHost.ts`). If a real consumer of this field is ever
-                            // added, it will need the guest to report its peer id in a
-                            // follow-up message after `Peer` finishes opening, not here.
-                            peer_id: socket.id.to_string(),
+                            device_id: data.device_id,
+                            device_token: data.device_token,
+                            invite_id,
+                            requested_at: now_millis(),
                         });
-
-                        // Confirm to client
-                        let _ = socket.emit("JOIN_SUCCESS", JoinSuccessPayload {
-                            room_id: room_id.clone(),
-                            host_peer_id,
+                        if let Err(reason) = parked {
+                            let _ = socket.emit("JOIN_REJECTED", JoinRejectedPayload { reason });
+                            return;
+                        }
+                        let _ = socket.emit("JOIN_PENDING", ());
+                        let _ = socket.to(room.host_socket_id).emit("LOBBY_UPDATED", lobby_payload(&state, &room_id));
+                        log::info!("[Signaling] Client {} waiting in the lobby of room {}", socket.id, room_id);
+                    }
+                    Ok((room, _)) => {
+                        admit_guest(&socket, &state, &app, room, PendingGuest {
+                            room_id,
+                            display_name: data.display_name,
+                            device_id: data.device_id,
+                            device_token: data.device_token,
+                            invite_id: None,
+                            requested_at: now_millis(),
                         });
-
-                        log::info!("[Signaling] Client {} joined room {}", socket.id, room_id);
                     }
                     Err(e) => {
                         let _ = socket.emit("JOIN_REJECTED", JoinRejectedPayload { reason: e });
@@ -309,28 +1266,282 @@ pub async fn on_connect(socket: SocketRef, _state: State<RoomManager>) {
         }
     });
 
-    // Handle disconnect
-    socket.on_disconnect(|socket: SocketRef, state: State<RoomManager>| async move {
-        log::info!("[Signaling] Client disconnected: {}", socket.id);
+    // Host removes a guest, optionally banning their device for the session
+    socket.on("KICK_CLIENT", |socket: SocketRef, Data::<KickClientPayload>(data), state: State<RoomManager>, peers: State<PeerRegistry>| async move {
+        let Some(room) = state.get_room_by_host_socket(&socket.id.to_string()) else {
+            let _ = socket.emit("ERROR", ErrorPayload {
+                code: "NOT_HOST".to_string(),
+                message: "Only the host can remove guests".to_string(),
+            });
+            return;
+        };
+
+        match state.kick_guest_from(&peers, &room.room_id, &data.peer_id, data.ban) {
+            Some(guest_socket_id) => {
+                let reason = data.reason.unwrap_or_else(|| "You were removed by the host".to_string());
+                let _ = socket.within(guest_socket_id.clone()).emit("KICKED", KickedPayload {
+                    reason,
+                    banned: data.ban,
+                });
+                let _ = socket.within(guest_socket_id).disconnect();
+                log::info!("[Signaling] Host kicked peer {} (ban: {})", data.peer_id, data.ban);
+            }
+            None => {
+                let _ = socket.emit("ERROR", ErrorPayload {
+                    code: "KICK_FAILED".to_string(),
+                    message: "Guest not found".to_string(),
+                });
+            }
+        }
+    });
 
-        // Check if host
+    // Host offers the room to another host, or withdraws the offer
+    socket.on("OFFER_HANDOFF", |socket: SocketRef, Data::<OfferHandoffPayload>(data), state: State<RoomManager>| async move {
+        let Some(room) = state.get_room_by_host_socket(&socket.id.to_string()) else {
+            let _ = socket.emit("ERROR", ErrorPayload {
+                code: "NOT_HOST".to_string(),
+                message: "Only the host can hand off the room".to_string(),
+            });
+            return;
+        };
+        state.offer_handoff(&room.room_id, data.code_hash, now_millis());
+    });
+
+    // Another host takes the room over with the code. It gets the room's
+    // state, and everyone in the room, the old host included, is sent to the
+    // new host's invite link, so guests need not scan anything.
+    socket.on("CLAIM_HANDOFF", |socket: SocketRef, Data::<ClaimHandoffPayload>(data), state: State<RoomManager>, app: State<AppHandle>| async move {
+        let fail = |message: String| {
+            let _ = socket.emit("ERROR", ErrorPayload { code: "HANDOFF_FAILED".to_string(), message });
+        };
+        let url = data.host_url.trim();
+        if url.len() > 2048 || !(url.starts_with("http://") || url.starts_with("https://")) {
+            fail("The new host's link is not valid".to_string());
+            return;
+        }
+        let room = match state.claim_handoff(&data.code, &client_address(&socket), now_millis()) {
+            Ok(room) => room,
+            Err(e) => {
+                fail(e);
+                return;
+            }
+        };
+        let Some(package) = crate::commands::handoff_package(&app, &room.room_id) else {
+            fail("The room is no longer open".to_string());
+            return;
+        };
+
+        log::info!("[Signaling] Room {} handed off to {}", room.room_id, url);
+        let _ = socket.emit("HANDOFF_PACKAGE", HandoffPackagePayload { room: package });
+        let moved = HostMovedPayload { url: url.to_string() };
+        let _ = socket.to(room.room_id.clone()).emit("HOST_MOVED", &moved);
+        for (request_id, _) in state.pending_guests(&room.room_id) {
+            let _ = socket.within(request_id).emit("HOST_MOVED", &moved);
+        }
+        state.delete_room(&room.room_id);
+    });
+
+    // Host sets, changes or clears the room PIN
+    socket.on("SET_PIN", |socket: SocketRef, Data::<SetPinPayload>(data), state: State<RoomManager>| async move {
+        let Some(room) = state.get_room_by_host_socket(&socket.id.to_string()) else {
+            let _ = socket.emit("ERROR", ErrorPayload {
+                code: "NOT_HOST".to_string(),
+                message: "Only the host can set the PIN".to_string(),
+            });
+            return;
+        };
+        state.set_pin(&room.room_id, data.pin_hash);
+    });
+
+    // Host changes how many guests are seated. Guests already in keep their
+    // seat or spectator place.
+    socket.on("SET_CAPACITY", |socket: SocketRef, Data::<SetCapacityPayload>(data), state: State<RoomManager>| async move {
+        let Some(room) = state.get_room_by_host_socket(&socket.id.to_string()) else {
+            let _ = socket.emit("ERROR", ErrorPayload {
+                code: "NOT_HOST".to_string(),
+                message: "Only the host can change the capacity".to_string(),
+            });
+            return;
+        };
+        state.set_capacity(&room.room_id, data.capacity);
+        let capacity = state.get_room(&room.room_id).map_or(DEFAULT_ROOM_CAPACITY, |r| r.capacity);
+        let _ = socket.emit("CAPACITY_UPDATED", CapacityUpdatedPayload { capacity });
+    });
+
+    // Host turns the lobby on or off. Guests already waiting stay there.
+    socket.on("SET_LOBBY", |socket: SocketRef, Data::<SetLobbyPayload>(data), state: State<RoomManager>| async move {
+        let Some(room) = state.get_room_by_host_socket(&socket.id.to_string()) else {
+            let _ = socket.emit("ERROR", ErrorPayload {
+                code: "NOT_HOST".to_string(),
+                message: "Only the host can change the lobby".to_string(),
+            });
+            return;
+        };
+        state.set_lobby(&room.room_id, data.enabled);
+        let _ = socket.emit("LOBBY_UPDATED", lobby_payload(&state, &room.room_id));
+    });
+
+    // Host lets a waiting guest in
+    socket.on("APPROVE_GUEST", |socket: SocketRef, Data::<LobbyDecisionPayload>(data), state: State<RoomManager>, app: State<AppHandle>| async move {
+        let Some(room) = state.get_room_by_host_socket(&socket.id.to_string()) else {
+            let _ = socket.emit("ERROR", ErrorPayload {
+                code: "NOT_HOST".to_string(),
+                message: "Only the host can approve guests".to_string(),
+            });
+            return;
+        };
+
+        let guest_socket = data.request_id.parse::<Sid>().ok()
+            .and_then(|sid| socket.broadcast().get_socket(sid));
+        let result = match guest_socket {
+            Some(guest_socket) => state.approve_guest(&room.room_id, &data.request_id)
+                .map(|(guest, room)| admit_guest(&guest_socket, &state, &app, room, guest)),
+            None => {
+                state.remove_pending(&data.request_id);
+                Err("That guest has left".to_string())
+            }
+        };
+        if let Err(e) = result {
+            let _ = socket.emit("ERROR", ErrorPayload {
+                code: "APPROVE_FAILED".to_string(),
+                message: e,
+            });
+        }
+        let _ = socket.emit("LOBBY_UPDATED", lobby_payload(&state, &room.room_id));
+    });
+
+    // Host turns a waiting guest away
+    socket.on("REJECT_GUEST", |socket: SocketRef, Data::<LobbyDecisionPayload>(data), state: State<RoomManager>| async move {
+        let Some(room) = state.get_room_by_host_socket(&socket.id.to_string()) else {
+            let _ = socket.emit("ERROR", ErrorPayload {
+                code: "NOT_HOST".to_string(),
+                message: "Only the host can reject guests".to_string(),
+            });
+            return;
+        };
+
+        let waiting = state.pending_guests(&room.room_id).iter().any(|(id, _)| *id == data.request_id);
+        if waiting {
+            state.remove_pending(&data.request_id);
+            let reason = data.reason.unwrap_or_else(|| "The host did not let you in".to_string());
+            let _ = socket.within(data.request_id.clone()).emit("JOIN_REJECTED", JoinRejectedPayload { reason });
+            log::info!("[Signaling] Host rejected lobby request {}", data.request_id);
+        }
+        let _ = socket.emit("LOBBY_UPDATED", lobby_payload(&state, &room.room_id));
+    });
+
+    // Host issues an invite; the token itself never reaches the server
+    socket.on("CREATE_INVITE", |socket: SocketRef, Data::<CreateInvitePayload>(data), state: State<RoomManager>| async move {
+        let Some(room) = state.get_room_by_host_socket(&socket.id.to_string()) else {
+            let _ = socket.emit("ERROR", ErrorPayload {
+                code: "NOT_HOST".to_string(),
+                message: "Only the host can create invites".to_string(),
+            });
+            return;
+        };
+
+        let invite = Invite::new(data.invite_id, data.token_hash, data.label, data.expires_in_secs, data.max_uses, now_millis());
+        match state.add_invite(&room.room_id, invite) {
+            Ok(()) => {
+                let _ = socket.emit("INVITES_UPDATED", InvitesUpdatedPayload {
+                    invites: state.list_invites(&room.room_id),
+                });
+            }
+            Err(e) => {
+                let _ = socket.emit("ERROR", ErrorPayload {
+                    code: "CREATE_INVITE_FAILED".to_string(),
+                    message: e,
+                });
+            }
+        }
+    });
+
+    // Host revokes an invite; guests already in the room stay
+    socket.on("REVOKE_INVITE", |socket: SocketRef, Data::<RevokeInvitePayload>(data), state: State<RoomManager>| async move {
+        let Some(room) = state.get_room_by_host_socket(&socket.id.to_string()) else {
+            let _ = socket.emit("ERROR", ErrorPayload {
+                code: "NOT_HOST".to_string(),
+                message: "Only the host can revoke invites".to_string(),
+            });
+            return;
+        };
+
+        if state.revoke_invite(&room.room_id, &data.invite_id) {
+            log::info!("[Signaling] Invite {} revoked", data.invite_id);
+        }
+        let _ = socket.emit("INVITES_UPDATED", InvitesUpdatedPayload {
+            invites: state.list_invites(&room.room_id),
+        });
+    });
+
+    // Host closes the room on purpose: no grace period
+    socket.on("LEAVE_ROOM", |socket: SocketRef, state: State<RoomManager>| async move {
         if let Some(room) = state.get_room_by_host_socket(&socket.id.to_string()) {
-            // Host disconnected
             let _ = socket.to(room.room_id.clone()).emit("HOST_DISCONNECTED", ());
+            close_lobby(&socket, &state, &room.room_id);
             state.delete_room(&room.room_id);
+        }
+    });
+
+    // Handle disconnect
+    socket.on_disconnect(|socket: SocketRef, state: State<RoomManager>, app: State<AppHandle>| async move {
+        log::info!("[Signaling] Client disconnected: {}", socket.id);
+
+        // Check if host. Keep the room for the grace period so a reloaded or
+        // briefly disconnected host can reclaim it; guests wait meanwhile.
+        if let Some(room) = state.get_room_by_host_socket(&socket.id.to_string()) {
+            let grace = room.host_grace_secs;
+            let away_since = if grace > 0 { state.mark_host_away(&room.room_id, now_millis()) } else { None };
+            let Some(away_since) = away_since else {
+                let _ = socket.to(room.room_id.clone()).emit("HOST_DISCONNECTED", ());
+                close_lobby(&socket, &state, &room.room_id);
+                state.delete_room(&room.room_id);
+                return;
+            };
+
+            log::info!("[Signaling] Host of room {} away, waiting {}s", room.room_id, grace);
+            let _ = socket.to(room.room_id.clone()).emit("HOST_AWAY", HostAwayPayload { grace_secs: grace });
+            let manager = state.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(grace)).await;
+                let lobby = manager.pending_guests(&room.room_id);
+                if manager.expire_host(&room.room_id, away_since) {
+                    let _ = socket.to(room.room_id.clone()).emit("HOST_DISCONNECTED", ());
+                    for (request_id, _) in lobby {
+                        let _ = socket.within(request_id).emit("JOIN_REJECTED", JoinRejectedPayload {
+                            reason: "The host closed the room".to_string(),
+                        });
+                    }
+                }
+            });
+            return;
+        }
+
+        // A guest that gave up waiting in the lobby
+        if let Some(room_id) = state.remove_pending(&socket.id.to_string()) {
+            if let Some(room) = state.get_room(&room_id) {
+                let _ = socket.to(room.host_socket_id).emit("LOBBY_UPDATED", lobby_payload(&state, &room_id));
+            }
             return;
         }
 
         // Check if client
         if let Some(room_id) = state.get_socket_room(&socket.id.to_string()) {
+             let guest = state.get_guest(&socket.id.to_string()).unwrap_or_default();
+             let client_id = guest.peer_id.unwrap_or_else(|| socket.id.to_string());
              if let Some(room) = state.get_room(&room_id) {
                  // Notify host
                  let _ = socket.to(room.host_socket_id).emit("CLIENT_LEFT", ClientLeftPayload {
-                     client_id: socket.id.to_string(),
+                     client_id: client_id.clone(),
                  });
                  state.remove_client(&room_id);
              }
+             crate::commands::client_left(&app, &room_id, &client_id);
+             if let Some(token) = &guest.device_token {
+                 state.release_identity(token, now_millis());
+             }
              state.remove_socket_room(&socket.id.to_string());
+             state.remove_guest(&socket.id.to_string());
         }
     });
 }
@@ -340,6 +1551,7 @@ mod tests {
     use super::*;
 
     const TOKEN: &str = "correct-horse-battery-staple";
+    const HOST_KEY: &str = "only-the-host-knows";
 
     fn manager_with_room() -> RoomManager {
         let mgr = RoomManager::new();
@@ -347,6 +1559,7 @@ mod tests {
             "room-1".to_string(),
             "host-socket".to_string(),
             hash_token(TOKEN),
+            hash_token(HOST_KEY),
             Some("host-peer".to_string()),
         )
         .expect("room creation should succeed");
@@ -414,6 +1627,44 @@ mod tests {
         assert!(mgr.verify_room(&resolved.room_id, "").is_err());
     }
 
+    #[test]
+    fn handoff_codes_work_once_and_lock_out_guessers() {
+        let mgr = manager_with_room();
+        mgr.offer_handoff("room-1", Some(hash_token("ABCD2345")), 0);
+
+        for _ in 0..PIN_MAX_FAILURES {
+            assert!(mgr.claim_handoff("ZZZZ9999", "10.0.0.5", 1_000).is_err());
+        }
+        assert!(mgr.claim_handoff("ABCD2345", "10.0.0.5", 2_000).is_err(), "locked out");
+
+        let claimed = mgr.claim_handoff("abcd-2345", "10.0.0.6", 2_000).expect("typed codes are normalised");
+        assert_eq!(claimed.room_id, "room-1");
+        assert!(mgr.claim_handoff("ABCD2345", "10.0.0.6", 2_000).is_err(), "a code works once");
+    }
+
+    #[test]
+    fn handoff_codes_expire() {
+        let mgr = manager_with_room();
+        mgr.offer_handoff("room-1", Some(hash_token("ABCD2345")), 0);
+        assert!(mgr.claim_handoff("ABCD2345", "10.0.0.5", HANDOFF_TTL_MS).is_err());
+
+        mgr.offer_handoff("room-1", Some(hash_token("ABCD2345")), 0);
+        mgr.offer_handoff("room-1", None, 0);
+        assert!(mgr.claim_handoff("ABCD2345", "10.0.0.5", 0).is_err(), "withdrawn offers cannot be claimed");
+    }
+
+    #[test]
+    fn guests_without_a_room_land_in_the_main_room_or_their_pins() {
+        let mgr = manager_with_room();
+        mgr.create_room("side".to_string(), "host-socket-2".to_string(), hash_token(TOKEN), hash_token(HOST_KEY), None).unwrap();
+        mgr.rooms.write().get_mut("side").unwrap().created_at += 1;
+        mgr.set_pin("side", Some(hash_token(PIN)));
+
+        assert_eq!(mgr.get_first_active_room().unwrap().room_id, "room-1");
+        assert_eq!(mgr.verify_pin(None, "042 917", "10.0.0.5", 0).unwrap().room_id, "side");
+        assert!(mgr.verify_pin(None, "000000", "10.0.0.5", 0).is_err());
+    }
+
     #[test]
     fn hash_token_is_stable_and_distinct() {
         assert_eq!(hash_token(TOKEN), hash_token(TOKEN));
@@ -421,4 +1672,411 @@ mod tests {
         // SHA-256 hex
         assert_eq!(hash_token(TOKEN).len(), 64);
     }
+
+    fn join_guest(mgr: &RoomManager, socket_id: &str, peer_id: &str, device_id: &str) {
+        mgr.add_client("room-1");
+        mgr.set_socket_room(socket_id, "room-1");
+        mgr.set_guest(socket_id, GuestIdentity {
+            peer_id: Some(peer_id.to_string()),
+            device_id: Some(device_id.to_string()),
+            device_token: None,
+        });
+    }
+
+    #[test]
+    fn kick_guest_finds_the_socket_by_peer_id() {
+        let mgr = manager_with_room();
+        join_guest(&mgr, "sock-a", "peer-a", "device-a");
+        join_guest(&mgr, "sock-b", "peer-b", "device-b");
+
+        assert_eq!(mgr.kick_guest("room-1", "peer-b", false).as_deref(), Some("sock-b"));
+        assert!(mgr.kick_guest("room-1", "peer-z", false).is_none());
+        assert!(mgr.kick_guest("other-room", "peer-b", false).is_none());
+        assert!(!mgr.is_banned("room-1", Some("device-b")), "a plain kick is not a ban");
+    }
+
+    #[test]
+    fn kicking_a_peer_from_another_room_leaves_its_relay_alone() {
+        let mgr = manager_with_room();
+        mgr.create_room("room-2".to_string(), "host-socket-2".to_string(), hash_token(TOKEN), hash_token(HOST_KEY), None).unwrap();
+        join_guest(&mgr, "sock-a", "peer-a", "device-a");
+        let peers = PeerRegistry::new();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        peers.insert("peer-a".to_string(), tx);
+
+        assert!(mgr.kick_guest_from(&peers, "room-2", "peer-a", false).is_none());
+        assert!(peers.contains("peer-a"));
+        assert!(rx.try_recv().is_err());
+
+        assert_eq!(mgr.kick_guest_from(&peers, "room-1", "peer-a", false).as_deref(), Some("sock-a"));
+        assert!(!peers.contains("peer-a"));
+    }
+
+    #[test]
+    fn banned_devices_stay_out_until_the_room_is_gone() {
+        let mgr = manager_with_room();
+        join_guest(&mgr, "sock-a", "peer-a", "device-a");
+
+        mgr.kick_guest("room-1", "peer-a", true).expect("guest should be found");
+        assert!(mgr.is_banned("room-1", Some("device-a")));
+        assert!(!mgr.is_banned("room-1", Some("device-b")));
+        assert!(!mgr.is_banned("room-1", None));
+
+        mgr.delete_room("room-1");
+        mgr.create_room("room-1".to_string(), "host-socket".to_string(), hash_token(TOKEN), hash_token(HOST_KEY), None).unwrap();
+        assert!(!mgr.is_banned("room-1", Some("device-a")));
+    }
+
+    #[test]
+    fn device_token_restores_identity_within_the_grace_window() {
+        let mgr = manager_with_room();
+        let first = mgr.claim_identity("room-1", None, "Ana", 0);
+        assert!(!first.restored);
+
+        mgr.release_identity(&first.device_token, 1_000);
+        let again = mgr.claim_identity("room-1", Some(&first.device_token), "Someone else", 1_000 + IDENTITY_GRACE_MS);
+        assert!(again.restored);
+        assert_eq!(again.client_id, first.client_id);
+        assert_eq!(again.display_name, "Ana");
+        assert_eq!(again.device_token, first.device_token);
+    }
+
+    #[test]
+    fn a_rename_survives_a_reconnect() {
+        let mgr = manager_with_room();
+        let first = mgr.claim_identity("room-1", None, "Ana", 0);
+        mgr.rename_identity("room-1", &first.client_id, "Ana B");
+        mgr.rename_identity("room-2", &first.client_id, "Not Ana");
+
+        mgr.release_identity(&first.device_token, 1_000);
+        let again = mgr.claim_identity("room-1", Some(&first.device_token), "Ana", 2_000);
+        assert!(again.restored);
+        assert_eq!(again.display_name, "Ana B");
+    }
+
+    #[test]
+    fn device_token_expires_after_the_grace_window() {
+        let mgr = manager_with_room();
+        let first = mgr.claim_identity("room-1", None, "Ana", 0);
+        mgr.release_identity(&first.device_token, 1_000);
+
+        let later = mgr.claim_identity("room-1", Some(&first.device_token), "Ana", 1_001 + IDENTITY_GRACE_MS);
+        assert!(!later.restored);
+        assert_ne!(later.client_id, first.client_id);
+        assert_ne!(later.device_token, first.device_token);
+    }
+
+    #[test]
+    fn device_token_from_another_room_is_not_honoured() {
+        let mgr = manager_with_room();
+        let first = mgr.claim_identity("room-1", None, "Ana", 0);
+        let elsewhere = mgr.claim_identity("room-2", Some(&first.device_token), "Ana", 0);
+        assert!(!elsewhere.restored);
+    }
+
+    #[test]
+    fn reclaiming_a_live_identity_replaces_the_old_socket() {
+        let mgr = manager_with_room();
+        let first = mgr.claim_identity("room-1", None, "Ana", 0);
+        mgr.set_socket_room("sock-old", "room-1");
+        mgr.set_guest("sock-old", GuestIdentity {
+            peer_id: Some(first.client_id.clone()),
+            device_id: None,
+            device_token: Some(first.device_token.clone()),
+        });
+
+        let again = mgr.claim_identity("room-1", Some(&first.device_token), "Ana", 5);
+        assert!(again.restored);
+        assert_eq!(again.replaced_socket.as_deref(), Some("sock-old"));
+    }
+
+    #[test]
+    fn kicked_guests_lose_their_identity() {
+        let mgr = manager_with_room();
+        let first = mgr.claim_identity("room-1", None, "Ana", 0);
+        mgr.add_client("room-1");
+        mgr.set_socket_room("sock-a", "room-1");
+        mgr.set_guest("sock-a", GuestIdentity {
+            peer_id: Some(first.client_id.clone()),
+            device_id: None,
+            device_token: Some(first.device_token.clone()),
+        });
+
+        mgr.kick_guest("room-1", &first.client_id, false).expect("guest should be found");
+        assert!(!mgr.claim_identity("room-1", Some(&first.device_token), "Ana", 1).restored);
+    }
+
+    #[test]
+    fn host_reclaims_its_room_with_its_own_key_once_away() {
+        let mgr = manager_with_room();
+        assert_eq!(
+            mgr.reclaim_room("room-1", "new-socket".to_string(), &hash_token(HOST_KEY), None).unwrap_err(),
+            HOST_STILL_CONNECTED,
+            "a connected host keeps its room"
+        );
+        assert_eq!(mgr.get_room("room-1").unwrap().host_socket_id, "host-socket");
+
+        mgr.mark_host_away("room-1", 1_000);
+        assert!(mgr.reclaim_room("room-1", "new-socket".to_string(), &hash_token("wrong"), None).is_err());
+        assert!(
+            mgr.reclaim_room("room-1", "new-socket".to_string(), &hash_token(TOKEN), None).is_err(),
+            "guests hold the join token, so it cannot reclaim"
+        );
+
+        let room = mgr.reclaim_room("room-1", "new-socket".to_string(), &hash_token(HOST_KEY), Some("new-peer".to_string()))
+            .expect("matching hash should reclaim");
+        assert_eq!(room.host_socket_id, "new-socket");
+        assert_eq!(room.host_peer_id.as_deref(), Some("new-peer"));
+        assert!(mgr.get_room_by_host_socket("new-socket").is_some());
+    }
+
+    #[test]
+    fn away_host_expires_unless_it_comes_back() {
+        let mgr = manager_with_room();
+        let away_since = mgr.mark_host_away("room-1", 1_000).unwrap();
+        assert!(mgr.get_room("room-1").unwrap().host_away_since.is_some());
+
+        mgr.reclaim_room("room-1", "new-socket".to_string(), &hash_token(HOST_KEY), None).unwrap();
+        assert!(!mgr.expire_host("room-1", away_since), "a reclaimed room must survive its old timer");
+        assert!(mgr.get_room("room-1").is_some());
+
+        let away_again = mgr.mark_host_away("room-1", 2_000).unwrap();
+        assert!(mgr.expire_host("room-1", away_again));
+        assert!(mgr.get_room("room-1").is_none());
+    }
+
+    fn manager_with_invite(expires_in_secs: Option<u64>, max_uses: Option<u32>, now: u64) -> RoomManager {
+        let mgr = manager_with_room();
+        let invite = Invite::new("inv-1".to_string(), hash_token("invite-token"), Some(" table 3 ".to_string()), expires_in_secs, max_uses, now);
+        mgr.add_invite("room-1", invite).expect("invite should be added");
+        mgr
+    }
+
+    #[test]
+    fn single_use_invites_admit_exactly_once() {
+        let mgr = manager_with_invite(None, Some(1), now_millis());
+        assert!(mgr.verify_room("room-1", "invite-token").is_ok());
+        let err = mgr.verify_room("room-1", "invite-token").unwrap_err();
+        assert!(err.contains("used"), "expected a used-up error, got: {err}");
+
+        let invites = mgr.list_invites("room-1");
+        assert_eq!(invites[0].uses, 1);
+        assert_eq!(invites[0].label.as_deref(), Some("table 3"));
+    }
+
+    #[test]
+    fn expired_and_revoked_invites_are_refused() {
+        let mgr = manager_with_invite(Some(60), None, 0);
+        let err = mgr.verify_room("room-1", "invite-token").unwrap_err();
+        assert!(err.contains("expired"), "expected an expiry error, got: {err}");
+
+        let mgr = manager_with_invite(None, None, now_millis());
+        assert!(mgr.revoke_invite("room-1", "inv-1"));
+        assert!(mgr.verify_room("room-1", "invite-token").is_err());
+        assert!(!mgr.revoke_invite("room-1", "inv-1"));
+    }
+
+    #[test]
+    fn a_full_room_does_not_use_up_an_invite() {
+        let mgr = manager_with_invite(None, Some(1), now_millis());
+        for _ in 0..MAX_CLIENTS_PER_ROOM {
+            mgr.add_client("room-1");
+        }
+        assert!(mgr.verify_room("room-1", "invite-token").is_err());
+        assert_eq!(mgr.list_invites("room-1")[0].uses, 0);
+    }
+
+    #[test]
+    fn returning_guests_are_known_by_device_token() {
+        let mgr = manager_with_room();
+        let first = mgr.claim_identity("room-1", None, "Ana", 0);
+        assert!(mgr.has_identity("room-1", &first.device_token, 0));
+        assert!(!mgr.has_identity("room-2", &first.device_token, 0));
+
+        mgr.release_identity(&first.device_token, 1_000);
+        assert!(mgr.has_identity("room-1", &first.device_token, 1_000 + IDENTITY_GRACE_MS));
+        assert!(!mgr.has_identity("room-1", &first.device_token, 1_001 + IDENTITY_GRACE_MS));
+    }
+
+    fn park(mgr: &RoomManager, socket_id: &str, name: &str, at: u64) {
+        mgr.park_guest(socket_id, PendingGuest {
+            room_id: "room-1".to_string(),
+            display_name: name.to_string(),
+            device_id: None,
+            device_token: None,
+            invite_id: None,
+            requested_at: at,
+        }).unwrap();
+    }
+
+    #[test]
+    fn lobby_defers_the_capacity_check_to_approval() {
+        let mgr = manager_with_room();
+        mgr.set_lobby("room-1", true);
+        for _ in 0..MAX_CLIENTS_PER_ROOM {
+            mgr.add_client("room-1");
+        }
+        assert!(mgr.verify_room("room-1", TOKEN).is_ok(), "a full room still lets guests wait");
+
+        park(&mgr, "sock-a", "Ana", 1);
+        let err = mgr.approve_guest("room-1", "sock-a").unwrap_err();
+        assert!(err.contains("full"), "expected a capacity error, got: {err}");
+        assert_eq!(mgr.pending_guests("room-1").len(), 1, "a refused approval keeps the guest waiting");
+
+        mgr.remove_client("room-1");
+        let (guest, _) = mgr.approve_guest("room-1", "sock-a").expect("room has space again");
+        assert_eq!(guest.display_name, "Ana");
+        assert!(mgr.pending_guests("room-1").is_empty());
+    }
+
+    #[test]
+    fn lobby_counts_an_invite_use_only_on_approval() {
+        let mgr = manager_with_invite(None, Some(1), now_millis());
+        mgr.set_lobby("room-1", true);
+        for socket_id in ["sock-a", "sock-b"] {
+            let (_, invite_id) = mgr.verify_room("room-1", "invite-token").expect("waiting does not use the invite");
+            assert_eq!(invite_id.as_deref(), Some("inv-1"));
+            mgr.park_guest(socket_id, PendingGuest {
+                room_id: "room-1".to_string(),
+                display_name: socket_id.to_string(),
+                device_id: None,
+                device_token: None,
+                invite_id,
+                requested_at: 0,
+            }).unwrap();
+        }
+        assert_eq!(mgr.list_invites("room-1")[0].uses, 0);
+
+        mgr.approve_guest("room-1", "sock-a").expect("the first approval uses the invite");
+        assert_eq!(mgr.list_invites("room-1")[0].uses, 1);
+        let err = mgr.approve_guest("room-1", "sock-b").unwrap_err();
+        assert!(err.contains("used"), "expected a used-up error, got: {err}");
+        assert_eq!(mgr.pending_guests("room-1").len(), 1);
+    }
+
+    #[test]
+    fn lobby_has_room_for_only_so_many() {
+        let mgr = manager_with_room();
+        for i in 0..MAX_LOBBY_PER_ROOM {
+            park(&mgr, &format!("sock-{i}"), "Guest", i as u64);
+        }
+        let extra = PendingGuest {
+            room_id: "room-1".to_string(),
+            display_name: "Late".to_string(),
+            device_id: None,
+            device_token: None,
+            invite_id: None,
+            requested_at: 99,
+        };
+        assert!(mgr.park_guest("sock-late", extra.clone()).is_err());
+        assert!(mgr.park_guest("sock-late", PendingGuest { room_id: "room-2".to_string(), ..extra }).is_ok());
+    }
+
+    #[test]
+    fn lobby_lists_guests_in_arrival_order_per_room() {
+        let mgr = manager_with_room();
+        park(&mgr, "sock-b", "Ben", 20);
+        park(&mgr, "sock-a", "Ana", 10);
+
+        let names: Vec<_> = mgr.pending_guests("room-1").into_iter().map(|(_, g)| g.display_name).collect();
+        assert_eq!(names, ["Ana", "Ben"]);
+        assert!(mgr.pending_guests("room-2").is_empty());
+        assert!(mgr.approve_guest("room-2", "sock-a").is_err(), "a request is only approvable in its own room");
+
+        assert_eq!(mgr.remove_pending("sock-b").as_deref(), Some("room-1"));
+        mgr.delete_room("room-1");
+        assert!(mgr.remove_pending("sock-a").is_none(), "closing the room empties its lobby");
+    }
+
+    #[test]
+    fn capacity_is_clamped_and_never_turns_guests_away() {
+        let mgr = manager_with_room();
+        mgr.set_capacity("room-1", 2);
+        join_guest(&mgr, "sock-a", "peer-a", "device-a");
+        join_guest(&mgr, "sock-b", "peer-b", "device-b");
+        assert!(mgr.verify_room("room-1", TOKEN).is_ok(), "capacity never turns guests away");
+
+        mgr.set_capacity("room-1", 0);
+        assert_eq!(mgr.get_room("room-1").unwrap().capacity, 1);
+        mgr.set_capacity("room-1", 10_000);
+        assert_eq!(mgr.get_room("room-1").unwrap().capacity, MAX_CLIENTS_PER_ROOM);
+    }
+
+    const PIN: &str = "042917";
+
+    fn manager_with_pin() -> RoomManager {
+        let mgr = manager_with_room();
+        mgr.set_pin("room-1", Some(hash_token(PIN)));
+        mgr
+    }
+
+    #[test]
+    fn pin_admits_and_ignores_spacing() {
+        let mgr = manager_with_pin();
+        assert!(mgr.verify_pin(Some("room-1"), PIN, "10.0.0.5", 0).is_ok());
+        assert!(mgr.verify_pin(Some("room-1"), "042 917", "10.0.0.5", 0).is_ok());
+        assert!(mgr.verify_pin(Some("room-1"), "000000", "10.0.0.5", 0).is_err());
+
+        mgr.set_pin("room-1", None);
+        assert!(mgr.verify_pin(Some("room-1"), PIN, "10.0.0.5", 0).is_err(), "no PIN set means no PIN entry");
+    }
+
+    #[test]
+    fn wrong_pins_lock_out_the_address() {
+        let mgr = manager_with_pin();
+        for _ in 0..PIN_MAX_FAILURES {
+            assert!(mgr.verify_pin(Some("room-1"), "111111", "10.0.0.5", 1_000).is_err());
+        }
+        let err = mgr.verify_pin(Some("room-1"), PIN, "10.0.0.5", 2_000).unwrap_err();
+        assert!(err.contains("Too many"), "even the right PIN waits out the lockout, got: {err}");
+        assert!(mgr.verify_pin(Some("room-1"), PIN, "10.0.0.6", 2_000).is_ok(), "other addresses are unaffected");
+
+        assert!(mgr.verify_pin(Some("room-1"), PIN, "10.0.0.5", 1_000 + PIN_LOCKOUT_MS).is_ok());
+    }
+
+    #[test]
+    fn wrong_pins_from_many_addresses_lock_the_room() {
+        let mgr = manager_with_pin();
+        for i in 0..PIN_ROOM_MAX_FAILURES {
+            let _ = mgr.verify_pin(Some("room-1"), "111111", &format!("10.0.1.{}", i), 1_000);
+        }
+        assert!(mgr.verify_pin(Some("room-1"), PIN, "10.0.0.9", 2_000).is_err());
+        assert!(mgr.verify_room("room-1", TOKEN).is_ok(), "QR joins are not locked with the PIN");
+    }
+
+    #[test]
+    fn pins_without_a_room_are_charged_to_the_address_first() {
+        let mgr = manager_with_pin();
+        mgr.create_room("side".to_string(), "host-socket-2".to_string(), hash_token(TOKEN), hash_token(HOST_KEY), None).unwrap();
+        mgr.set_pin("side", Some(hash_token("777777")));
+        for _ in 0..PIN_MAX_FAILURES {
+            assert!(mgr.verify_pin(None, "111111", "10.0.0.5", 1_000).is_err());
+        }
+        let err = mgr.verify_pin(None, "777777", "10.0.0.5", 2_000).unwrap_err();
+        assert!(err.contains("Too many"), "the address is locked before any room is looked at, got: {err}");
+
+        // Guesses without a room count against every room they were tried on
+        for i in 0..PIN_ROOM_MAX_FAILURES {
+            let _ = mgr.verify_pin(None, "111111", &format!("10.0.1.{}", i), 1_000);
+        }
+        assert!(mgr.verify_pin(Some("side"), "777777", "10.0.0.9", 2_000).is_err());
+        assert!(mgr.verify_pin(None, PIN, "10.0.0.9", 2_000).is_err());
+    }
+
+    #[test]
+    fn ipv6_guesses_are_charged_to_the_prefix() {
+        let key = |ip: &str| address_key(ip.parse().unwrap());
+        assert_eq!(key("::ffff:10.0.0.5"), "10.0.0.5");
+        assert_eq!(key("2001:db8:1:2::1"), "2001:db8:1:2::/64");
+        assert_eq!(key("2001:db8:1:2:aaaa:bbbb:cccc:dddd"), key("2001:db8:1:2::1"));
+        assert_ne!(key("2001:db8:1:3::1"), key("2001:db8:1:2::1"));
+    }
+
+    #[test]
+    fn host_grace_is_capped() {
+        let mgr = manager_with_room();
+        assert_eq!(mgr.get_room("room-1").unwrap().host_grace_secs, DEFAULT_HOST_GRACE_SECS);
+        mgr.set_host_grace("room-1", 86_400);
+        assert_eq!(mgr.get_room("room-1").unwrap().host_grace_secs, MAX_HOST_GRACE_SECS);
+    }
 }
@@ -0,0 +1,222 @@
+//! Optional HTTPS for the embedded web server.
+//!
+//! Phone browsers only grant the microphone, wake lock and other
+//! secure-context APIs to `https` pages, and a LAN address cannot get a
+//! publicly trusted certificate. So the host makes its own: a self-signed
+//! certificate kept in the app data dir, whose SHA-256 fingerprint guests
+//! compare against the host screen the first time their browser warns.
+//!
+//! The certificate is made once and kept, so the fingerprint guests checked
+//! stays the same across restarts and network changes. It names the
+//! addresses the machine had when it was made; browsers warn either way.
+
+use parking_lot::RwLock;
+use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::ServerConfig;
+
+const CERT_FILE: &str = "cert.pem";
+const KEY_FILE: &str = "key.pem";
+
+/// A certificate and its key, as PEM
+#[derive(Clone)]
+struct Identity {
+    cert_pem: String,
+    key_pem: String,
+}
+
+/// Make a self-signed certificate for `localhost` and `addresses`
+fn generate(addresses: &[String]) -> Result<Identity, String> {
+    let mut names = vec!["localhost".to_string(), "127.0.0.1".to_string()];
+    names.extend(addresses.iter().cloned());
+    let mut params = CertificateParams::new(names).map_err(|e| format!("Invalid certificate names: {}", e))?;
+    let mut subject = DistinguishedName::new();
+    subject.push(DnType::CommonName, "KaraokeNatin");
+    params.distinguished_name = subject;
+    let key = KeyPair::generate().map_err(|e| format!("Failed to generate key: {}", e))?;
+    let cert = params.self_signed(&key).map_err(|e| format!("Failed to sign certificate: {}", e))?;
+    Ok(Identity { cert_pem: cert.pem(), key_pem: key.serialize_pem() })
+}
+
+/// SHA-256 of the certificate, as browsers show it: `AB:CD:…`
+fn fingerprint(cert: &CertificateDer) -> String {
+    Sha256::digest(cert.as_ref())
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn parse(identity: &Identity) -> Result<(CertificateDer<'static>, PrivateKeyDer<'static>), String> {
+    let cert = CertificateDer::from_pem_slice(identity.cert_pem.as_bytes())
+        .map_err(|e| format!("Failed to read certificate: {}", e))?;
+    let key = PrivateKeyDer::from_pem_slice(identity.key_pem.as_bytes())
+        .map_err(|e| format!("Failed to read certificate key: {}", e))?;
+    Ok((cert, key))
+}
+
+/// Save the private key readable by the owner only
+fn write_key(path: &Path, key_pem: &str) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(key_pem.as_bytes())?;
+    // The mode only applies when the file is created
+    restrict_key(path)
+}
+
+/// Take group and other access away from a key file that has it, such as one
+/// saved by an older version
+#[cfg(unix)]
+fn restrict_key(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = fs::metadata(path)?.permissions();
+    if permissions.mode() & 0o077 != 0 {
+        permissions.set_mode(0o600);
+        fs::set_permissions(path, permissions)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restrict_key(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Keeps the server certificate in `tls/` under the app data dir, made on
+/// first use.
+pub struct CertificateStore {
+    dir: Arc<RwLock<Option<PathBuf>>>,
+    identity: Arc<RwLock<Option<Identity>>>,
+}
+
+impl CertificateStore {
+    pub fn new() -> Self {
+        Self {
+            dir: Arc::new(RwLock::new(None)),
+            identity: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Point the store at the app data dir; the certificate is read lazily
+    pub fn initialize(&self, app_data_dir: PathBuf) {
+        let mut dir = app_data_dir;
+        dir.push("tls");
+        *self.dir.write() = Some(dir);
+    }
+
+    /// The saved certificate, or a new one for `addresses` if there is none
+    /// (or it is unreadable)
+    fn identity(&self, addresses: &[String]) -> Result<Identity, String> {
+        if let Some(identity) = self.identity.read().clone() {
+            return Ok(identity);
+        }
+        let mut slot = self.identity.write();
+        if let Some(identity) = slot.clone() {
+            return Ok(identity);
+        }
+
+        let dir = self.dir.read().clone();
+        let saved = dir.as_ref().and_then(|dir| {
+            let cert_pem = fs::read_to_string(dir.join(CERT_FILE)).ok()?;
+            let key_path = dir.join(KEY_FILE);
+            if let Err(e) = restrict_key(&key_path) {
+                log::warn!("Failed to restrict access to {:?}: {}", key_path, e);
+            }
+            let key_pem = fs::read_to_string(key_path).ok()?;
+            let identity = Identity { cert_pem, key_pem };
+            match parse(&identity) {
+                Ok(_) => Some(identity),
+                Err(e) => {
+                    log::error!("Discarding saved certificate: {}", e);
+                    None
+                }
+            }
+        });
+        let identity = match saved {
+            Some(identity) => identity,
+            None => {
+                let identity = generate(addresses)?;
+                if let Some(dir) = dir {
+                    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+                    fs::write(dir.join(CERT_FILE), &identity.cert_pem)
+                        .and_then(|_| write_key(&dir.join(KEY_FILE), &identity.key_pem))
+                        .map_err(|e| format!("Failed to save certificate: {}", e))?;
+                }
+                log::info!("Generated a certificate for {:?}", addresses);
+                identity
+            }
+        };
+        *slot = Some(identity.clone());
+        Ok(identity)
+    }
+
+    /// Fingerprint guests should see when their browser asks about the
+    /// certificate
+    pub fn fingerprint(&self, addresses: &[String]) -> Result<String, String> {
+        let (cert, _) = parse(&self.identity(addresses)?)?;
+        Ok(fingerprint(&cert))
+    }
+
+    /// rustls settings for serving with the certificate
+    pub fn server_config(&self, addresses: &[String]) -> Result<Arc<ServerConfig>, String> {
+        let (cert, key) = parse(&self.identity(addresses)?)?;
+        let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| format!("Failed to set up TLS: {}", e))?
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)
+            .map_err(|e| format!("Failed to set up TLS: {}", e))?;
+        // Socket.io and the PeerJS relay both upgrade over HTTP/1.1
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(Arc::new(config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_one_certificate_across_restarts() {
+        let dir = std::env::temp_dir().join(format!("karaokenatin-tls-{}", uuid::Uuid::new_v4()));
+        let store = CertificateStore::new();
+        store.initialize(dir.clone());
+        let first = store.fingerprint(&["192.168.1.20".to_string()]).unwrap();
+        assert_eq!(first.len(), 32 * 3 - 1);
+        assert!(store.server_config(&[]).is_ok());
+
+        let restarted = CertificateStore::new();
+        restarted.initialize(dir.clone());
+        assert_eq!(restarted.fingerprint(&["10.0.0.4".to_string()]).unwrap(), first);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_key_is_readable_by_the_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("karaokenatin-tls-{}", uuid::Uuid::new_v4()));
+        let store = CertificateStore::new();
+        store.initialize(dir.clone());
+        store.fingerprint(&[]).unwrap();
+        let key = dir.join("tls").join(KEY_FILE);
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&key), 0o600);
+
+        fs::set_permissions(&key, fs::Permissions::from_mode(0o644)).unwrap();
+        let restarted = CertificateStore::new();
+        restarted.initialize(dir.clone());
+        restarted.fingerprint(&[]).unwrap();
+        assert_eq!(mode(&key), 0o600);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -5,12 +5,19 @@ use axum::{
     http::StatusCode,
 };
 use tower_http::cors::{CorsLayer, Any};
-use std::net::SocketAddr;
-use std::sync::atomic::{AtomicU16, Ordering};
+use std::future::IntoFuture;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 use socketioxide::SocketIo;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use crate::network::{NetworkSettings, PortRange, DEFAULT_PORT_RANGE};
 use crate::signaling::{RoomManager, on_connect};
 use crate::peer_server::{self, PeerRegistry};
+use tauri::Manager;
 
 /// The embedded remote control UI HTML
 const REMOTE_UI_HTML: &str = include_str!("../remote-ui/index.html");
@@ -23,14 +30,33 @@ const VENDOR_PEERJS: &str = include_str!("../remote-ui/vendor/peerjs-1.5.5.min.j
 const VENDOR_QRCODEJS: &str = include_str!("../remote-ui/vendor/qrcodejs-1.0.0.min.js");
 const VENDOR_LUCIDE: &str = include_str!("../remote-ui/vendor/lucide-1.27.0.min.js");
 
+/// How long a new connection may take to send its first byte, and then to
+/// finish the TLS handshake. This runs before the concurrency limit sees the
+/// connection, so a silent client must not hold its task for good.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Store the actual port being used (for QR code generation)
 static ACTUAL_PORT: AtomicU16 = AtomicU16::new(0);
 
+/// Whether the server speaks TLS (plain http is still accepted on the port)
+static SECURE: AtomicBool = AtomicBool::new(false);
+
 /// Get the current server port
 pub fn get_server_port() -> u16 {
     ACTUAL_PORT.load(Ordering::SeqCst)
 }
 
+/// Record whether the server will speak TLS. Set before serving starts, so
+/// links are right as soon as `start_host_server` returns.
+pub fn set_secure(secure: bool) {
+    SECURE.store(secure, Ordering::SeqCst);
+}
+
+/// `https` when the server speaks TLS, for the links handed to guests
+pub fn scheme() -> &'static str {
+    if SECURE.load(Ordering::SeqCst) { "https" } else { "http" }
+}
+
 /// Bind a TCP listener on an available port, synchronously.
 ///
 /// This used to be async and run *inside* the spawned tokio runtime in
@@ -40,65 +66,163 @@ pub fn get_server_port() -> u16 {
 /// caller do it on the calling thread, learn the real port, and only then
 /// spin up the runtime that serves on it (see `serve_web_server`).
 ///
-/// Returns the bound (but not yet async-registered) listener plus the port
-/// it landed on. The listener is left in blocking mode; `serve_web_server`
-/// is responsible for handing it to tokio.
-pub fn bind_web_server() -> Result<(std::net::TcpListener, u16), String> {
+/// Returns the bound (but not yet async-registered) listeners plus the port
+/// they landed on. That is one dual-stack listener on all interfaces, or with
+/// `bind_preferred_only` one on the chosen address and one on loopback, which
+/// the host's own window uses. If that address is gone (say, the Wi-Fi
+/// changed) it falls back to all interfaces. The listeners are left in
+/// blocking mode; `serve_web_server` is responsible for handing them to tokio.
+pub fn bind_web_server(network: &NetworkSettings) -> Result<(Vec<std::net::TcpListener>, u16), String> {
+    let only = network.bind_ip();
+    let bind = |hosts: &[IpAddr]| bind_on(hosts, network.preferred_port, network.port_range);
+    let (listeners, port) = match bind(&listen_hosts(only)) {
+        Ok(bound) => bound,
+        Err(e) if only.is_some() => {
+            log::warn!("{}; listening on all interfaces instead", e);
+            bind(&listen_hosts(None))?
+        }
+        Err(e) => return Err(e),
+    };
+
+    ACTUAL_PORT.store(port, Ordering::SeqCst);
+    log::info!("[WebServer] Bound to port {} on {:?}", port, listen_hosts(only));
+
+    Ok((listeners, port))
+}
+
+fn listen_hosts(only: Option<IpAddr>) -> Vec<IpAddr> {
+    match only {
+        Some(ip) if !ip.is_loopback() => vec![ip, IpAddr::V4(Ipv4Addr::LOCALHOST)],
+        Some(ip) => vec![ip],
+        None => vec![IpAddr::V6(Ipv6Addr::UNSPECIFIED)],
+    }
+}
+
+/// Bind one address. `[::]` is bound dual-stack, so IPv4 guests arrive on it
+/// too, and falls back to `0.0.0.0` on machines without IPv6.
+fn bind_tcp(addr: SocketAddr) -> std::io::Result<std::net::TcpListener> {
+    if addr.ip() != IpAddr::V6(Ipv6Addr::UNSPECIFIED) {
+        return std::net::TcpListener::bind(addr);
+    }
+    let dual_stack = || -> std::io::Result<std::net::TcpListener> {
+        let socket = Socket::new(Domain::IPV6, Type::STREAM, Some(Protocol::TCP))?;
+        // Windows and some BSDs default to IPv6 only
+        socket.set_only_v6(false)?;
+        // As std does, so a restart can take the port back straight away
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(1024)?;
+        Ok(socket.into())
+    };
+    match dual_stack() {
+        Err(e) if e.kind() != std::io::ErrorKind::AddrInUse => {
+            log::warn!("[WebServer] No IPv6 ({}), listening on IPv4 only", e);
+            std::net::TcpListener::bind((Ipv4Addr::UNSPECIFIED, addr.port()))
+        }
+        bound => bound,
+    }
+}
+
+/// Bind every host on one port; port 0 lets the OS pick it for the first
+fn bind_all(hosts: &[IpAddr], port: u16) -> std::io::Result<Vec<std::net::TcpListener>> {
+    let first = bind_tcp(SocketAddr::new(hosts[0], port))?;
+    let port = first.local_addr()?.port();
+    let mut listeners = vec![first];
+    for host in &hosts[1..] {
+        listeners.push(bind_tcp(SocketAddr::new(*host, port))?);
+    }
+    Ok(listeners)
+}
+
+/// Bind `preferred` if it is free, else a random port from `range` (the
+/// ephemeral range when unset). With a range set, every port in it is tried
+/// before giving up; without one the OS picks as a last resort.
+fn bind_on(
+    hosts: &[IpAddr],
+    preferred: Option<u16>,
+    range: Option<PortRange>,
+) -> Result<(Vec<std::net::TcpListener>, u16), String> {
     use rand::Rng;
-    let mut rng = rand::thread_rng();
 
-    // Try random ports in the IANA ephemeral range (49152–65535).
     let mut bound = None;
+    if let Some(port) = preferred.filter(|p| range.map_or(true, |r| r.contains(*p))) {
+        match bind_all(hosts, port) {
+            Ok(listeners) => bound = Some(listeners),
+            Err(e) => log::warn!("[WebServer] Preferred port {} is unavailable ({}), picking another", port, e),
+        }
+    }
+
+    let allowed = range.unwrap_or(DEFAULT_PORT_RANGE);
+    let mut rng = rand::thread_rng();
     for _ in 0..20 {
-        let port = rng.gen_range(49152..=65535);
-        if let Ok(listener) = std::net::TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], port))) {
-            bound = Some(listener);
+        if bound.is_some() {
             break;
         }
+        bound = bind_all(hosts, rng.gen_range(allowed.start..=allowed.end)).ok();
     }
 
-    let listener = match bound {
-        Some(listener) => listener,
-        None => {
+    let listeners = match (bound, range) {
+        (Some(listeners), _) => listeners,
+        (None, Some(range)) => (range.start..=range.end)
+            .find_map(|port| bind_all(hosts, port).ok())
+            .ok_or_else(|| format!("[WebServer] Every port in {}-{} is taken", range.start, range.end))?,
+        (None, None) => {
             log::warn!("[WebServer] No random ports available, falling back to an OS-assigned port");
-            std::net::TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], 0)))
-                .map_err(|e| format!("[WebServer] Failed to bind any port: {}", e))?
+            bind_all(hosts, 0)
+                .map_err(|e| format!("[WebServer] Failed to bind {:?}: {}", hosts, e))?
         }
     };
 
-    let port = listener
+    let port = listeners[0]
         .local_addr()
         .map_err(|e| format!("[WebServer] Failed to read bound port: {}", e))?
         .port();
 
-    ACTUAL_PORT.store(port, Ordering::SeqCst);
-    log::info!("[WebServer] Bound to port {}", port);
-
-    Ok((listener, port))
+    Ok((listeners, port))
 }
 
-/// Serve the embedded web server on an already-bound listener.
+/// Serve the embedded web server on already-bound listeners.
 ///
 /// Must be called from within a tokio runtime (this is the async half of
 /// startup — see `bind_web_server` for the synchronous half, which must run
 /// first so the caller already knows the port before this starts).
-pub async fn serve_web_server(listener: std::net::TcpListener) -> Result<(), String> {
-    let port = listener
-        .local_addr()
-        .map(|a| a.port())
-        .unwrap_or_else(|_| get_server_port());
-
-    listener
-        .set_nonblocking(true)
-        .map_err(|e| format!("[WebServer] Failed to set listener non-blocking: {}", e))?;
-    let listener = tokio::net::TcpListener::from_std(listener)
-        .map_err(|e| format!("[WebServer] Failed to attach listener to the async runtime: {}", e))?;
+///
+/// With `tls`, each connection is served over TLS or plain http depending on
+/// how it opens, so the host's own window keeps using `http://localhost`.
+pub async fn serve_web_server(
+    listeners: Vec<std::net::TcpListener>,
+    app: tauri::AppHandle,
+    tls: Option<Arc<ServerConfig>>,
+) -> Result<(), String> {
+    let port = get_server_port();
+
+    let mut async_listeners = Vec::with_capacity(listeners.len());
+    for listener in listeners {
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| format!("[WebServer] Failed to set listener non-blocking: {}", e))?;
+        async_listeners.push(
+            tokio::net::TcpListener::from_std(listener)
+                .map_err(|e| format!("[WebServer] Failed to attach listener to the async runtime: {}", e))?,
+        );
+    }
 
     log::info!("[WebServer] Starting embedded web server on port {}", port);
 
-    // Initialize Socket.io with connection limits
+    // The embedded PeerJS broker. Without this, clients fall back to the public
+    // 0.peerjs.com cloud and the app cannot connect guests without internet —
+    // see peer_server.rs for the full rationale.
+    let peer_registry = PeerRegistry::new();
+
+    // Initialize Socket.io with connection limits. The peer registry is shared
+    // so KICK_CLIENT can close a kicked guest's relay socket, and the app
+    // handle lets joins and leaves reach the room state. The room manager is
+    // the app's, so commands can reach signaling too.
     let (layer, io) = SocketIo::builder()
-        .with_state(RoomManager::new())
+        .with_state(app.state::<RoomManager>().inner().clone())
+        .with_state(peer_registry.clone())
+        .with_state(app)
         .ping_interval(Duration::from_secs(25))
         .ping_timeout(Duration::from_secs(20))
         .max_buffer_size(128)
@@ -106,11 +230,6 @@ pub async fn serve_web_server(listener: std::net::TcpListener) -> Result<(), Str
 
     io.ns("/", on_connect);
 
-    // The embedded PeerJS broker. Without this, clients fall back to the public
-    // 0.peerjs.com cloud and the app cannot connect guests without internet —
-    // see peer_server.rs for the full rationale.
-    let peer_registry = PeerRegistry::new();
-
     let peer_routes = Router::new()
         // GET /peerjs upgrades to the relay socket when an Upgrade header is
         // present; PeerJS also probes /peerjs/id for a server-assigned id.
@@ -159,9 +278,26 @@ pub async fn serve_web_server(listener: std::net::TcpListener) -> Result<(), Str
         // Limit concurrent connections to prevent resource exhaustion
         .layer(tower::limit::ConcurrencyLimitLayer::new(64));
 
-    // Start server
-    match axum::serve(listener, app).await {
-        Ok(_) => {
+    // Start server. Connect info lets signaling rate-limit PIN guesses per
+    // client address.
+    let result = match tls {
+        Some(config) => {
+            let acceptor = TlsAcceptor::from(config);
+            let servers = async_listeners
+                .into_iter()
+                .map(|listener| serve_tls_or_plain(listener, app.clone(), acceptor.clone()));
+            futures_util::future::try_join_all(servers).await.map(|_| ())
+        }
+        None => {
+            let service = app.into_make_service_with_connect_info::<SocketAddr>();
+            let servers = async_listeners
+                .into_iter()
+                .map(|listener| axum::serve(listener, service.clone()).into_future());
+            futures_util::future::try_join_all(servers).await.map(|_| ())
+        }
+    };
+    match result {
+        Ok(()) => {
             log::info!("[WebServer] Server stopped gracefully");
             Ok(())
         }
@@ -173,7 +309,67 @@ pub async fn serve_web_server(listener: std::net::TcpListener) -> Result<(), Str
     }
 }
 
-/// Serve the remote control UI
+/// Accept loop for TLS mode. A TLS handshake opens with a 0x16 record, so
+/// the first byte tells the two apart without consuming it.
+async fn serve_tls_or_plain(
+    listener: tokio::net::TcpListener,
+    app: Router,
+    acceptor: TlsAcceptor,
+) -> std::io::Result<()> {
+    use axum::extract::ConnectInfo;
+    use hyper_util::rt::{TokioExecutor, TokioIo};
+    use hyper_util::server::conn::auto::Builder;
+    use hyper_util::service::TowerToHyperService;
+    use tower::ServiceExt;
+
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                // Usually out of file descriptors; back off like axum::serve
+                log::warn!("[WebServer] Accept failed: {}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let service = TowerToHyperService::new(app.clone().map_request(
+            move |mut request: axum::http::Request<hyper::body::Incoming>| {
+                request.extensions_mut().insert(ConnectInfo(addr));
+                request
+            },
+        ));
+        let acceptor = acceptor.clone();
+        tokio::spawn(async move {
+            let builder = Builder::new(TokioExecutor::new());
+            let mut first = [0u8; 1];
+            let peeked = tokio::time::timeout(HANDSHAKE_TIMEOUT, stream.peek(&mut first)).await;
+            let served = match peeked {
+                Ok(Ok(1)) if first[0] == 0x16 => {
+                    let handshake = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await;
+                    match handshake {
+                        Ok(Ok(stream)) => builder.serve_connection_with_upgrades(TokioIo::new(stream), service).await,
+                        Ok(Err(e)) => {
+                            // Expected while a guest's browser is still deciding
+                            // whether to trust the certificate
+                            log::debug!("[WebServer] TLS handshake with {} failed: {}", addr, e);
+                            return;
+                        }
+                        Err(_) => {
+                            log::debug!("[WebServer] TLS handshake with {} timed out", addr);
+                            return;
+                        }
+                    }
+                }
+                Ok(Ok(_)) => builder.serve_connection_with_upgrades(TokioIo::new(stream), service).await,
+                Ok(Err(_)) | Err(_) => return,
+            };
+            if let Err(e) = served {
+                log::debug!("[WebServer] Connection from {} ended: {}", addr, e);
+            }
+        });
+    }
+}
+
 async fn serve_index() -> impl IntoResponse {
     (
         [
@@ -224,6 +420,34 @@ async fn serve_vendor_lucide() -> impl IntoResponse {
 mod tests {
     use super::*;
 
+    /// A port that was free a moment ago
+    fn free_port() -> u16 {
+        std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap().local_addr().unwrap().port()
+    }
+
+    #[test]
+    fn listens_for_ipv4_guests_on_the_dual_stack_socket() {
+        let (_listeners, port) = bind_on(&listen_hosts(None), None, None).unwrap();
+        assert!(std::net::TcpStream::connect((Ipv4Addr::LOCALHOST, port)).is_ok());
+    }
+
+    #[test]
+    fn binds_the_preferred_port_when_free() {
+        let port = free_port();
+        let (_listeners, bound) = bind_on(&[IpAddr::V4(Ipv4Addr::LOCALHOST)], Some(port), None).unwrap();
+        assert_eq!(bound, port);
+    }
+
+    #[test]
+    fn falls_back_within_the_range_when_the_preferred_port_is_taken() {
+        let taken = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = taken.local_addr().unwrap().port();
+        let range = PortRange { start: port.saturating_sub(20).max(1024), end: port.saturating_add(20) };
+        let (_listeners, bound) = bind_on(&[IpAddr::V4(Ipv4Addr::LOCALHOST)], Some(port), Some(range)).unwrap();
+        assert_ne!(bound, port);
+        assert!(range.contains(bound));
+    }
+
     #[tokio::test]
     async fn test_health_check() {
         let response = health_check().await.into_response();
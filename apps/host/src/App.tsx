@@ -6,15 +6,17 @@ import GuestMode from './components/GuestMode';
 import ModeSelect from './components/ModeSelect';
 import Library from './components/Library';
 import { useRoomState } from './hooks/useRoomState';
-import { usePeerHost } from './hooks/usePeerHost';
+import { usePeerHost, ensureRoomCredentials } from './hooks/usePeerHost';
 import HelpDialog from './components/HelpDialog';
 import { invoke } from '@tauri-apps/api/core';
 import {
   startHostServer,
+  processCommand,
+  openRoomWindow,
   loadCollectionFromFile,
   playlistImportCollection,
 } from './lib/commands';
-import { Clapperboard, SlidersHorizontal, Unplug, ArrowLeft, HelpCircle } from 'lucide-react';
+import { Clapperboard, SlidersHorizontal, Unplug, ArrowLeft, HelpCircle, History } from 'lucide-react';
 
 // Initialize spatial navigation for DPAD / Android TV
 init({
@@ -34,8 +36,12 @@ type AppMode = 'select' | 'host' | 'guest' | 'library';
 
 // ---- Host-mode wrapper (hooks only active when rendered) ----
 function HostView({ onBack }: { onBack: () => void }) {
-  const { roomState, loading, initializeRoom } = useRoomState();
-  const { connectionUrl, connectedClients } = usePeerHost();
+  const { roomState, loading, initializeRoom, restorableSession, resolveSession } = useRoomState();
+  const {
+    connectionUrl, connectedClients, guestIds, kickGuest, invites, createInvite, revokeInvite,
+    pin, baseUrl, refreshBaseUrl, regeneratePin, handoffCode, offerHandoff, cancelHandoff, takeOverRoom, handedOffTo,
+    lobbyEnabled, lobby, setLobbyMode, approveGuest, rejectGuest, capacity, setCapacity,
+  } = usePeerHost();
   const [isPanelCollapsed, setIsPanelCollapsed] = useState(false);
   const [searchResults, setSearchResults] = useState<SearchResult[]>([]);
   const [searching, setSearching] = useState(false);
@@ -52,7 +58,7 @@ function HostView({ onBack }: { onBack: () => void }) {
       } catch (e) {
         console.warn('[Host] startHostServer:', e);
       }
-      initializeRoom();
+      initializeRoom(ensureRoomCredentials().roomId);
     })();
   }, []);
 
@@ -98,16 +104,24 @@ function HostView({ onBack }: { onBack: () => void }) {
   };
 
   const handleAddToPlaylist = async (url: string, collectionId: string) => {
-    await invoke('process_command', {
-      command: {
-        type: 'PLAYLIST_ADD',
-        youtubeUrl: url,
-        collectionId,
-        addedBy: 'Host',
-      },
+    await processCommand({
+      type: 'PLAYLIST_ADD',
+      youtubeUrl: url,
+      collectionId,
+      addedBy: 'Host',
     });
   };
 
+  // Each room gets a window of its own: its own player, queue and guests
+  const handleOpenRoom = async () => {
+    try {
+      await openRoomWindow();
+    } catch (error) {
+      console.error('Failed to open another room:', error);
+      alert(typeof error === 'string' ? error : 'Could not open another room');
+    }
+  };
+
   const handleTabSwitch = useCallback((tab: 'player' | 'controls') => {
     setActiveTab(tab);
   }, []);
@@ -139,6 +153,36 @@ function HostView({ onBack }: { onBack: () => void }) {
 
         {/* Main player area */}
         <div className={`main-area ${isMobile && activeTab !== 'player' ? 'hidden-mobile' : ''}`}>
+          {handedOffTo && (
+            <div className="restore-banner">
+              <span className="restore-banner-label">
+                This room moved to another host; guests were sent there.
+              </span>
+              <div className="restore-banner-actions">
+                <button className="btn-sm btn-secondary" onClick={onBack}>
+                  Leave
+                </button>
+              </div>
+            </div>
+          )}
+          {restorableSession && (
+            <div className="restore-banner">
+              <span className="restore-banner-label">
+                Pick up where you left off?{' '}
+                {restorableSession.currentSong ? `“${restorableSession.currentSong}” and ` : ''}
+                {restorableSession.queueLength} queued song{restorableSession.queueLength === 1 ? '' : 's'} from{' '}
+                {new Date(restorableSession.savedAt).toLocaleTimeString([], { hour: '2-digit', minute: '2-digit' })}
+              </span>
+              <div className="restore-banner-actions">
+                <button className="btn-sm btn-primary" onClick={() => resolveSession(true)}>
+                  <History size={14} /> Restore
+                </button>
+                <button className="btn-sm btn-secondary" onClick={() => resolveSession(false)}>
+                  Discard
+                </button>
+              </div>
+            </div>
+          )}
           <Player />
           <footer className="yt-footer-host">
             Videos are played via YouTube embedding. All videos are subject to YouTube's{' '}
@@ -150,10 +194,34 @@ function HostView({ onBack }: { onBack: () => void }) {
         <div className={`panel-wrapper ${isMobile && activeTab !== 'controls' ? 'hidden-mobile' : ''}`}>
           <ControlPanel
             connectionUrl={connectionUrl}
+            invites={invites}
+            onCreateInvite={createInvite}
+            onRevokeInvite={revokeInvite}
+            pin={pin}
+            baseUrl={baseUrl}
+            onRegeneratePin={regeneratePin}
+            onNetworkChanged={refreshBaseUrl}
+            handoffCode={handoffCode}
+            onOfferHandoff={offerHandoff}
+            onCancelHandoff={cancelHandoff}
+            onTakeOverRoom={takeOverRoom}
             roomId={roomState?.roomId}
             queue={roomState?.queue || []}
             playlists={roomState?.playlists || []}
             connectedClients={connectedClients}
+            guests={guestIds.map(id => {
+              const client = roomState?.connectedClients.find(c => c.id === id);
+              return { id, displayName: client?.displayName, overflow: client?.overflow };
+            })}
+            roles={roomState?.roles}
+            onKickGuest={kickGuest}
+            lobbyEnabled={lobbyEnabled}
+            lobby={lobby}
+            onSetLobby={setLobbyMode}
+            onApproveGuest={approveGuest}
+            onRejectGuest={rejectGuest}
+            capacity={capacity}
+            onSetCapacity={setCapacity}
             isCollapsed={isMobile ? false : isPanelCollapsed}
             onToggle={() => setIsPanelCollapsed(!isPanelCollapsed)}
             onSearch={handleSearch}
@@ -166,8 +234,14 @@ function HostView({ onBack }: { onBack: () => void }) {
             isMuted={roomState?.player.isMuted}
             currentTime={roomState?.player.currentTime}
             duration={roomState?.player.duration}
+            canUndo={roomState?.history.canUndo}
+            canRedo={roomState?.history.canRedo}
+            autoDj={roomState?.autoDj}
+            skipPolicy={roomState?.skipPolicy}
+            skipVotes={roomState?.skipVotes}
             isMobile={isMobile}
             onBack={onBack}
+            onOpenRoom={isMobile ? undefined : handleOpenRoom}
           />
         </div>
       </div>
@@ -340,7 +414,10 @@ function GuestView({ onBack }: { onBack: () => void }) {
 
 // ---- Root App ----
 function App() {
-  const [appMode, setAppMode] = useState<AppMode>('select');
+  // Room windows opened from Host Mode start straight in it
+  const [appMode, setAppMode] = useState<AppMode>(
+    () => new URLSearchParams(window.location.search).get('mode') === 'host' ? 'host' : 'select',
+  );
   const [showHelp, setShowHelp] = useState(false);
 
   const handleBack = useCallback(() => setAppMode('select'), []);
@@ -1,18 +1,20 @@
 import { useState, useRef, useEffect, useCallback, useReducer, memo } from 'react';
 import type { RefObject } from 'react';
-import { invoke } from '@tauri-apps/api/core';
 import { useFocusable, FocusContext } from '@noriginmedia/norigin-spatial-navigation';
 import QRDisplay from './QRDisplay';
 import Queue from './Queue';
 import { Song, PlaylistCollection } from '../hooks/useRoomState';
+import { DEFAULT_GUEST_ROLE } from '@karaokenatin/shared';
+import type { AutoDjConfig, LobbyEntry, Role, SkipPolicy, SkipVotes } from '@karaokenatin/shared';
 import { setHostInputFocused } from '../hooks/useRoomState';
-import { saveCollectionToFile, loadCollectionFromFile, getPlaylists, playlistAddSong, playlistCreateCollection, playlistDeleteCollection, playlistRenameCollection, playlistSetVisibility, playlistRemoveSong } from '../lib/commands';
+import { processCommand, listNetworkAddresses, getNetworkSettings, setNetworkSettings, getCertificateFingerprint, NetworkAddress, NetworkSettings, saveCollectionToFile, saveHistoryToFile, setAutoDj, setGuestRole, setSkipPolicy, loadCollectionFromFile, getPlaylists, playlistAddSong, playlistCreateCollection, playlistDeleteCollection, playlistRenameCollection, playlistSetVisibility, playlistRemoveSong } from '../lib/commands';
 import { addStatusReducer, initialAddStatusState } from './addStatusReducer';
+import type { InviteLink, InviteOptions } from '../hooks/usePeerHost';
 import {
     ChevronLeft, ChevronRight, Users, Search, Plus, Sun, Moon,
     Play, Pause, SkipForward, Music, Trash2, UserPlus,
     Globe, Lock, Pencil, Upload, Download, ChevronDown, ArrowLeft, Star,
-    Volume2, VolumeX,
+    Volume2, VolumeX, Undo2, Redo2, History, Radio, Shuffle, UserX, Ban, QrCode, Check, X, RefreshCw, AppWindow, ArrowRightLeft,
 } from 'lucide-react';
 
 interface SearchResult {
@@ -25,10 +27,41 @@ interface SearchResult {
 
 interface ControlPanelProps {
     connectionUrl: string;
+    /** Invites the host has issued; each one can be shown as a QR code. */
+    invites?: InviteLink[];
+    onCreateInvite?: (options: InviteOptions) => void;
+    onRevokeInvite?: (inviteId: string) => void;
+    /** Room PIN and the address to type it at, for guests who cannot scan */
+    pin?: string;
+    baseUrl?: string;
+    onRegeneratePin?: () => void;
+    /** Called after the host picks another address for the QR code */
+    onNetworkChanged?: () => void;
+    /**
+     * Moving the room between hosts: the code while this room is offered, and
+     * taking over another host's room by its address and code.
+     */
+    handoffCode?: string | null;
+    onOfferHandoff?: () => void;
+    onCancelHandoff?: () => void;
+    onTakeOverRoom?: (address: string, code: string) => Promise<void>;
     roomId?: string;
     queue: Song[];
     playlists: PlaylistCollection[];
     connectedClients: number;
+    /** Open guest connections, by PeerJS id, and the roles the host gave them. */
+    guests?: { id: string; displayName?: string; overflow?: boolean }[];
+    roles?: Record<string, Role>;
+    onKickGuest?: (guestId: string, ban: boolean) => void;
+    /** Join approval: whether guests wait in a lobby, and who is waiting. */
+    lobbyEnabled?: boolean;
+    lobby?: LobbyEntry[];
+    onSetLobby?: (enabled: boolean) => void;
+    onApproveGuest?: (requestId: string) => void;
+    onRejectGuest?: (requestId: string) => void;
+    /** Seats before guests join as spectators; `null` until the room exists. */
+    capacity?: number | null;
+    onSetCapacity?: (capacity: number) => void;
     isCollapsed: boolean;
     onToggle: () => void;
     onSearch: (query: string) => void;
@@ -42,8 +75,49 @@ interface ControlPanelProps {
     isMuted?: boolean;
     currentTime?: number;
     duration?: number;
+    /** Whether the backend has a queue change to UNDO/REDO. */
+    canUndo?: boolean;
+    canRedo?: boolean;
+    /** Current Auto-DJ setting, `null` when off. */
+    autoDj?: AutoDjConfig | null;
+    /** Guest skip settings and the votes so far on the current song. */
+    skipPolicy?: SkipPolicy;
+    skipVotes?: SkipVotes;
     isMobile?: boolean;
     onBack?: () => void;
+    /** Open a side room in a new host window; absent where that is unsupported */
+    onOpenRoom?: () => void;
+}
+
+const ROLE_LABELS: Record<Role, string> = {
+    host: 'Host',
+    cohost: 'Co-host',
+    singer: 'Singer',
+    spectator: 'Spectator',
+};
+
+/** Invite limits the host cycles through; `undefined` is no limit. */
+const INVITE_EXPIRY_CHOICES: (number | undefined)[] = [undefined, 30 * 60, 2 * 60 * 60];
+const INVITE_USE_CHOICES: (number | undefined)[] = [undefined, 1, 5, 10];
+
+/** Room capacities the host cycles through. */
+const CAPACITY_CHOICES = [10, 20, 30, 50, 100];
+
+function formatExpiry(secs: number | undefined): string {
+    if (secs === undefined) return 'No expiry';
+    return secs < 3600 ? `${secs / 60} min` : `${secs / 3600} h`;
+}
+
+/** One-line status for an invite row, e.g. "2/5 used · until 21:30". */
+export function describeInvite(invite: InviteLink, now: number): string {
+    if (invite.expiresAt != null && now >= invite.expiresAt) return 'Expired';
+    if (invite.maxUses != null && invite.uses >= invite.maxUses) return 'Used up';
+    const parts = [invite.maxUses != null ? `${invite.uses}/${invite.maxUses} used` : `${invite.uses} joined`];
+    if (invite.expiresAt != null) {
+        const until = new Date(invite.expiresAt).toLocaleTimeString([], { hour: '2-digit', minute: '2-digit' });
+        parts.push(`until ${until}`);
+    }
+    return parts.join(' · ');
 }
 
 /** mm:ss for the seek bar. Hours are not worth handling for karaoke tracks. */
@@ -379,10 +453,31 @@ const PlaylistSongRow = memo(function PlaylistSongRow({
 
 const ControlPanel = ({
     connectionUrl,
+    invites = [],
+    onCreateInvite,
+    onRevokeInvite,
+    pin,
+    baseUrl,
+    onRegeneratePin,
+    onNetworkChanged,
+    handoffCode = null,
+    onOfferHandoff,
+    onCancelHandoff,
+    onTakeOverRoom,
     roomId,
     queue,
     playlists,
     connectedClients,
+    guests = [],
+    roles = {},
+    onKickGuest,
+    lobbyEnabled = false,
+    lobby = [],
+    onSetLobby,
+    onApproveGuest,
+    onRejectGuest,
+    capacity = null,
+    onSetCapacity,
     isCollapsed,
     onToggle,
     onSearch,
@@ -395,12 +490,31 @@ const ControlPanel = ({
     isMuted = false,
     currentTime = 0,
     duration = 0,
+    canUndo = false,
+    canRedo = false,
+    autoDj = null,
+    skipPolicy,
+    skipVotes,
     isMobile,
     onBack,
+    onOpenRoom,
 }: ControlPanelProps) => {
     const [searchQuery, setSearchQuery] = useState('');
     const [theme, setTheme] = useState<'dark' | 'light'>('dark');
     const [showInvite, setShowInvite] = useState(false);
+    // Which invite's QR is shown; the room's default invite until one is picked
+    const [shownInviteId, setShownInviteId] = useState<string | null>(null);
+    const [inviteLabel, setInviteLabel] = useState('');
+    const [inviteExpiry, setInviteExpiry] = useState(0);
+    const [inviteUses, setInviteUses] = useState(0);
+    const [takeoverAddress, setTakeoverAddress] = useState('');
+    const [takeoverCode, setTakeoverCode] = useState('');
+    const [takeoverStatus, setTakeoverStatus] = useState<string | null>(null);
+    const [networkAddresses, setNetworkAddresses] = useState<NetworkAddress[]>([]);
+    const [networkSettings, setNetworkSettingsState] = useState<NetworkSettings | null>(null);
+    const [portDraft, setPortDraft] = useState('');
+    const [portRangeDraft, setPortRangeDraft] = useState('');
+    const [certificateFingerprint, setCertificateFingerprint] = useState<string | null>(null);
     // Per-result loading/success state for the three "add to…" actions.
     // Was six parallel useState<Set<string>> hooks; consolidated into one
     // reducer (see addStatusReducer.ts) so the near-identical
@@ -440,6 +554,54 @@ const ControlPanel = ({
         loadLocalPlaylists();
     }, [loadLocalPlaylists]);
 
+    // Interfaces come and go (VPNs, hotspots), so list them each time the
+    // invite panel opens
+    useEffect(() => {
+        if (!showInvite) return;
+        listNetworkAddresses().then(setNetworkAddresses).catch((e) => console.error('[ControlPanel] Failed to list addresses:', e));
+        getNetworkSettings().then((settings) => {
+            setNetworkSettingsState(settings);
+            setPortDraft(settings.preferredPort?.toString() ?? '');
+            setPortRangeDraft(settings.portRange ? `${settings.portRange.start}-${settings.portRange.end}` : '');
+        }).catch((e) => console.error('[ControlPanel] Failed to load network settings:', e));
+    }, [showInvite]);
+
+    // Made on first use, so only ask once HTTPS is on
+    useEffect(() => {
+        if (!showInvite || !networkSettings?.https) return;
+        getCertificateFingerprint().then(setCertificateFingerprint).catch((e) => console.error('[ControlPanel] Failed to read certificate:', e));
+    }, [showInvite, networkSettings?.https]);
+
+    const shownInviteUrl = invites.find(i => i.inviteId === shownInviteId)?.url ?? connectionUrl;
+    // More than one real adapter and no pick yet: show the other links to try
+    const networkUnclear = !!networkSettings && !networkSettings.preferredIp
+        && networkAddresses.filter(a => !a.likelyVirtual).length > 1;
+
+    const updateNetworkSettings = useCallback(async (next: NetworkSettings) => {
+        try {
+            await setNetworkSettings(next);
+            setNetworkSettingsState(next);
+            onNetworkChanged?.();
+        } catch (e) {
+            alert(e instanceof Error ? e.message : String(e));
+        }
+    }, [onNetworkChanged]);
+
+    // Blank fields mean "remember whatever it lands on" and "any free port"
+    const savePortSettings = useCallback(() => {
+        if (!networkSettings) return;
+        const range = portRangeDraft.trim().match(/^(\d+)\s*-\s*(\d+)$/);
+        if (portRangeDraft.trim() && !range) {
+            alert('Enter the range as first-last, e.g. 50000-50100');
+            return;
+        }
+        updateNetworkSettings({
+            ...networkSettings,
+            preferredPort: portDraft.trim() ? Number(portDraft) : null,
+            portRange: range ? { start: Number(range[1]), end: Number(range[2]) } : null,
+        });
+    }, [networkSettings, portDraft, portRangeDraft, updateNetworkSettings]);
+
     // Close picker on outside click
     useEffect(() => {
         const handleClickOutside = (e: MouseEvent) => {
@@ -496,9 +658,7 @@ const ControlPanel = ({
 
     const handlePlayPause = useCallback(async () => {
         try {
-            await invoke('process_command', {
-                command: { type: isPlaying ? 'PAUSE' : 'PLAY' },
-            });
+            await processCommand({ type: isPlaying ? 'PAUSE' : 'PLAY' });
         } catch (error) {
             console.error('[ControlPanel] Play/Pause failed:', error);
         }
@@ -506,9 +666,7 @@ const ControlPanel = ({
 
     const handleSkip = useCallback(async () => {
         try {
-            await invoke('process_command', {
-                command: { type: 'SKIP' },
-            });
+            await processCommand({ type: 'SKIP' });
         } catch (error) {
             console.error('[ControlPanel] Skip failed:', error);
         }
@@ -520,9 +678,7 @@ const ControlPanel = ({
         const clamped = Math.max(0, Math.min(100, Math.round(next)));
         setPendingVolume(clamped);
         try {
-            await invoke('process_command', {
-                command: { type: 'SET_VOLUME', volume: clamped },
-            });
+            await processCommand({ type: 'SET_VOLUME', volume: clamped });
         } catch (error) {
             console.error('[ControlPanel] Volume change failed:', error);
         }
@@ -530,7 +686,7 @@ const ControlPanel = ({
 
     const handleToggleMute = useCallback(async () => {
         try {
-            await invoke('process_command', { command: { type: 'TOGGLE_MUTE' } });
+            await processCommand({ type: 'TOGGLE_MUTE' });
         } catch (error) {
             console.error('[ControlPanel] Mute toggle failed:', error);
         }
@@ -539,21 +695,25 @@ const ControlPanel = ({
     const handleSeek = useCallback(async (seconds: number) => {
         setPendingSeek(null);
         try {
-            await invoke('process_command', {
-                command: { type: 'SEEK', time: Math.max(0, seconds) },
-            });
+            await processCommand({ type: 'SEEK', time: Math.max(0, seconds) });
         } catch (error) {
             console.error('[ControlPanel] Seek failed:', error);
         }
     }, []);
 
+    const handleHistory = useCallback(async (type: 'UNDO' | 'REDO') => {
+        try {
+            await processCommand({ type });
+        } catch (error) {
+            console.error(`[ControlPanel] ${type} failed:`, error);
+        }
+    }, []);
+
     const handleAddToQueue = useCallback(async (url: string) => {
         dispatchAddStatus({ type: 'START', target: 'queue', url });
         let success = false;
         try {
-            await invoke('process_command', {
-                command: { type: 'ADD_SONG', youtubeUrl: url, addedBy: 'Host' },
-            });
+            await processCommand({ type: 'ADD_SONG', youtubeUrl: url, addedBy: 'Host' });
             success = true;
         } catch (error) {
             console.error('[ControlPanel] Add to queue failed:', error);
@@ -631,9 +791,7 @@ const ControlPanel = ({
 
     const handlePlaylistToQueue = useCallback(async (collectionId: string, songId: string) => {
         try {
-            await invoke('process_command', {
-                command: { type: 'PLAYLIST_TO_QUEUE', songId, collectionId },
-            });
+            await processCommand({ type: 'PLAYLIST_TO_QUEUE', songId, collectionId });
         } catch (error) {
             console.error('[ControlPanel] Playlist to queue failed:', error);
         }
@@ -698,6 +856,62 @@ const ControlPanel = ({
         }
     }, []);
 
+    // Off -> in order -> shuffled -> off, for the collection being viewed.
+    // Picking a different collection switches the Auto-DJ over to it.
+    const handleCycleAutoDj = useCallback(async (collectionId: string) => {
+        let next: AutoDjConfig | null;
+        if (autoDj?.collectionId !== collectionId) {
+            next = { collectionId, order: 'sequential' };
+        } else if (autoDj.order === 'sequential') {
+            next = { collectionId, order: 'shuffle' };
+        } else {
+            next = null;
+        }
+        try {
+            await setAutoDj(next);
+        } catch (error) {
+            console.error('[ControlPanel] Set Auto-DJ failed:', error);
+        }
+    }, [autoDj]);
+
+    // Guests skip freely -> vote at 50% -> 75% -> everyone -> back to free.
+    // An overflow spectator is seated as a singer first
+    const handleCycleGuestRole = useCallback(async (guestId: string, overflow?: boolean) => {
+        const steps: Role[] = ['singer', 'spectator', 'cohost'];
+        const current = overflow ? -1 : steps.indexOf(roles[guestId] ?? DEFAULT_GUEST_ROLE);
+        try {
+            await setGuestRole(guestId, steps[(current + 1) % steps.length]);
+        } catch (error) {
+            console.error('[ControlPanel] Set guest role failed:', error);
+        }
+    }, [roles]);
+
+    const handleCycleSkipPolicy = useCallback(async () => {
+        const steps: SkipPolicy[] = [
+            { restrictSkip: false, voteThreshold: 0.5 },
+            { restrictSkip: true, voteThreshold: 0.5 },
+            { restrictSkip: true, voteThreshold: 0.75 },
+            { restrictSkip: true, voteThreshold: 1 },
+        ];
+        const current = steps.findIndex(p =>
+            p.restrictSkip === skipPolicy?.restrictSkip && p.voteThreshold === skipPolicy?.voteThreshold);
+        try {
+            await setSkipPolicy(steps[(current + 1) % steps.length]);
+        } catch (error) {
+            console.error('[ControlPanel] Set skip policy failed:', error);
+        }
+    }, [skipPolicy]);
+
+    const handleExportHistory = useCallback(async (format: 'csv' | 'json') => {
+        try {
+            await saveHistoryToFile(format);
+        } catch (error) {
+            console.error('[ControlPanel] Export history failed:', error);
+            if (typeof error === 'string' && error.includes('cancelled')) return;
+            alert('Failed to save file');
+        }
+    }, []);
+
     const handleLoadFromFile = useCallback(async () => {
         try {
             await loadCollectionFromFile();
@@ -760,6 +974,11 @@ const ControlPanel = ({
                                 <ArrowLeft size={18} />
                             </FocusableButton>
                         )}
+                        {onOpenRoom && (
+                            <FocusableButton className="btn-icon" onClick={onOpenRoom} title="Open another room in a new window">
+                                <AppWindow size={18} />
+                            </FocusableButton>
+                        )}
                         <FocusableButton className="btn-icon" onClick={toggleTheme}>
                             {theme === 'dark' ? <Sun size={18} /> : <Moon size={18} />}
                         </FocusableButton>
@@ -781,7 +1000,269 @@ const ControlPanel = ({
                         </FocusableButton>
                         {showInvite && (
                             <div style={{ marginTop: '12px' }}>
-                                <QRDisplay url={connectionUrl} roomId={roomId ?? null} />
+                                <QRDisplay
+                                    url={shownInviteUrl}
+                                    roomId={roomId ?? null}
+                                />
+                                {pin && baseUrl && (
+                                    <div className="status-item" style={{ justifyContent: 'space-between' }}>
+                                        <span>
+                                            Or enter PIN <strong>{pin.slice(0, 3)} {pin.slice(3)}</strong>
+                                            <span style={{ color: 'var(--text-secondary)', fontSize: '12px' }}>
+                                                {' '}at {baseUrl.replace(/^https?:\/\//, '')}
+                                            </span>
+                                        </span>
+                                        <FocusableButton
+                                            className="btn-sm btn-secondary"
+                                            onClick={() => onRegeneratePin?.()}
+                                            title="New PIN; guests already in stay"
+                                        >
+                                            <RefreshCw size={14} />
+                                        </FocusableButton>
+                                    </div>
+                                )}
+                                {networkSettings && (
+                                    <>
+                                        <div className="section-label" style={{ marginTop: '12px' }}>Network</div>
+                                        {networkAddresses.length > 1 && (
+                                            <>
+                                                {networkUnclear && (
+                                                    <div style={{ color: 'var(--text-secondary)', fontSize: '12px' }}>
+                                                        This machine is on several networks. If guests cannot connect, pick the one they are on.
+                                                    </div>
+                                                )}
+                                                {networkAddresses.map(address => {
+                                                    const chosen = networkSettings.preferredIp === address.ip;
+                                                    return (
+                                                        <div key={`${address.interface}-${address.ip}`} className="status-item" style={{ justifyContent: 'space-between' }}>
+                                                            <span>
+                                                                {address.ip}
+                                                                <span style={{ color: 'var(--text-secondary)', fontSize: '12px' }}>
+                                                                    {' '}{address.interface}{address.likelyVirtual ? ' · virtual' : ''}
+                                                                </span>
+                                                                {networkUnclear && !address.likelyVirtual && baseUrl && shownInviteUrl && (
+                                                                    <span style={{ display: 'block', fontSize: '12px', wordBreak: 'break-all' }}>
+                                                                        {shownInviteUrl.replace(baseUrl, address.url)}
+                                                                    </span>
+                                                                )}
+                                                            </span>
+                                                            <FocusableButton
+                                                                className="btn-sm btn-secondary"
+                                                                onClick={() => updateNetworkSettings({
+                                                                    ...networkSettings,
+                                                                    preferredIp: chosen ? null : address.ip,
+                                                                    bindPreferredOnly: chosen ? false : networkSettings.bindPreferredOnly,
+                                                                })}
+                                                                title={chosen ? 'Go back to picking automatically' : 'Use this address for the QR code'}
+                                                            >
+                                                                {chosen ? <Check size={14} /> : 'Use'}
+                                                            </FocusableButton>
+                                                        </div>
+                                                    );
+                                                })}
+                                                {networkSettings.preferredIp && (
+                                                    <div className="status-item" style={{ justifyContent: 'space-between' }}>
+                                                        <span>
+                                                            {networkSettings.bindPreferredOnly ? `Listening on ${networkSettings.preferredIp} only` : 'Listening on every network'}
+                                                            <span style={{ color: 'var(--text-secondary)', fontSize: '12px' }}> · from next start</span>
+                                                        </span>
+                                                        <FocusableButton
+                                                            className="btn-sm btn-secondary"
+                                                            onClick={() => updateNetworkSettings({ ...networkSettings, bindPreferredOnly: !networkSettings.bindPreferredOnly })}
+                                                            title="Refuse guests on the other networks"
+                                                        >
+                                                            {networkSettings.bindPreferredOnly ? 'All' : 'Only this'}
+                                                        </FocusableButton>
+                                                    </div>
+                                                )}
+                                            </>
+                                        )}
+                                        <div className="status-item" style={{ gap: '4px' }}>
+                                            <input
+                                                type="text"
+                                                className="search-input"
+                                                inputMode="numeric"
+                                                placeholder="Port"
+                                                value={portDraft}
+                                                maxLength={5}
+                                                onChange={(e) => setPortDraft(e.target.value.replace(/\D/g, ''))}
+                                                onFocus={() => setHostInputFocused(true)}
+                                                onBlur={() => setHostInputFocused(false)}
+                                            />
+                                            <input
+                                                type="text"
+                                                className="search-input"
+                                                placeholder="Range, e.g. 50000-50100"
+                                                value={portRangeDraft}
+                                                maxLength={11}
+                                                onChange={(e) => setPortRangeDraft(e.target.value)}
+                                                onFocus={() => setHostInputFocused(true)}
+                                                onBlur={() => setHostInputFocused(false)}
+                                            />
+                                            <FocusableButton
+                                                className="btn-sm btn-secondary"
+                                                onClick={savePortSettings}
+                                                title="Tried first on the next start; another port from the range if it is taken"
+                                            >
+                                                <Check size={14} />
+                                            </FocusableButton>
+                                        </div>
+                                        <div style={{ color: 'var(--text-secondary)', fontSize: '12px' }}>
+                                            Port changes apply from next start
+                                        </div>
+                                        <div className="status-item" style={{ justifyContent: 'space-between' }}>
+                                            <span>
+                                                {networkSettings.https ? 'HTTPS for guests' : 'Plain HTTP for guests'}
+                                                <span style={{ color: 'var(--text-secondary)', fontSize: '12px' }}> · from next start</span>
+                                            </span>
+                                            <FocusableButton
+                                                className="btn-sm btn-secondary"
+                                                onClick={() => updateNetworkSettings({ ...networkSettings, https: !networkSettings.https })}
+                                                title="Phones only allow the microphone and keeping the screen on over HTTPS"
+                                            >
+                                                {networkSettings.https ? 'Off' : 'On'}
+                                            </FocusableButton>
+                                        </div>
+                                        {networkSettings.https && certificateFingerprint && (
+                                            <div style={{ color: 'var(--text-secondary)', fontSize: '12px', wordBreak: 'break-all' }}>
+                                                Guests' browsers will warn about this host's own certificate. Trust it only if its SHA-256 fingerprint reads:
+                                                <span style={{ display: 'block', fontFamily: 'monospace' }}>{certificateFingerprint}</span>
+                                            </div>
+                                        )}
+                                    </>
+                                )}
+                                {invites.map(invite => (
+                                    <div key={invite.inviteId} className="status-item" style={{ justifyContent: 'space-between' }}>
+                                        <span>
+                                            {invite.label ?? `Invite ${invite.inviteId}`}
+                                            <span style={{ color: 'var(--text-secondary)', fontSize: '12px' }}>
+                                                {' '}{describeInvite(invite, Date.now())}
+                                            </span>
+                                        </span>
+                                        <div style={{ display: 'flex', gap: '4px' }}>
+                                            <FocusableButton
+                                                className="btn-sm btn-secondary"
+                                                onClick={() => setShownInviteId(invite.inviteId)}
+                                                title="Show this invite's QR code"
+                                            >
+                                                <QrCode size={14} />
+                                            </FocusableButton>
+                                            <FocusableButton
+                                                className="btn-sm btn-secondary"
+                                                onClick={() => onRevokeInvite?.(invite.inviteId)}
+                                                title="Revoke this invite"
+                                            >
+                                                <Trash2 size={14} />
+                                            </FocusableButton>
+                                        </div>
+                                    </div>
+                                ))}
+                                <div className="status-item" style={{ gap: '4px' }}>
+                                    <input
+                                        type="text"
+                                        className="search-input"
+                                        placeholder="Label, e.g. table 3"
+                                        value={inviteLabel}
+                                        maxLength={40}
+                                        onChange={(e) => setInviteLabel(e.target.value)}
+                                        onFocus={() => setHostInputFocused(true)}
+                                        onBlur={() => setHostInputFocused(false)}
+                                    />
+                                    <FocusableButton
+                                        className="btn-sm btn-secondary"
+                                        onClick={() => setInviteExpiry((inviteExpiry + 1) % INVITE_EXPIRY_CHOICES.length)}
+                                        title="How long the invite works"
+                                    >
+                                        {formatExpiry(INVITE_EXPIRY_CHOICES[inviteExpiry])}
+                                    </FocusableButton>
+                                    <FocusableButton
+                                        className="btn-sm btn-secondary"
+                                        onClick={() => setInviteUses((inviteUses + 1) % INVITE_USE_CHOICES.length)}
+                                        title="How many guests can join with it"
+                                    >
+                                        {INVITE_USE_CHOICES[inviteUses] === undefined ? 'Any uses' : `${INVITE_USE_CHOICES[inviteUses]} use${INVITE_USE_CHOICES[inviteUses] === 1 ? '' : 's'}`}
+                                    </FocusableButton>
+                                    <FocusableButton
+                                        className="btn-sm btn-secondary"
+                                        onClick={() => {
+                                            onCreateInvite?.({
+                                                label: inviteLabel.trim() || undefined,
+                                                expiresInSecs: INVITE_EXPIRY_CHOICES[inviteExpiry],
+                                                maxUses: INVITE_USE_CHOICES[inviteUses],
+                                            });
+                                            setInviteLabel('');
+                                        }}
+                                        title="Issue a new invite"
+                                    >
+                                        <Plus size={14} />
+                                    </FocusableButton>
+                                </div>
+                                <div className="section-label" style={{ marginTop: '12px' }}>Move Room</div>
+                                {handoffCode ? (
+                                    <div className="status-item" style={{ justifyContent: 'space-between' }}>
+                                        <span>
+                                            Take over at {baseUrl?.replace(/^https?:\/\//, '')} with code <strong>{handoffCode}</strong>
+                                        </span>
+                                        <FocusableButton
+                                            className="btn-sm btn-secondary"
+                                            onClick={() => onCancelHandoff?.()}
+                                            title="Withdraw the hand-off code"
+                                        >
+                                            <X size={14} />
+                                        </FocusableButton>
+                                    </div>
+                                ) : (
+                                    <FocusableButton
+                                        className="btn-sm btn-secondary"
+                                        onClick={() => onOfferHandoff?.()}
+                                        title="Get a code another host can use to take this room over"
+                                    >
+                                        <ArrowRightLeft size={14} /> Hand off this room
+                                    </FocusableButton>
+                                )}
+                                <div className="status-item" style={{ gap: '4px' }}>
+                                    <input
+                                        type="text"
+                                        className="search-input"
+                                        placeholder="Other host, e.g. 192.168.1.20:8080"
+                                        value={takeoverAddress}
+                                        onChange={(e) => setTakeoverAddress(e.target.value)}
+                                        onFocus={() => setHostInputFocused(true)}
+                                        onBlur={() => setHostInputFocused(false)}
+                                    />
+                                    <input
+                                        type="text"
+                                        className="search-input"
+                                        placeholder="Code"
+                                        value={takeoverCode}
+                                        maxLength={12}
+                                        style={{ width: '90px' }}
+                                        onChange={(e) => setTakeoverCode(e.target.value)}
+                                        onFocus={() => setHostInputFocused(true)}
+                                        onBlur={() => setHostInputFocused(false)}
+                                    />
+                                    <FocusableButton
+                                        className="btn-sm btn-secondary"
+                                        onClick={async () => {
+                                            if (!onTakeOverRoom || !takeoverAddress.trim() || !takeoverCode.trim()) return;
+                                            setTakeoverStatus('Taking over…');
+                                            try {
+                                                await onTakeOverRoom(takeoverAddress, takeoverCode);
+                                                setTakeoverStatus('Room taken over; its guests are on their way');
+                                                setTakeoverAddress('');
+                                                setTakeoverCode('');
+                                            } catch (e) {
+                                                setTakeoverStatus(e instanceof Error ? e.message : String(e));
+                                            }
+                                        }}
+                                        title="Take over another host's room, guests included"
+                                    >
+                                        <ArrowRightLeft size={14} />
+                                    </FocusableButton>
+                                </div>
+                                {takeoverStatus && (
+                                    <div style={{ color: 'var(--text-secondary)', fontSize: '12px' }}>{takeoverStatus}</div>
+                                )}
                             </div>
                         )}
                     </div>
@@ -870,9 +1351,100 @@ const ControlPanel = ({
 
                     {/* Status */}
                     <div className="control-panel-section">
-                        <div className="status-item">
-                            <Users size={16} />
-                            <span>{connectedClients} connected</span>
+                        <div className="status-item" style={{ justifyContent: 'space-between' }}>
+                            <span style={{ display: 'flex', alignItems: 'center', gap: '8px' }}>
+                                <Users size={16} />
+                                {connectedClients} connected
+                            </span>
+                            {capacity !== null && (
+                                <FocusableButton
+                                    className="btn-sm btn-secondary"
+                                    onClick={() => {
+                                        const next = CAPACITY_CHOICES.find(c => c > capacity) ?? CAPACITY_CHOICES[0];
+                                        onSetCapacity?.(next);
+                                    }}
+                                    title="Seats in the room; later guests join as spectators"
+                                >
+                                    {capacity} seats
+                                </FocusableButton>
+                            )}
+                        </div>
+                        {guests.map(guest => (
+                            <div key={guest.id} className="status-item" style={{ justifyContent: 'space-between' }}>
+                                <span>{guest.displayName ?? `Guest ${guest.id.slice(0, 6)}`}</span>
+                                <div style={{ display: 'flex', gap: '4px' }}>
+                                    <FocusableButton
+                                        className="btn-sm btn-secondary"
+                                        onClick={() => handleCycleGuestRole(guest.id, guest.overflow)}
+                                        title={guest.overflow ? 'Joined past capacity; give them a seat' : 'Change what this guest can do'}
+                                    >
+                                        {guest.overflow ? 'Watching' : ROLE_LABELS[roles[guest.id] ?? DEFAULT_GUEST_ROLE]}
+                                    </FocusableButton>
+                                    <FocusableButton
+                                        className="btn-sm btn-secondary"
+                                        onClick={() => onKickGuest?.(guest.id, false)}
+                                        title="Remove this guest"
+                                    >
+                                        <UserX size={14} />
+                                    </FocusableButton>
+                                    <FocusableButton
+                                        className="btn-sm btn-secondary"
+                                        onClick={() => {
+                                            if (confirm('Ban this guest for the rest of the session?')) {
+                                                onKickGuest?.(guest.id, true);
+                                            }
+                                        }}
+                                        title="Remove and ban this guest"
+                                    >
+                                        <Ban size={14} />
+                                    </FocusableButton>
+                                </div>
+                            </div>
+                        ))}
+                        <div className="status-item" style={{ justifyContent: 'space-between' }}>
+                            <span>{lobbyEnabled ? `Lobby (${lobby.length} waiting)` : 'Guests join directly'}</span>
+                            <FocusableButton
+                                className="btn-sm btn-secondary"
+                                onClick={() => onSetLobby?.(!lobbyEnabled)}
+                                title="Make joining guests wait for your approval"
+                            >
+                                {lobbyEnabled ? 'Approve' : 'Open'}
+                            </FocusableButton>
+                        </div>
+                        {lobby.map(entry => (
+                            <div key={entry.requestId} className="status-item" style={{ justifyContent: 'space-between' }}>
+                                <span>{entry.displayName} wants to join</span>
+                                <div style={{ display: 'flex', gap: '4px' }}>
+                                    <FocusableButton
+                                        className="btn-sm btn-secondary"
+                                        onClick={() => onApproveGuest?.(entry.requestId)}
+                                        title="Let this guest in"
+                                    >
+                                        <Check size={14} />
+                                    </FocusableButton>
+                                    <FocusableButton
+                                        className="btn-sm btn-secondary"
+                                        onClick={() => onRejectGuest?.(entry.requestId)}
+                                        title="Turn this guest away"
+                                    >
+                                        <X size={14} />
+                                    </FocusableButton>
+                                </div>
+                            </div>
+                        ))}
+                        <div className="status-item" style={{ justifyContent: 'space-between' }}>
+                            <span>
+                                {skipPolicy?.restrictSkip
+                                    ? `Guests vote to skip (${skipVotes?.voters.length ?? 0}/${skipVotes?.needed ?? 1})`
+                                    : 'Guests can skip'}
+                            </span>
+                            <FocusableButton
+                                className="btn-sm btn-secondary"
+                                onClick={handleCycleSkipPolicy}
+                                title="Change who can skip songs"
+                            >
+                                {skipPolicy?.restrictSkip ? `${Math.round(skipPolicy.voteThreshold * 100)}%` : 'Anyone'}
+                            </FocusableButton>
                         </div>
                     </div>
 
@@ -955,10 +1527,53 @@ const ControlPanel = ({
 
                     {/* Queue Section */}
                     <div className="control-panel-section">
-                        <div className="section-label">Queue ({queue.length})</div>
+                        <div className="section-label" style={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center' }}>
+                            <span>Queue ({queue.length})</span>
+                            <div style={{ display: 'flex', gap: '4px' }}>
+                                <FocusableButton
+                                    className="btn-sm btn-secondary"
+                                    onClick={() => handleHistory('UNDO')}
+                                    title="Undo last queue change"
+                                    disabled={!canUndo}
+                                >
+                                    <Undo2 size={13} />
+                                </FocusableButton>
+                                <FocusableButton
+                                    className="btn-sm btn-secondary"
+                                    onClick={() => handleHistory('REDO')}
+                                    title="Redo queue change"
+                                    disabled={!canRedo}
+                                >
+                                    <Redo2 size={13} />
+                                </FocusableButton>
+                            </div>
+                        </div>
                         <Queue songs={queue} />
                     </div>
 
+                    {/* Session History Section */}
+                    <div className="control-panel-section">
+                        <div className="section-label" style={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center' }}>
+                            <span><History size={16} style={{ display: 'inline', verticalAlign: '-2px' }} /> Played Tonight</span>
+                            <div style={{ display: 'flex', gap: '4px' }}>
+                                <FocusableButton
+                                    className="btn-sm btn-secondary"
+                                    onClick={() => handleExportHistory('csv')}
+                                    title="Export what was sung as a spreadsheet"
+                                >
+                                    <Upload size={13} /> CSV
+                                </FocusableButton>
+                                <FocusableButton
+                                    className="btn-sm btn-secondary"
+                                    onClick={() => handleExportHistory('json')}
+                                    title="Export what was sung as JSON"
+                                >
+                                    <Upload size={13} /> JSON
+                                </FocusableButton>
+                            </div>
+                        </div>
+                    </div>
+
                     {/* Playlist Collections Section */}
                     <div className="control-panel-section">
                         <div className="section-label" style={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center' }}>
@@ -1008,9 +1623,7 @@ const ControlPanel = ({
                                 <button
                                     className="collection-tab collection-tab-add"
                                     onClick={() => {
-                                        invoke('process_command', {
-                                            command: { type: 'CREATE_COLLECTION', name: 'New Collection', visibility: 'public' },
-                                        });
+                                        processCommand({ type: 'CREATE_COLLECTION', name: 'New Collection', visibility: 'public' });
                                     }}
                                     title="Create new collection"
                                 >
@@ -1046,6 +1659,15 @@ const ControlPanel = ({
                                 >
                                     <Upload size={13} /> Export
                                 </FocusableButton>
+                                <FocusableButton
+                                    className={`btn-sm ${autoDj?.collectionId === activeCollection.id ? 'btn-primary' : 'btn-secondary'}`}
+                                    onClick={() => handleCycleAutoDj(activeCollection.id)}
+                                    title="Auto-DJ: keep the party going from this collection when the queue runs dry"
+                                >
+                                    {autoDj?.collectionId === activeCollection.id && autoDj.order === 'shuffle'
+                                        ? <><Shuffle size={13} /> Auto-DJ</>
+                                        : <><Radio size={13} /> Auto-DJ</>}
+                                </FocusableButton>
                                 {playlists.length > 1 && (
                                     <FocusableButton
                                         className="btn-sm btn-danger-text"
@@ -1,6 +1,7 @@
 import { useState, useEffect, useRef } from 'react';
-import { X, QrCode, ClipboardPaste } from 'lucide-react';
+import { X, QrCode, ClipboardPaste, Radar } from 'lucide-react';
 import { Html5Qrcode } from 'html5-qrcode';
+import { discoverHosts, DiscoveredHost } from '../lib/commands';
 
 interface GuestModeProps {
     onClose: () => void;
@@ -8,7 +9,7 @@ interface GuestModeProps {
 }
 
 /**
- * Guest Mode — scan QR / paste URL overlay.
+ * Guest Mode — scan QR / paste URL / find on network overlay.
  *
  * On successful URL resolution, calls onConnect(hostUrl) which lets App.tsx
  * render the remote-ui iframe in the main area (alongside ControlPanel).
@@ -18,6 +19,9 @@ export default function GuestMode({ onClose, onConnect }: GuestModeProps) {
     const [scanning, setScanning] = useState(false);
     const [pasteMode, setPasteMode] = useState(false);
     const [hostUrl, setHostUrl] = useState('');
+    // Rooms found on the LAN; `null` until a search has run
+    const [nearby, setNearby] = useState<DiscoveredHost[] | null>(null);
+    const [searching, setSearching] = useState(false);
     const scannerRef = useRef<Html5Qrcode | null>(null);
     const scannerContainerId = 'guest-qr-reader';
 
@@ -87,6 +91,18 @@ export default function GuestMode({ onClose, onConnect }: GuestModeProps) {
         }
     };
 
+    const findNearby = async () => {
+        setError('');
+        setSearching(true);
+        try {
+            setNearby(await discoverHosts());
+        } catch (err) {
+            setError(`Could not search the network: ${err instanceof Error ? err.message : String(err)}`);
+        } finally {
+            setSearching(false);
+        }
+    };
+
     const handlePasteFromClipboard = async () => {
         try {
             const text = await navigator.clipboard.readText();
@@ -124,7 +140,7 @@ export default function GuestMode({ onClose, onConnect }: GuestModeProps) {
             <div className="guest-mode-body">
                 <div className="guest-mode-scan">
                     <p className="guest-mode-desc">
-                        Scan the QR code shown on the host screen, or paste the room URL to join.
+                        Scan the QR code shown on the host screen, paste the room URL, or find the room on your network.
                     </p>
 
                     {error && <div className="guest-mode-error">{error}</div>}
@@ -137,6 +153,38 @@ export default function GuestMode({ onClose, onConnect }: GuestModeProps) {
                                 Cancel Scan
                             </button>
                         </div>
+                    ) : nearby !== null || searching ? (
+                        <div className="guest-paste-form">
+                            <label className="guest-mode-label">Rooms on this network</label>
+                            {searching ? (
+                                <p className="guest-mode-desc">Looking for rooms…</p>
+                            ) : nearby?.length === 0 ? (
+                                <p className="guest-mode-desc">No rooms found. Is the host on the same Wi-Fi?</p>
+                            ) : (
+                                nearby?.map(host => (
+                                    <button
+                                        key={`${host.roomId}@${host.address}`}
+                                        className="guest-action-card"
+                                        onClick={() => onConnect(host.url)}
+                                    >
+                                        <span className="guest-action-label">Room {host.roomId}</span>
+                                        <span className="guest-action-hint">{host.address} · join with the room PIN</span>
+                                    </button>
+                                ))
+                            )}
+                            <div className="guest-paste-buttons">
+                                <button
+                                    className="btn btn-secondary"
+                                    onClick={() => { setNearby(null); setError(''); }}
+                                    disabled={searching}
+                                >
+                                    Back
+                                </button>
+                                <button className="btn btn-primary" onClick={findNearby} disabled={searching}>
+                                    Search again
+                                </button>
+                            </div>
+                        </div>
                     ) : !pasteMode ? (
                         <div className="guest-mode-actions">
                             <button className="guest-action-card" onClick={startScanner}>
@@ -149,6 +197,11 @@ export default function GuestMode({ onClose, onConnect }: GuestModeProps) {
                                 <span className="guest-action-label">Paste URL</span>
                                 <span className="guest-action-hint">Enter or paste the host's room URL</span>
                             </button>
+                            <button className="guest-action-card" onClick={findNearby}>
+                                <Radar size={32} />
+                                <span className="guest-action-label">Find Nearby</span>
+                                <span className="guest-action-hint">List the rooms on this Wi-Fi</span>
+                            </button>
                         </div>
                     ) : (
                         <div className="guest-paste-form">
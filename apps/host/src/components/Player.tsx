@@ -2,7 +2,7 @@ import { useEffect, useRef, useState, useCallback } from 'react';
 import { useRoomState } from '../hooks/useRoomState';
 import { useMicCoverage, coverageToScore } from '../hooks/useMicCoverage';
 import { useWakeLock } from '../hooks/useWakeLock';
-import { invoke } from '@tauri-apps/api/core';
+import { finishSong, updatePlayerState as reportPlayerState } from '../lib/commands';
 import { useFocusable, FocusContext } from '@noriginmedia/norigin-spatial-navigation';
 import ScoringOverlay from './ScoringOverlay';
 
@@ -252,11 +252,7 @@ const Player = () => {
     // Update player state in Rust backend
     const updatePlayerState = async (status?: string, currentTime?: number, duration?: number) => {
         try {
-            await invoke('update_player_state', {
-                status: status || undefined,
-                currentTime: currentTime !== undefined ? currentTime : undefined,
-                duration: duration !== undefined ? duration : undefined,
-            });
+            await reportPlayerState({ status: status || undefined, currentTime, duration });
         } catch (error) {
             console.error('[Player] Failed to update player state:', error);
         }
@@ -290,7 +286,8 @@ const Player = () => {
         }, 1000);
     };
 
-    // Handle song ended - show scoring (real mic-coverage score) then skip.
+    // Handle song ended - show scoring (real mic-coverage score) then move on.
+    // `finish_song` advances like SKIP but records the song as completed.
     // Mic failures must never block the queue: if capture never started for
     // this song (denied, unavailable, errored), skip straight to the next
     // song instead of showing a score.
@@ -305,11 +302,9 @@ const Player = () => {
 
         if (!wasTracking) {
             try {
-                await invoke('process_command', {
-                    command: { type: 'SKIP' },
-                });
+                await finishSong();
             } catch (error) {
-                console.error('[Player] Failed to skip song:', error);
+                console.error('[Player] Failed to finish song:', error);
             }
             return;
         }
@@ -321,11 +316,9 @@ const Player = () => {
         } catch (error) {
             console.error('[Player] Failed to read mic coverage, skipping score:', error);
             try {
-                await invoke('process_command', {
-                    command: { type: 'SKIP' },
-                });
+                await finishSong();
             } catch (skipError) {
-                console.error('[Player] Failed to skip song:', skipError);
+                console.error('[Player] Failed to finish song:', skipError);
             }
         }
     }, [roomState?.player.currentSong?.title, micStop]);
@@ -334,11 +327,9 @@ const Player = () => {
     const handleScoringComplete = useCallback(async () => {
         setShowScoring(false);
         try {
-            await invoke('process_command', {
-                command: { type: 'SKIP' },
-            });
+            await finishSong();
         } catch (error) {
-            console.error('[Player] Failed to skip song:', error);
+            console.error('[Player] Failed to finish song:', error);
         }
     }, []);
 
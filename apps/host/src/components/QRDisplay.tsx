@@ -13,7 +13,7 @@ const QRDisplay = ({ url, roomId: _roomId }: QRDisplayProps) => {
     const [copied, setCopied] = useState(false);
 
     useEffect(() => {
-        // `url` comes from usePeerHost and already carries the join token
+        // `url` comes from usePeerHost and already carries an invite token
         // (?t=...). Prefer it: a URL fetched from get_qr_url has no token, and
         // since signaling now verifies tokens on every join, a guest scanning
         // a tokenless QR would simply be rejected.
@@ -1,5 +1,5 @@
 import { Song } from '@karaokenatin/shared';
-import { invoke } from '@tauri-apps/api/core';
+import { processCommand } from '../lib/commands';
 import { useFocusable, FocusContext } from '@noriginmedia/norigin-spatial-navigation';
 
 interface QueueProps {
@@ -62,9 +62,7 @@ const Queue = ({ songs }: QueueProps) => {
 
     const handleMoveUp = async (songId: string) => {
         try {
-            await invoke('process_command', {
-                command: { type: 'MOVE_SONG_UP', songId },
-            });
+            await processCommand({ type: 'MOVE_SONG_UP', songId });
         } catch (error) {
             console.error('[Queue] Failed to move song up:', error);
         }
@@ -72,9 +70,7 @@ const Queue = ({ songs }: QueueProps) => {
 
     const handleMoveDown = async (songId: string) => {
         try {
-            await invoke('process_command', {
-                command: { type: 'MOVE_SONG_DOWN', songId },
-            });
+            await processCommand({ type: 'MOVE_SONG_DOWN', songId });
         } catch (error) {
             console.error('[Queue] Failed to move song down:', error);
         }
@@ -86,9 +82,7 @@ const Queue = ({ songs }: QueueProps) => {
     // repeated "move up" taps were the only workaround.
     const handleMoveToTop = async (songId: string) => {
         try {
-            await invoke('process_command', {
-                command: { type: 'MOVE_SONG_TO_TOP', songId },
-            });
+            await processCommand({ type: 'MOVE_SONG_TO_TOP', songId });
         } catch (error) {
             console.error('[Queue] Failed to move song to top:', error);
         }
@@ -96,9 +90,7 @@ const Queue = ({ songs }: QueueProps) => {
 
     const handleRemove = async (songId: string) => {
         try {
-            await invoke('process_command', {
-                command: { type: 'REMOVE_SONG', songId },
-            });
+            await processCommand({ type: 'REMOVE_SONG', songId });
         } catch (error) {
             console.error('[Queue] Failed to remove song:', error);
         }
@@ -142,6 +134,7 @@ const Queue = ({ songs }: QueueProps) => {
                                 <div className="queue-title-compact">{song.title}</div>
                                 <div className="queue-meta-compact">
                                     {song.addedBy} • {formatDuration(song.duration)}
+                                    {song.voters && song.voters.length > 0 && ` • 👍 ${song.voters.length}`}
                                 </div>
                             </div>
 
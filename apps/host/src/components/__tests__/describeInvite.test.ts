@@ -0,0 +1,33 @@
+import { describe, it, expect } from 'vitest';
+import { describeInvite } from '../ControlPanel';
+import type { InviteLink } from '../../hooks/usePeerHost';
+
+const invite = (overrides: Partial<InviteLink> = {}): InviteLink => ({
+    inviteId: 'ABCD2345',
+    createdAt: 0,
+    uses: 0,
+    url: 'http://host/?t=token',
+    ...overrides,
+});
+
+describe('describeInvite', () => {
+    it('counts joins on an open invite', () => {
+        expect(describeInvite(invite({ uses: 3 }), 1000)).toBe('3 joined');
+    });
+
+    it('shows uses against the limit', () => {
+        expect(describeInvite(invite({ uses: 2, maxUses: 5 }), 1000)).toBe('2/5 used');
+    });
+
+    it('reports a spent invite as used up', () => {
+        expect(describeInvite(invite({ uses: 1, maxUses: 1 }), 1000)).toBe('Used up');
+    });
+
+    it('reports expiry before anything else', () => {
+        expect(describeInvite(invite({ uses: 1, maxUses: 1, expiresAt: 500 }), 1000)).toBe('Expired');
+    });
+
+    it('mentions when an unexpired invite runs out', () => {
+        expect(describeInvite(invite({ expiresAt: 5000 }), 1000)).toMatch(/^0 joined · until /);
+    });
+});
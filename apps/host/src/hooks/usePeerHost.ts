@@ -1,11 +1,111 @@
 import { useState, useEffect, useRef } from 'react';
 import Peer, { DataConnection } from 'peerjs';
 import { io, Socket } from 'socket.io-client';
-import { listen } from '@tauri-apps/api/event';
+import { getCurrentWebviewWindow } from '@tauri-apps/api/webviewWindow';
 import { invoke } from '@tauri-apps/api/core';
-import { HostBroadcast, isClientCommand, RoomState } from '@karaokenatin/shared';
-import { processCommand, getRoomState } from '../lib/commands';
-import { hashToken, generateRoomId, generateJoinToken } from '../lib/security';
+import { GuestView, HandoffPackage, HostBroadcast, Invite, isClientCommand, LobbyEntry, PublicBroadcast, RoomState } from '@karaokenatin/shared';
+import { processCommand, getPublicState, verifyGuest, closeRoom, importHandoff } from '../lib/commands';
+import { hashToken, generateRoomId, generateJoinToken, generatePin } from '../lib/security';
+
+/** What a guest the room does not know yet is told about themselves */
+const NO_VIEW: GuestView = { role: 'spectator', overflow: false, ownSongIds: [], votedSongIds: [], votedSkip: false };
+
+/**
+ * Turn a rejected `process_command` into an ERROR broadcast. Rust rejects with
+ * a serialised `CommandError` (`{ code, message, details? }`), which is passed
+ * through so the guest can tell a full queue from a cooldown.
+ */
+function toErrorBroadcast(error: unknown): HostBroadcast {
+    if (typeof error === 'object' && error !== null && 'code' in error && 'message' in error) {
+        const e = error as { code: unknown; message: unknown; details?: Record<string, unknown> };
+        return {
+            type: 'ERROR',
+            code: String(e.code),
+            message: String(e.message),
+            ...(e.details ? { details: e.details } : {}),
+        };
+    }
+    return {
+        type: 'ERROR',
+        code: 'COMMAND_FAILED',
+        message: error instanceof Error ? error.message : typeof error === 'string' ? error : 'Unknown error',
+    };
+}
+
+/**
+ * How long guests wait for the host after its signaling socket drops (say, a
+ * webview reload) before the server closes the room.
+ */
+const HOST_GRACE_SECS = 60;
+
+/**
+ * How often to ask for the room back while the server still counts our old
+ * socket as connected (until its ping times out)
+ */
+const RECLAIM_RETRY_MS = 5000;
+
+/** How long a hand-off code and the invite sent along with it stay valid */
+const HANDOFF_SECS = 600;
+
+/**
+ * Room credentials are kept for the life of the webview so a reloaded host
+ * can reclaim its room (CREATE_ROOM with the same host key hash) instead of
+ * stranding every guest. Invite tokens are kept alongside, since the server
+ * only has their hashes and the QR codes need the tokens.
+ */
+const ROOM_SESSION_KEY = 'karaoke_host_room';
+
+interface RoomCredentials {
+    roomId: string;
+    joinToken: string;
+    /** Never leaves this window except hashed; proves it hosts the room */
+    hostKey: string;
+    /** Invite tokens by invite id */
+    inviteTokens: Record<string, string>;
+    /** The invite created with the room, shown by default */
+    defaultInviteId?: string;
+    /** Six-digit PIN guests can type instead of scanning */
+    pin?: string;
+}
+
+function loadRoomCredentials(): RoomCredentials | null {
+    try {
+        const saved = JSON.parse(sessionStorage.getItem(ROOM_SESSION_KEY) || 'null');
+        if (!saved || typeof saved.roomId !== 'string' || typeof saved.joinToken !== 'string' || typeof saved.hostKey !== 'string') return null;
+        return { ...saved, inviteTokens: saved.inviteTokens ?? {} };
+    } catch {
+        return null;
+    }
+}
+
+function saveRoomCredentials(credentials: RoomCredentials) {
+    sessionStorage.setItem(ROOM_SESSION_KEY, JSON.stringify(credentials));
+}
+
+/**
+ * This window's room: the one in its session, or a new one. Each host window
+ * has its own session, so a side room opened in a second window gets its own
+ * ID, and the backend state and signaling room both go by it.
+ */
+export function ensureRoomCredentials(): RoomCredentials {
+    const saved = loadRoomCredentials();
+    if (saved) return saved;
+    const room: RoomCredentials = { roomId: generateRoomId(), joinToken: generateJoinToken(), hostKey: generateJoinToken(), inviteTokens: {} };
+    saveRoomCredentials(room);
+    return room;
+}
+
+/** Options for a new invite; leave a limit out for no limit */
+export interface InviteOptions {
+    label?: string;
+    expiresInSecs?: number;
+    maxUses?: number;
+}
+
+/** An invite with the link that carries its token */
+export interface InviteLink extends Invite {
+    url: string;
+}
 
 /**
  * Hook to manage PeerJS host and WebRTC connections
@@ -14,10 +114,18 @@ export function usePeerHost() {
     const [peer, setPeer] = useState<Peer | null>(null);
     const [connections, setConnections] = useState<Map<string, DataConnection>>(new Map());
     const connectionsRef = useRef<Map<string, DataConnection>>(new Map());
-    const [connectionUrl, setConnectionUrl] = useState<string>('');
-    // Held in state so the socket survives re-renders; the cleanup path uses the
-    // local `socketInstance` binding instead, so this value is write-only.
-    const [, setSocket] = useState<Socket | null>(null);
+    // Held in state so the socket survives re-renders and `kickGuest` can reach
+    // it; the cleanup path uses the local `socketInstance` binding instead.
+    const [socket, setSocket] = useState<Socket | null>(null);
+    const [invites, setInvites] = useState<Invite[]>([]);
+    // Mirrors the session credentials so links re-render as tokens change
+    const [credentials, setCredentials] = useState<RoomCredentials | null>(null);
+    const [baseUrl, setBaseUrl] = useState<string>('');
+    const [lobbyEnabled, setLobbyEnabled] = useState(false);
+    const [capacity, setCapacityState] = useState<number | null>(null);
+    const [lobby, setLobby] = useState<LobbyEntry[]>([]);
+    const [handoffCode, setHandoffCode] = useState<string | null>(null);
+    const [handedOffTo, setHandedOffTo] = useState<string | null>(null);
 
     // Keep ref in sync with state
     useEffect(() => {
@@ -49,7 +157,7 @@ export function usePeerHost() {
     // Subscribe to room state updates and broadcast to all connected peers
     useEffect(() => {
         // `room_state_public` is emitted by Rust with personal collections
-        // already stripped (see emit_state in commands.rs). Do not switch this
+        // and peer ids already stripped (see emit_state in commands.rs). Do not switch this
         // to `room_state_updated` — that carries the host's private playlists
         // and this handler forwards its payload straight to every guest.
         const broadcast = (message: HostBroadcast) => {
@@ -60,15 +168,26 @@ export function usePeerHost() {
             });
         };
 
-        const unlistenFull = listen<RoomState>('room_state_public', (event) => {
-            broadcast({ type: 'STATE_UPDATE', state: event.payload });
+        // Rust sends each room's events to its own window only; the global
+        // `listen` would also pick up other rooms' and relay them here.
+        const win = getCurrentWebviewWindow();
+        // Each guest gets only their own view (role, own songs, votes); peer
+        // ids and who voted for what stay on the host.
+        const unlistenFull = win.listen<PublicBroadcast>('room_state_public', (event) => {
+            const { state, viewers } = event.payload;
+            connectionsRef.current.forEach((conn, peerId) => {
+                if (conn.open) {
+                    const message: HostBroadcast = { type: 'STATE_UPDATE', state, you: viewers[peerId] ?? NO_VIEW };
+                    conn.send(message);
+                }
+            });
         });
 
         // Player progress ticks arrive several times a minute and previously
         // resent the entire room — queue plus every public collection — to
         // every guest just to move a timestamp. `player` is a self-contained
         // subtree, so patching it cannot desync the rest.
-        const unlistenPlayer = listen<RoomState['player']>('room_player_patch', (event) => {
+        const unlistenPlayer = win.listen<RoomState['player']>('room_player_patch', (event) => {
             broadcast({ type: 'STATE_PATCH', patch: { player: event.payload } });
         });
 
@@ -110,34 +229,63 @@ export function usePeerHost() {
         peerInstance.on('open', async (peerId) => {
             console.log('[PeerHost] Peer ID:', peerId);
 
-            // Generate room credentials
-            const roomId = generateRoomId();
-            const joinToken = generateJoinToken();
+            // Reuse this webview's room if it had one, so reloading reclaims it
+            const room = ensureRoomCredentials();
+            const { roomId, joinToken, hostKey } = room;
+            setCredentials(room);
             const joinTokenHash = await hashToken(joinToken);
+            const hostKeyHash = await hashToken(hostKey);
 
             // Connect to signaling server (same embedded server, same port)
             socketInstance = io(`http://localhost:${port}`);
 
-            // Include peerId when creating room so clients can connect
-            socketInstance.emit('CREATE_ROOM', { roomId, joinTokenHash, hostPeerId: peerId });
-
-            socketInstance.on('ROOM_CREATED', async () => {
-                console.log('[PeerHost] Room created on signaling server');
-                // Get the base URL (http://ip:port) from the backend
-                try {
-                    const baseUrl = await invoke<string>('get_qr_url');
-                    // The token rides in the QR URL. remote-ui reads ?t= and
-                    // sends it as joinToken, which the signaling server now
-                    // verifies for every join (see signaling.rs JOIN_ROOM).
-                    // Without this the token would be unusable and the server
-                    // would have to accept anonymous joins.
-                    setConnectionUrl(`${baseUrl}/?t=${encodeURIComponent(joinToken)}`);
-                } catch (e) {
-                    console.error('Failed to get QR URL:', e);
-                    setConnectionUrl(`${window.location.origin}/?t=${encodeURIComponent(joinToken)}`);
+            // Include peerId when creating room so clients can connect. Sent
+            // on every (re)connect: a new socket reclaims the room it created.
+            const socketForRoom = socketInstance;
+            const claimRoom = () => {
+                socketForRoom.emit('CREATE_ROOM', { roomId, joinTokenHash, hostKeyHash, hostPeerId: peerId, hostGraceSecs: HOST_GRACE_SECS });
+            };
+            socketForRoom.on('connect', claimRoom);
+
+            socketInstance.on('ROOM_CREATED', async ({ reclaimed }: { reclaimed?: boolean }) => {
+                console.log(reclaimed ? '[PeerHost] Reclaimed room on signaling server' : '[PeerHost] Room created on signaling server');
+                await refreshBaseUrl();
+                // A new room starts with one open invite, the QR shown by
+                // default; a reclaimed room still has its invites
+                if (!reclaimed) {
+                    const defaultInviteId = await issueInvite(socketForRoom, {});
+                    updateCredentials((c) => ({ ...c, defaultInviteId }));
+                    await issuePin(socketForRoom, loadRoomCredentials()?.pin ?? generatePin());
                 }
             });
 
+            socketInstance.on('INVITES_UPDATED', ({ invites }: { invites: Invite[] }) => {
+                setInvites(invites);
+            });
+
+            socketInstance.on('CAPACITY_UPDATED', ({ capacity }: { capacity: number }) => {
+                setCapacityState(capacity);
+            });
+
+            socketInstance.on('LOBBY_UPDATED', ({ enabled, pending }: { enabled: boolean; pending: LobbyEntry[] }) => {
+                setLobbyEnabled(enabled);
+                setLobby(pending);
+            });
+
+            // Another host claimed this room; the server has closed it here
+            socketInstance.on('HOST_MOVED', ({ url }: { url: string }) => {
+                setHandoffCode(null);
+                setHandedOffTo(url);
+            });
+
+            socketInstance.on('ERROR', ({ code, message }: { code: string; message: string }) => {
+                console.error('[PeerHost] Signaling error:', code, message);
+                if (code === 'APPROVE_FAILED') alert(message);
+                // The server has not yet noticed our old socket dropped, and
+                // only hands the room back once it has
+                if (code === 'HOST_STILL_CONNECTED') setTimeout(claimRoom, RECLAIM_RETRY_MS);
+            });
+
             setSocket(socketInstance);
         });
 
@@ -160,12 +308,34 @@ export function usePeerHost() {
             // which would otherwise leave an orphaned Peer and socket alive.
             cancelled = true;
             peerInstance?.destroy();
+            // Leaving Host Mode closes the room for good; only a reload,
+            // which never runs this cleanup, gets the grace period
+            socketInstance?.emit('LEAVE_ROOM');
+            closeRoom().catch((e) => console.error('[PeerHost] Failed to close room:', e));
+            sessionStorage.removeItem(ROOM_SESSION_KEY);
+            setCredentials(null);
             socketInstance?.disconnect();
         };
     }, []);
 
     const setupDataChannelHandlers = (conn: DataConnection) => {
-        conn.on('open', () => {
+        // Guests pick their PeerJS id at the broker, so the id alone proves
+        // nothing: the connection must carry the device token signaling issued
+        // for that id. Nothing it sends is handled until Rust has checked it
+        // (see verify_guest in commands.rs).
+        const deviceToken = conn.metadata?.deviceToken;
+        const verified = verifyGuest(conn.peer, typeof deviceToken === 'string' ? deviceToken : '')
+            .catch((e) => {
+                console.error('[PeerHost] Failed to verify guest:', e);
+                return false;
+            });
+
+        conn.on('open', async () => {
+            if (!(await verified)) {
+                console.warn('[PeerHost] Closing connection without a valid device token:', conn.peer);
+                conn.close();
+                return;
+            }
             console.log('[PeerHost] DataChannel open:', conn.peer);
             setConnections((prev) => new Map(prev).set(conn.peer, conn));
 
@@ -174,6 +344,7 @@ export function usePeerHost() {
         });
 
         conn.on('data', async (data) => {
+            if (!(await verified)) return;
             console.log('[PeerHost] Received data:', data);
 
             // Handle SEARCH command separately (not a standard ClientCommand)
@@ -215,24 +386,22 @@ export function usePeerHost() {
             if (isClientCommand(data)) {
                 console.log('[PeerHost] Received command:', data);
                 try {
-                    // Process command in Rust backend
-                    await processCommand(data);
-                    // State update will be broadcast via Tauri event
+                    // Process command in Rust backend, tagged with the sender
+                    // so per-guest queue limits apply.
+                    const reply = await processCommand(data, conn.peer);
+                    // Queries are answered to the sender only; state updates
+                    // are broadcast via Tauri event
+                    if (reply) conn.send(reply);
                 } catch (error) {
                     console.error('[PeerHost] Command processing failed:', error);
-                    const errorMsg: HostBroadcast = {
-                        type: 'ERROR',
-                        code: 'COMMAND_FAILED',
-                        message: error instanceof Error ? error.message : 'Unknown error',
-                    };
-                    conn.send(errorMsg);
+                    conn.send(toErrorBroadcast(error));
                 }
             }
         });
 
         conn.on('close', () => {
             console.log('[PeerHost] Connection closed:', conn.peer);
-            dropConnection(conn.peer);
+            dropConnection(conn.peer, conn);
         });
 
         // Without this, a guest whose phone slept or briefly dropped Wi-Fi —
@@ -241,13 +410,17 @@ export function usePeerHost() {
         // the client count was permanently wrong.
         conn.on('error', (err) => {
             console.warn('[PeerHost] Connection error, dropping peer:', conn.peer, err);
-            dropConnection(conn.peer);
+            dropConnection(conn.peer, conn);
         });
     };
 
-    const dropConnection = (peerId: string) => {
+    // Guests keep their PeerJS id across reconnects, so a stale channel closing
+    // late must not drop the fresh one that replaced it: pass `conn` to only
+    // drop that exact connection.
+    const dropConnection = (peerId: string, conn?: DataConnection) => {
         setConnections((prev) => {
             if (!prev.has(peerId)) return prev;
+            if (conn && prev.get(peerId) !== conn) return prev;
             const next = new Map(prev);
             next.delete(peerId);
             return next;
@@ -256,16 +429,11 @@ export function usePeerHost() {
 
     const sendStateUpdate = async (conn: DataConnection) => {
         try {
-            const state = await getRoomState();
-            const publicState = {
-                ...state,
-                playlists: (state.playlists || []).filter(
-                    (c: { visibility: string }) => c.visibility === 'public'
-                ),
-            };
+            const { state, viewers } = await getPublicState();
             const broadcast: HostBroadcast = {
                 type: 'STATE_UPDATE',
-                state: publicState,
+                state,
+                you: viewers[conn.peer] ?? NO_VIEW,
             };
             conn.send(broadcast);
         } catch (error) {
@@ -273,6 +441,155 @@ export function usePeerHost() {
         }
     };
 
+    /**
+     * Remove a guest: the signaling server closes their socket and relay
+     * connection, and with `ban` refuses their device until the room closes.
+     */
+    const kickGuest = (peerId: string, ban = false) => {
+        socket?.emit('KICK_CLIENT', {
+            peerId,
+            reason: ban ? 'You were banned by the host' : 'You were removed by the host',
+            ban,
+        });
+        connectionsRef.current.get(peerId)?.close();
+        dropConnection(peerId);
+    };
+
+    /**
+     * Get the base URL (http://ip:port) from the backend, again whenever the
+     * host picks another network address for the QR code
+     */
+    const refreshBaseUrl = async () => {
+        try {
+            setBaseUrl(await invoke<string>('get_qr_url'));
+        } catch (e) {
+            console.error('Failed to get QR URL:', e);
+            setBaseUrl(window.location.origin);
+        }
+    };
+
+    const updateCredentials = (update: (c: RoomCredentials) => RoomCredentials) => {
+        const current = loadRoomCredentials();
+        if (!current) return;
+        const next = update(current);
+        saveRoomCredentials(next);
+        setCredentials(next);
+    };
+
+    /**
+     * Mint an invite token, keep it for the QR code and register its hash.
+     * The signaling server answers with INVITES_UPDATED.
+     */
+    const issueInvite = async (target: Socket, options: InviteOptions): Promise<string> => {
+        const inviteId = generateRoomId();
+        const token = generateJoinToken();
+        updateCredentials((c) => ({ ...c, inviteTokens: { ...c.inviteTokens, [inviteId]: token } }));
+        target.emit('CREATE_INVITE', { inviteId, tokenHash: await hashToken(token), ...options });
+        return inviteId;
+    };
+
+    /** Set the room PIN; the server only ever sees its hash */
+    const issuePin = async (target: Socket, pin: string) => {
+        updateCredentials((c) => ({ ...c, pin }));
+        target.emit('SET_PIN', { pinHash: await hashToken(pin) });
+    };
+
+    /** New PIN, e.g. after it was shared too widely. Guests already in stay. */
+    const regeneratePin = async () => {
+        if (socket) await issuePin(socket, generatePin());
+    };
+
+    /**
+     * Let another host take this room over. The code is read out or typed at
+     * the new host, which claims the room from our signaling server.
+     */
+    const offerHandoff = async () => {
+        if (!socket) return;
+        const code = generateRoomId();
+        socket.emit('OFFER_HANDOFF', { codeHash: await hashToken(code) });
+        setHandoffCode(code);
+    };
+
+    const cancelHandoff = () => {
+        socket?.emit('OFFER_HANDOFF', { codeHash: null });
+        setHandoffCode(null);
+    };
+
+    /**
+     * Take over the room offered at `address` (the other host's ip:port) into
+     * this window's room. Its guests are sent to a fresh invite here, so they
+     * need not scan a new QR code. Rejects with the other host's reason.
+     */
+    const takeOverRoom = async (address: string, code: string) => {
+        if (!socket || !baseUrl) throw new Error('This room is not ready yet');
+        const inviteId = await issueInvite(socket, { label: 'Hand-off', expiresInSecs: HANDOFF_SECS });
+        const room = loadRoomCredentials();
+        const token = room?.inviteTokens[inviteId];
+        if (!room || !token) throw new Error('Could not create an invite for the guests');
+        const hostUrl = `${baseUrl}/?r=${encodeURIComponent(room.roomId)}&t=${encodeURIComponent(token)}`;
+
+        const target = address.trim().replace(/^https?:\/\//, '').replace(/\/+$/, '');
+        const other = io(`http://${target}`, { reconnection: false, timeout: 10000, forceNew: true });
+        try {
+            const handoff = await new Promise<HandoffPackage>((resolve, reject) => {
+                other.on('connect', () => other.emit('CLAIM_HANDOFF', { code, hostUrl }));
+                other.on('connect_error', () => reject(new Error(`Could not reach ${target}`)));
+                other.on('HANDOFF_PACKAGE', ({ room }: { room: HandoffPackage }) => resolve(room));
+                other.on('ERROR', ({ code, message }: { code: string; message: string }) => {
+                    if (code === 'HANDOFF_FAILED') reject(new Error(message));
+                });
+            });
+            await importHandoff(handoff);
+        } finally {
+            other.disconnect();
+        }
+    };
+
+    const createInvite = async (options: InviteOptions) => {
+        if (socket) await issueInvite(socket, options);
+    };
+
+    const revokeInvite = (inviteId: string) => {
+        socket?.emit('REVOKE_INVITE', { inviteId });
+        updateCredentials((c) => {
+            const inviteTokens = { ...c.inviteTokens };
+            delete inviteTokens[inviteId];
+            return { ...c, inviteTokens };
+        });
+    };
+
+    // The token rides in the link. remote-ui reads ?t= and sends it as
+    // joinToken, which the signaling server checks against the room's invites
+    // on every join (see signaling.rs JOIN_ROOM). ?r= names the room, since
+    // one server can host several.
+    const inviteLinks: InviteLink[] = baseUrl && credentials
+        ? invites
+            .filter((invite) => credentials.inviteTokens[invite.inviteId])
+            .map((invite) => ({
+                ...invite,
+                url: `${baseUrl}/?r=${encodeURIComponent(credentials.roomId)}&t=${encodeURIComponent(credentials.inviteTokens[invite.inviteId])}`,
+            }))
+        : [];
+    const connectionUrl = inviteLinks.find((link) => link.inviteId === credentials?.defaultInviteId)?.url ?? '';
+
+    /** Seats in the room; guests past it join as read-only spectators */
+    const setCapacity = (next: number) => {
+        socket?.emit('SET_CAPACITY', { capacity: next });
+    };
+
+    /** Park joining guests until approved. Capacity is checked on approval. */
+    const setLobbyMode = (enabled: boolean) => {
+        socket?.emit('SET_LOBBY', { enabled });
+    };
+
+    const approveGuest = (requestId: string) => {
+        socket?.emit('APPROVE_GUEST', { requestId });
+    };
+
+    const rejectGuest = (requestId: string) => {
+        socket?.emit('REJECT_GUEST', { requestId });
+    };
+
     const broadcastToAll = (message: HostBroadcast) => {
         connections.forEach((conn) => {
             if (conn.open) {
@@ -285,6 +602,33 @@ export function usePeerHost() {
         peer,
         connectionUrl,
         connectedClients: connections.size,
+        /** PeerJS ids of the open guest connections, the keys of `RoomState.roles` */
+        guestIds: Array.from(connections.keys()),
+        kickGuest,
+        /** The room's invites, newest last */
+        invites: inviteLinks,
+        createInvite,
+        revokeInvite,
+        /** Typed at `baseUrl` as an alternative to the QR code */
+        pin: credentials?.pin ?? '',
+        baseUrl,
+        refreshBaseUrl,
+        regeneratePin,
+        capacity,
+        setCapacity,
+        lobbyEnabled,
+        /** Guests waiting for approval, oldest first */
+        lobby,
+        setLobbyMode,
+        approveGuest,
+        rejectGuest,
+        /** The code another host needs to take this room over, while offered */
+        handoffCode,
+        offerHandoff,
+        cancelHandoff,
+        takeOverRoom,
+        /** Where this room went after another host took it over */
+        handedOffTo,
         broadcastToAll,
     };
 }
@@ -1,10 +1,10 @@
 import { useState, useEffect } from 'react';
-import { listen } from '@tauri-apps/api/event';
+import { getCurrentWebviewWindow } from '@tauri-apps/api/webviewWindow';
 import { RoomState, Song, PlaylistCollection } from '@karaokenatin/shared';
-import { createRoom, getRoomState } from '../lib/commands';
+import { createRoom, getRoomState, restoreSession, discardSession, RestorableSession } from '../lib/commands';
 
 // Re-export types for components
-export type { Song, RoomState, PlaylistCollection };
+export type { Song, RoomState, PlaylistCollection, RestorableSession };
 
 // Global ref to track if any input is focused (prevents re-renders while typing)
 let _isInputFocused = false;
@@ -27,6 +27,7 @@ let _flushCallback: ((state: RoomState) => void) | null = null;
 export function useRoomState() {
     const [roomState, setRoomState] = useState<RoomState | null>(null);
     const [loading, setLoading] = useState(true);
+    const [restorableSession, setRestorableSession] = useState<RestorableSession | null>(null);
 
     // Register the flush callback
     useEffect(() => {
@@ -34,10 +35,12 @@ export function useRoomState() {
         return () => { _flushCallback = null; };
     }, []);
 
-    const initializeRoom = async () => {
+    /** Open `roomId`, the same ID the window registers for signaling */
+    const initializeRoom = async (roomId: string) => {
         try {
             // Create room in Rust backend
-            await createRoom();
+            const room = await createRoom(roomId);
+            setRestorableSession(room.restorable_session);
             // Fetch initial state
             const state = await getRoomState();
             setRoomState(state);
@@ -49,8 +52,8 @@ export function useRoomState() {
     };
 
     useEffect(() => {
-        // Subscribe to room state updates from Rust
-        const unlisten = listen<RoomState>('room_state_updated', (event) => {
+        // Subscribe to this window's room state updates from Rust
+        const unlisten = getCurrentWebviewWindow().listen<RoomState>('room_state_updated', (event) => {
             if (_isInputFocused) {
                 // Defer update to avoid re-rendering while user is typing
                 _pendingState = event.payload;
@@ -64,5 +67,15 @@ export function useRoomState() {
         };
     }, []);
 
-    return { roomState, loading, initializeRoom };
+    /** Answer the offer to restore the previous session */
+    const resolveSession = async (restore: boolean) => {
+        setRestorableSession(null);
+        try {
+            await (restore ? restoreSession() : discardSession());
+        } catch (error) {
+            console.error('[useRoomState] Failed to resolve previous session:', error);
+        }
+    };
+
+    return { roomState, loading, initializeRoom, restorableSession, resolveSession };
 }
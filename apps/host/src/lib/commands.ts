@@ -1,20 +1,114 @@
 import { invoke } from '@tauri-apps/api/core';
-import { RoomState, ClientCommand, PlaylistCollection } from '@karaokenatin/shared';
+import { RoomState, ClientCommand, HostBroadcast, PlaylistCollection, QueueLimits, AutoDjConfig, SkipPolicy, PlayedSong, HistoryFormat, Role, HandoffPackage, PublicBroadcast } from '@karaokenatin/shared';
 
 /**
  * Tauri command wrappers for Rust backend
  */
 
-export async function createRoom(): Promise<{ roomId: string; joinToken: string }> {
-    return await invoke('create_room');
+/**
+ * The room this window hosts. One process can host several rooms, one per
+ * window, so every room command below names it; `createRoom` sets it.
+ */
+let hostedRoomId = '';
+
+/** A queue saved by a previous run that crashed or was closed mid-session */
+export interface RestorableSession {
+    savedAt: number;
+    queueLength: number;
+    /** Title of the song that was playing */
+    currentSong: string | null;
+}
+
+/** Open `roomId` in the backend, or get it back after a reload */
+export async function createRoom(roomId: string): Promise<{
+    roomId: string;
+    joinToken: string;
+    restorable_session: RestorableSession | null;
+}> {
+    hostedRoomId = roomId;
+    return await invoke('create_room', { roomId });
+}
+
+/** Close this window's room when leaving Host Mode */
+export async function closeRoom(): Promise<void> {
+    if (!hostedRoomId) return;
+    const roomId = hostedRoomId;
+    hostedRoomId = '';
+    return await invoke('close_room', { roomId });
+}
+
+/**
+ * Load a room taken over from another host into this window's room. Roles are
+ * given back as guests rejoin under the same name.
+ */
+export async function importHandoff(room: HandoffPackage): Promise<void> {
+    return await invoke('import_handoff', { roomId: hostedRoomId, package: room });
+}
+
+/** Open a second host window with a room of its own (desktop only) */
+export async function openRoomWindow(): Promise<void> {
+    return await invoke('open_room_window');
+}
+
+export async function restoreSession(): Promise<void> {
+    return await invoke('restore_session', { roomId: hostedRoomId });
+}
+
+export async function discardSession(): Promise<void> {
+    return await invoke('discard_session');
 }
 
 export async function getRoomState(): Promise<RoomState> {
-    return await invoke('get_room_state');
+    return await invoke('get_room_state', { roomId: hostedRoomId });
+}
+
+/** Whether a guest's DataConnection carried the device token signaling issued for its peer id */
+export async function verifyGuest(peerId: string, deviceToken: string): Promise<boolean> {
+    return await invoke('verify_guest', { roomId: hostedRoomId, peerId, deviceToken });
+}
+
+/** The state as guests see it, with each connected guest's own view */
+export async function getPublicState(): Promise<PublicBroadcast> {
+    return await invoke('get_public_state', { roomId: hostedRoomId });
+}
+
+/**
+ * `senderId` is the guest's PeerJS id; omit it for commands the host UI issues
+ * itself. Rejects with `{ code, message, details? }` (see `CommandError`).
+ * Query commands resolve to a reply meant for the sender only.
+ */
+export async function processCommand(command: ClientCommand, senderId?: string): Promise<HostBroadcast | null> {
+    return await invoke('process_command', { roomId: hostedRoomId, command, senderId });
+}
+
+/** The current song played to the end (as opposed to being skipped) */
+export async function finishSong(): Promise<void> {
+    return await invoke('finish_song', { roomId: hostedRoomId });
+}
+
+export async function getPlayHistory(): Promise<PlayedSong[]> {
+    return await invoke('get_play_history', { roomId: hostedRoomId });
 }
 
-export async function processCommand(command: ClientCommand): Promise<void> {
-    return await invoke('process_command', { command });
+export async function saveHistoryToFile(format: HistoryFormat): Promise<void> {
+    return await invoke('save_history_to_file', { roomId: hostedRoomId, format });
+}
+
+export async function setQueueLimits(limits: QueueLimits): Promise<void> {
+    return await invoke('set_queue_limits', { roomId: hostedRoomId, limits });
+}
+
+export async function setSkipPolicy(policy: SkipPolicy): Promise<void> {
+    return await invoke('set_skip_policy', { roomId: hostedRoomId, policy });
+}
+
+export async function setGuestRole(guestId: string, role: Role): Promise<void> {
+    return await invoke('set_guest_role', { roomId: hostedRoomId, guestId, role });
+}
+
+/** Pass `null` to turn the Auto-DJ off */
+export async function setAutoDj(config: AutoDjConfig | null): Promise<void> {
+    return await invoke('set_auto_dj', { roomId: hostedRoomId, config });
 }
 
 /**
@@ -28,6 +122,7 @@ export async function updatePlayerState(state: {
     duration?: number;
 }): Promise<void> {
     return await invoke('update_player_state', {
+        roomId: hostedRoomId,
         status: state.status,
         currentTime: state.currentTime,
         duration: state.duration,
@@ -99,3 +194,65 @@ export async function loadCollectionFromFile(): Promise<string> {
 export async function startHostServer(): Promise<number> {
     return await invoke('start_host_server');
 }
+
+/** An address guests might reach this host on */
+export interface NetworkAddress {
+    interface: string;
+    ip: string;
+    url: string;
+    /** A container, VM or VPN adapter; rarely what guests are on */
+    likelyVirtual: boolean;
+}
+
+/** Ports the server may listen on, both ends included */
+export interface PortRange {
+    start: number;
+    end: number;
+}
+
+export interface NetworkSettings {
+    /** Address the QR code points at; `null` picks the likeliest one */
+    preferredIp: string | null;
+    /** Listen on `preferredIp` only, from the next app start */
+    bindPreferredOnly: boolean;
+    /** Port to listen on when free; remembered from the first start */
+    preferredPort: number | null;
+    /** Ports to pick from when the preferred one is taken */
+    portRange: PortRange | null;
+    /** Serve guests over HTTPS with a self-signed certificate, from the next start */
+    https: boolean;
+}
+
+/** Every address of this machine guests might use, likeliest first */
+export async function listNetworkAddresses(): Promise<NetworkAddress[]> {
+    return await invoke('list_network_addresses');
+}
+
+export async function getNetworkSettings(): Promise<NetworkSettings> {
+    return await invoke('get_network_settings');
+}
+
+export async function setNetworkSettings(settings: NetworkSettings): Promise<void> {
+    return await invoke('set_network_settings', { settings });
+}
+
+/** SHA-256 of the HTTPS certificate, for guests to check when their browser warns */
+export async function getCertificateFingerprint(): Promise<string> {
+    return await invoke('get_certificate_fingerprint');
+}
+
+// ============================================================
+// LAN discovery
+// ============================================================
+
+/** A room advertised on the LAN; `url` has no invite, so guests use the PIN */
+export interface DiscoveredHost {
+    roomId: string;
+    address: string;
+    url: string;
+}
+
+/** Listen a few seconds for rooms on the LAN */
+export async function discoverHosts(): Promise<DiscoveredHost[]> {
+    return await invoke('discover_hosts');
+}
@@ -50,6 +50,24 @@ export function generateJoinToken(): string {
     return randomId(26);
 }
 
+/**
+ * Room PIN, for guests who cannot scan the QR code. Six digits is only ~20
+ * bits, so the signaling server locks PIN entry after a few wrong guesses;
+ * the QR invite tokens stay the strong credential.
+ */
+export function generatePin(): string {
+    const limit = 250; // largest multiple of 10 that fits in a byte
+    let out = '';
+    while (out.length < 6) {
+        const bytes = new Uint8Array(6 - out.length);
+        crypto.getRandomValues(bytes);
+        for (const b of bytes) {
+            if (b < limit) out += String(b % 10);
+        }
+    }
+    return out;
+}
+
 export async function hashToken(token: string): Promise<string> {
     const encoder = new TextEncoder();
     const data = encoder.encode(token);
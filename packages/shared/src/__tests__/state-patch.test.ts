@@ -46,10 +46,17 @@ function baseState(): RoomState {
                 addedAt: 0,
             },
         ],
+        queueMode: 'fifo',
+        queueLimits: { maxPendingPerSinger: null, addCooldownSecs: null, maxQueueLength: null },
+        autoDj: null,
+        skipPolicy: { voteThreshold: 0.5, restrictSkip: false },
+        skipVotes: { songId: null, voters: [], needed: 1 },
+        history: { canUndo: false, canRedo: false },
         playlists: [
             { id: 'c1', name: 'Party', visibility: 'public', songs: [], createdAt: 0, updatedAt: 0 },
         ],
         connectedClients: [],
+        roles: {},
         createdAt: 0,
         updatedAt: 0,
     };
@@ -2,15 +2,20 @@
  * P2P WebRTC DataChannel Protocol Definitions
  */
 
-import { RoomState, CollectionVisibility } from './room-state';
+import { RoomState, CollectionVisibility, QueueMode, PlayedSong, GuestView } from './room-state';
 
 /**
  * Commands sent from Client -> Host
+ *
+ * Each command is limited to certain roles (see `ClientCommand::allowed_roles`
+ * on the host). Anyone else gets ERROR { code: 'FORBIDDEN', details: { role, allowed } }.
  */
 export type ClientCommand =
     | { type: 'PLAY' }
     | { type: 'PAUSE' }
+    // Only the host and co-hosts may SKIP while SkipPolicy.restrictSkip is on
     | { type: 'SKIP' }
+    | { type: 'VOTE_SKIP' }
     | { type: 'SEEK'; time: number }
     | { type: 'SET_VOLUME'; volume: number }
     | { type: 'TOGGLE_MUTE' }
@@ -21,8 +26,25 @@ export type ClientCommand =
     | { type: 'MOVE_SONG_DOWN'; songId: string }
     | { type: 'MOVE_SONG_TO_TOP'; songId: string }
     | { type: 'MOVE_SONG_TO_BOTTOM'; songId: string }
+    | { type: 'SET_QUEUE_MODE'; mode: QueueMode }
+    // One vote per guest per song
+    | { type: 'UPVOTE'; songId: string }
+    | { type: 'UNVOTE'; songId: string }
+    // Host-only: guests get ERROR { code: 'FORBIDDEN' }
+    | { type: 'UNDO' }
+    | { type: 'REDO' }
+    // Duet / group songs. Guests act as themselves: only a song's lead may
+    // invite, and `singer` is read only from staff (or a lead taking
+    // someone off their song). An invite names its guest by `clientId`, as
+    // display names are not unique; staff may leave it out to invite a name
+    // that is not in the room.
+    | { type: 'INVITE_TO_DUET'; songId: string; singer: string; clientId?: string }
+    | { type: 'JOIN_DUET'; songId: string; singer: string }
+    | { type: 'LEAVE_DUET'; songId: string; singer: string }
     | { type: 'SET_DISPLAY_NAME'; name: string }
     | { type: 'PING' }
+    // Queries: answered to the sender only
+    | { type: 'GET_HISTORY' }
     // Collection management commands
     | { type: 'CREATE_COLLECTION'; name: string; visibility: CollectionVisibility }
     | { type: 'DELETE_COLLECTION'; collectionId: string }
@@ -51,11 +73,20 @@ export type ClientMessage = ClientCommand | ClientRequest;
  * Broadcasts sent from Host -> Clients
  */
 export type HostBroadcast =
-    | { type: 'STATE_UPDATE'; state: RoomState }
+    | { type: 'STATE_UPDATE'; state: RoomState; you?: GuestView }
     | { type: 'STATE_PATCH'; patch: Partial<RoomState> }
-    | { type: 'ERROR'; code: string; message: string }
+    | { type: 'ERROR'; code: string; message: string; details?: Record<string, unknown> }
     | { type: 'PONG'; serverTime: number }
-    | { type: 'SEARCH_RESULTS'; results: SearchResult[] };
+    | { type: 'SEARCH_RESULTS'; results: SearchResult[] }
+    // Reply to GET_HISTORY
+    | { type: 'HISTORY'; entries: PlayedSong[] };
+
+/**
+ * Machine-readable `ERROR.code` values for refused queue additions. `details`
+ * carries `{ limit }` for the quota/full codes and `{ retryAfterMs }` for the
+ * cooldown. Any other failure is reported as `COMMAND_FAILED`.
+ */
+export type QueueRejectionCode = 'SINGER_QUOTA_REACHED' | 'ADD_COOLDOWN' | 'QUEUE_FULL';
 
 /** A single YouTube search result, as returned by the host's `search_youtube`. */
 export interface SearchResult {
@@ -71,10 +102,11 @@ export interface SearchResult {
  * Type guard for client commands
  */
 export const CLIENT_COMMAND_TYPES = [
-    'PLAY', 'PAUSE', 'SKIP', 'SEEK', 'SET_VOLUME', 'TOGGLE_MUTE',
+    'PLAY', 'PAUSE', 'SKIP', 'VOTE_SKIP', 'SEEK', 'SET_VOLUME', 'TOGGLE_MUTE',
     'ADD_SONG', 'REMOVE_SONG', 'REORDER_QUEUE', 'MOVE_SONG_UP', 'MOVE_SONG_DOWN',
-    'MOVE_SONG_TO_TOP', 'MOVE_SONG_TO_BOTTOM',
-    'SET_DISPLAY_NAME', 'PING',
+    'MOVE_SONG_TO_TOP', 'MOVE_SONG_TO_BOTTOM', 'SET_QUEUE_MODE', 'UPVOTE', 'UNVOTE', 'UNDO', 'REDO',
+    'INVITE_TO_DUET', 'JOIN_DUET', 'LEAVE_DUET',
+    'SET_DISPLAY_NAME', 'PING', 'GET_HISTORY',
     'CREATE_COLLECTION', 'DELETE_COLLECTION', 'RENAME_COLLECTION', 'SET_COLLECTION_VISIBILITY',
     'PLAYLIST_ADD', 'PLAYLIST_REMOVE', 'PLAYLIST_TO_QUEUE',
     'IMPORT_COLLECTION'
@@ -83,7 +115,7 @@ export const CLIENT_COMMAND_TYPES = [
 export const CLIENT_REQUEST_TYPES = ['SEARCH'] as const satisfies readonly ClientRequest['type'][];
 
 export const HOST_BROADCAST_TYPES = [
-    'STATE_UPDATE', 'STATE_PATCH', 'ERROR', 'PONG', 'SEARCH_RESULTS'
+    'STATE_UPDATE', 'STATE_PATCH', 'ERROR', 'PONG', 'SEARCH_RESULTS', 'HISTORY'
 ] as const satisfies readonly HostBroadcast['type'][];
 
 /**
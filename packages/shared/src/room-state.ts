@@ -11,6 +11,79 @@ export interface Song {
     thumbnailUrl: string;
     addedBy: string;        // clientId
     addedAt: number;        // timestamp
+    singers?: SongSinger[]; // extra duet/group singers; absent for solo songs
+    voters?: string[];      // peer ids of guests who upvoted it; host only, never sent to guests
+    votes?: number;         // upvote count as guests see it
+    autoFilled?: boolean;   // queued by the Auto-DJ; absent otherwise
+    ownerId?: string;       // peer id of the guest who queued it; absent for host/Auto-DJ songs and for guests
+}
+
+export type InviteStatus = 'pending' | 'accepted';
+
+/** An extra singer on a duet or group song. Only accepted singers perform. */
+export interface SongSinger {
+    name: string;
+    invitedBy: string | null;   // null when they joined on their own
+    status: InviteStatus;
+}
+
+/**
+ * How upcoming songs are ordered: first come first served, round-robin
+ * between singers (keyed on `addedBy`), or most upvoted first.
+ */
+export type QueueMode = 'fifo' | 'rotation' | 'votes';
+
+/**
+ * Host-configurable limits on guest additions. `null` disables a limit; the
+ * host's own additions are never limited.
+ */
+export interface QueueLimits {
+    maxPendingPerSinger: number | null;
+    addCooldownSecs: number | null;
+    maxQueueLength: number | null;
+}
+
+/** Whether the host can UNDO/REDO a queue change right now. */
+export interface QueueHistory {
+    canUndo: boolean;
+    canRedo: boolean;
+}
+
+export type PlayOutcome = 'completed' | 'skipped';
+
+/** One song in the session's play history (not part of the broadcast state) */
+export interface PlayedSong {
+    youtubeId: string;
+    title: string;
+    artist: string;
+    singers: string[];      // everyone who performed it, lead singer first
+    startedAt: number;
+    endedAt: number;
+    outcome: PlayOutcome;
+}
+
+export type HistoryFormat = 'json' | 'csv';
+
+/** Host settings for skipping songs */
+export interface SkipPolicy {
+    voteThreshold: number;  // fraction of connected guests (0-1) whose VOTE_SKIP ends the song
+    restrictSkip: boolean;  // guests get FORBIDDEN for SKIP and must VOTE_SKIP
+}
+
+/** Votes to skip the song on stage; reset when the song changes */
+export interface SkipVotes {
+    songId: string | null;
+    voters: string[];       // peer ids of who voted; host only, empty for guests
+    votes?: number;         // vote count as guests see it
+    needed: number;
+}
+
+export type AutoDjOrder = 'sequential' | 'shuffle';
+
+/** Auto-DJ refills the queue from a collection when it runs dry */
+export interface AutoDjConfig {
+    collectionId: string;
+    order: AutoDjOrder;
 }
 
 export type PlayerStatus = 'idle' | 'playing' | 'paused' | 'loading' | 'error';
@@ -24,12 +97,19 @@ export interface PlayerState {
     isMuted: boolean;
 }
 
+/** Filled in by the host's signaling server as guests join and leave */
 export interface ConnectedClient {
-    id: string;
+    id: string;             // stable client id, which is also the guest's PeerJS id
     displayName: string;
     connectedAt: number;
+    overflow?: boolean;     // joined past capacity; watches until the host gives them a role
 }
 
+/** What a participant may do; guests without an entry in `roles` are singers */
+export type Role = 'host' | 'cohost' | 'singer' | 'spectator';
+
+export const DEFAULT_GUEST_ROLE: Role = 'singer';
+
 export type CollectionVisibility = 'public' | 'personal';
 
 export interface PlaylistCollection {
@@ -56,13 +136,20 @@ export interface RoomState {
     hostPeerId: string;
 
     // Connection state
-    connectedClients: ConnectedClient[];
+    connectedClients: ConnectedClient[];  // empty for guests
+    roles: Record<string, Role>;  // host-assigned guest roles, keyed by peer id; empty for guests
 
     // Playback state
     player: PlayerState;
 
     // Queue state
     queue: Song[];
+    queueMode: QueueMode;
+    queueLimits: QueueLimits;
+    autoDj: AutoDjConfig | null;
+    skipPolicy: SkipPolicy;
+    skipVotes: SkipVotes;
+    history: QueueHistory;
 
     // Playlist collections (replaces flat playlist)
     playlists: PlaylistCollection[];
@@ -72,6 +159,28 @@ export interface RoomState {
     updatedAt: number;
 }
 
+/**
+ * The parts of the state that differ per guest, sent to each guest only. The
+ * shared state carries no peer ids, so this is how a guest learns its own
+ * role and songs.
+ */
+export interface GuestView {
+    role: Role;
+    overflow: boolean;       // let in past capacity and not yet given a role
+    ownSongIds: string[];    // queued songs this guest added
+    votedSongIds: string[];  // queued songs this guest upvoted
+    votedSkip: boolean;      // whether this guest voted to skip the song on stage
+}
+
+/**
+ * What the host window forwards to guests (the `room_state_public` event):
+ * the shared state, and each guest's own view keyed by peer id
+ */
+export interface PublicBroadcast {
+    state: RoomState;
+    viewers: Record<string, GuestView>;
+}
+
 /**
  * Initial state factory
  */
@@ -81,6 +190,7 @@ export function createInitialRoomState(roomId: string, hostPeerId: string): Room
         roomId,
         hostPeerId,
         connectedClients: [],
+        roles: {},
         player: {
             status: 'idle',
             currentSong: null,
@@ -90,6 +200,12 @@ export function createInitialRoomState(roomId: string, hostPeerId: string): Room
             isMuted: false,
         },
         queue: [],
+        queueMode: 'fifo',
+        queueLimits: { maxPendingPerSinger: null, addCooldownSecs: null, maxQueueLength: null },
+        autoDj: null,
+        skipPolicy: { voteThreshold: 0.5, restrictSkip: false },
+        skipVotes: { songId: null, voters: [], needed: 1 },
+        history: { canUndo: false, canRedo: false },
         playlists: [],
         createdAt: now,
         updatedAt: now,
@@ -2,11 +2,64 @@
  * Socket.io Signaling Server Protocol
  */
 
+import { Role, SkipPolicy } from './room-state';
+
 /**
  * Events from Host -> Server
  */
 export interface HostToServerEvents {
-    CREATE_ROOM: (data: { roomId: string; joinTokenHash: string; hostPeerId?: string }) => void;
+    /**
+     * Also reclaims an existing room when `hostKeyHash` matches and the old
+     * host socket is gone, e.g. after a webview reload; while it still counts
+     * as connected the server answers ERROR `HOST_STILL_CONNECTED`.
+     * `hostGraceSecs` is how long guests wait for the host after its socket
+     * drops (default 60, at most 600). `capacity` is how many guests get a
+     * seat (default 10); later ones join as spectators.
+     */
+    CREATE_ROOM: (data: {
+        roomId: string;
+        joinTokenHash: string;
+        hostKeyHash: string;
+        hostPeerId?: string;
+        hostGraceSecs?: number;
+        capacity?: number;
+    }) => void;
+    /** Change the capacity; guests already in keep their seat or spectator place */
+    SET_CAPACITY: (data: { capacity: number }) => void;
+    /** Set the SHA-256 of the room PIN, or `null` to turn PIN entry off */
+    SET_PIN: (data: { pinHash: string | null }) => void;
+    /** Disconnect a guest by PeerJS id; `ban` also refuses their device until the room closes */
+    KICK_CLIENT: (data: { peerId: string; reason?: string; ban?: boolean }) => void;
+    /**
+     * Issue an invite. The host picks `inviteId` and keeps the token for the
+     * QR code; only its hash is sent. Without `expiresInSecs`/`maxUses` the
+     * invite lasts as long as the room.
+     */
+    CREATE_INVITE: (data: {
+        inviteId: string;
+        tokenHash: string;
+        label?: string;
+        expiresInSecs?: number;
+        maxUses?: number;
+    }) => void;
+    /** Stop an invite's token from admitting anyone; guests already in stay */
+    REVOKE_INVITE: (data: { inviteId: string }) => void;
+    /** With the lobby on, guests with a valid token wait for approval */
+    SET_LOBBY: (data: { enabled: boolean }) => void;
+    /** Let a lobby guest in; fails with APPROVE_FAILED if the room is full */
+    APPROVE_GUEST: (data: { requestId: string }) => void;
+    REJECT_GUEST: (data: { requestId: string; reason?: string }) => void;
+    /**
+     * Let another host take the room over with a one-time code, valid ten
+     * minutes. Only the hash is sent; `null` withdraws the offer.
+     */
+    OFFER_HANDOFF: (data: { codeHash: string | null }) => void;
+    /**
+     * Sent by the new host to the old host's server. On success the room
+     * arrives in HANDOFF_PACKAGE and its guests are sent on to `hostUrl`.
+     */
+    CLAIM_HANDOFF: (data: { code: string; hostUrl: string }) => void;
+    /** Close the room now, skipping the grace period */
     LEAVE_ROOM: () => void;
 }
 
@@ -14,7 +67,23 @@ export interface HostToServerEvents {
  * Events from Client -> Server
  */
 export interface ClientToServerEvents {
-    JOIN_ROOM: (data: { roomId: string; joinToken: string; displayName: string }) => void;
+    /**
+     * `joinToken` is an invite token from the QR code. `deviceId` is a stable
+     * per-browser id, used to enforce bans. `deviceToken` from an earlier
+     * JOIN_SUCCESS reclaims that identity within the grace window, without
+     * needing the invite again. A guest without the QR code leaves
+     * `joinToken` out and sends the room `pin`; wrong PINs lock the guest's
+     * address out for a while. `roomId` comes from the link's `?r=`; empty or
+     * 'default' means the PIN's room, else the main (oldest) room.
+     */
+    JOIN_ROOM: (data: {
+        roomId: string;
+        joinToken?: string;
+        pin?: string;
+        displayName: string;
+        deviceId?: string;
+        deviceToken?: string;
+    }) => void;
     LEAVE_ROOM: () => void;
 }
 
@@ -22,9 +91,18 @@ export interface ClientToServerEvents {
  * Events from Server -> Host
  */
 export interface ServerToHostEvents {
-    ROOM_CREATED: (data: { roomId: string }) => void;
+    ROOM_CREATED: (data: { roomId: string; reclaimed: boolean }) => void;
     CLIENT_JOINED: (data: { clientId: string; displayName: string; peerId: string }) => void;
     CLIENT_LEFT: (data: { clientId: string }) => void;
+    /** The room's invites, after any change including a guest using one */
+    INVITES_UPDATED: (data: { invites: Invite[] }) => void;
+    CAPACITY_UPDATED: (data: { capacity: number }) => void;
+    /** The lobby setting and who is waiting, oldest first */
+    LOBBY_UPDATED: (data: { enabled: boolean; pending: LobbyEntry[] }) => void;
+    /** The claimed room, for the new host to import */
+    HANDOFF_PACKAGE: (data: { room: HandoffPackage }) => void;
+    /** Another host took the room over; this room is closed */
+    HOST_MOVED: (data: { url: string }) => void;
     ERROR: (data: { code: string; message: string }) => void;
 }
 
@@ -32,12 +110,65 @@ export interface ServerToHostEvents {
  * Events from Server -> Client
  */
 export interface ServerToClientEvents {
-    JOIN_SUCCESS: (data: { roomId: string; hostPeerId: string }) => void;
+    /** The guest opens its Peer with `clientId` as its PeerJS id */
+    JOIN_SUCCESS: (data: {
+        roomId: string;
+        hostPeerId: string;
+        deviceToken: string;
+        clientId: string;
+        displayName: string;
+        restored: boolean;
+        hostAway: boolean;       // wait for HOST_RECONNECTED before dialing the host
+        spectator: boolean;      // over capacity: may watch but not send commands
+    }) => void;
+    /** Parked in the lobby; JOIN_SUCCESS or JOIN_REJECTED follows the host's decision */
+    JOIN_PENDING: () => void;
     JOIN_REJECTED: (data: { reason: string }) => void;
+    /** The host's socket dropped; the room waits `graceSecs` for it to come back */
+    HOST_AWAY: (data: { graceSecs: number }) => void;
+    /** The host is back, possibly with a new PeerJS id */
+    HOST_RECONNECTED: (data: { hostPeerId: string }) => void;
+    /** The host left or did not come back in time; the room is gone */
     HOST_DISCONNECTED: () => void;
+    /** The host removed this guest; the socket is closed right after */
+    KICKED: (data: { reason: string; banned: boolean }) => void;
+    /** The room moved to another host; rejoin at `url`, which carries an invite */
+    HOST_MOVED: (data: { url: string }) => void;
     ERROR: (data: { code: string; message: string }) => void;
 }
 
+/**
+ * An invite link as the signaling server reports it. Times are Unix millis.
+ */
+export interface Invite {
+    inviteId: string;
+    label?: string | null;
+    createdAt: number;
+    expiresAt?: number | null;
+    maxUses?: number | null;
+    uses: number;
+}
+
+/**
+ * A guest waiting in the lobby. Times are Unix millis.
+ */
+export interface LobbyEntry {
+    requestId: string;
+    displayName: string;
+    requestedAt: number;
+}
+
+/**
+ * A room on its way to a new host: the saved session plus who held which
+ * role. Only spectators are matched up again by display name when guests
+ * rejoin; co-hosts have to be made co-host again by the new host.
+ */
+export interface HandoffPackage {
+    session: unknown;
+    skipPolicy: SkipPolicy;
+    roster: { displayName: string; role: Role }[];
+}
+
 /**
  * Room metadata stored on signaling server
  */